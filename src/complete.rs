@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+
+use crate::infer::{start_patterns, timestamp_regexes};
+use crate::scanner::DEFAULT_INCLUDE_GLOBS;
+
+/// 单次补全最多返回的候选数（MCP 规定的上限）。
+pub const MAX_COMPLETIONS: usize = 100;
+
+/// 除默认包含模式外常用的 glob。
+const COMMON_GLOBS: &[&str] = &[
+    "**/*.log.*",
+    "**/*.txt",
+    "**/*.out",
+    "**/*.json",
+    "**/*.log.[0-9]*",
+];
+
+/// 为工具参数生成补全候选，只保留以当前输入开头的项。
+///
+/// - `root_path`：输入为空时给出配置文件所在目录，否则列出已输入部分的子目录；
+/// - `include_globs`/`exclude_globs`：常用 glob；
+/// - `timestamp_regex`/`log_start_pattern`：内置识别的时间戳与行首形态。
+pub fn complete_argument(name: &str, value: &str, configured_paths: &[String]) -> Vec<String> {
+    let candidates: Vec<String> = match name {
+        "root_path" => {
+            if value.is_empty() {
+                configured_dirs(configured_paths)
+            } else {
+                subdirectories(value)
+            }
+        }
+        "include_globs" | "exclude_globs" => DEFAULT_INCLUDE_GLOBS
+            .iter()
+            .chain(COMMON_GLOBS)
+            .map(|g| g.to_string())
+            .collect(),
+        "timestamp_regex" => timestamp_regexes().into_iter().map(str::to_string).collect(),
+        "log_start_pattern" => start_patterns(),
+        _ => Vec::new(),
+    };
+    candidates
+        .into_iter()
+        .filter(|c| c.starts_with(value))
+        .take(MAX_COMPLETIONS)
+        .collect()
+}
+
+/// 配置的日志文件所在的目录（去重、保持顺序）。
+fn configured_dirs(configured_paths: &[String]) -> Vec<String> {
+    let mut dirs: Vec<String> = Vec::new();
+    for dir in configured_paths.iter().filter_map(|p| Path::new(p).parent()) {
+        let dir = with_trailing_slash(dir);
+        if !dir.is_empty() && !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// 已输入路径的同级或下级目录：`/var/lo` 列出 `/var` 下以 `lo` 开头的目录，
+/// `/var/log/` 列出其全部子目录。隐藏目录只在输入以 `.` 开头时给出。
+fn subdirectories(value: &str) -> Vec<String> {
+    let (dir, prefix) = match value.rfind('/') {
+        Some(idx) => (&value[..=idx], &value[idx + 1..]),
+        None => ("./", value),
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut out: Vec<String> = entries
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|n| n.starts_with(prefix) && (prefix.starts_with('.') || !n.starts_with('.')))
+        .map(|n| {
+            let base = if value.contains('/') { dir } else { "" };
+            with_trailing_slash(&PathBuf::from(format!("{base}{n}")))
+        })
+        .collect();
+    out.sort();
+    out
+}
+
+fn with_trailing_slash(dir: &Path) -> String {
+    let s = dir.to_string_lossy().to_string();
+    if s.is_empty() || s.ends_with('/') {
+        s
+    } else {
+        format!("{s}/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn completes_directories_globs_and_timestamp_shapes() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("app")).unwrap();
+        std::fs::create_dir(dir.path().join("archive")).unwrap();
+        std::fs::create_dir(dir.path().join(".cache")).unwrap();
+        std::fs::write(dir.path().join("app.log"), "").unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+
+        let dirs = complete_argument("root_path", &format!("{root}/a"), &[]);
+        assert_eq!(dirs, vec![format!("{root}/app/"), format!("{root}/archive/")]);
+        assert_eq!(complete_argument("root_path", &format!("{root}/"), &[]).len(), 2);
+
+        let configured = vec![format!("{root}/app.log"), format!("{root}/other.log")];
+        assert_eq!(complete_argument("root_path", "", &configured), vec![format!("{root}/")]);
+
+        let globs = complete_argument("include_globs", "**/*.log", &[]);
+        assert!(globs.contains(&"**/*.log.gz".to_string()));
+        assert!(!globs.contains(&"**/*.txt".to_string()));
+
+        let shapes = complete_argument("timestamp_regex", "", &[]);
+        assert!(shapes.iter().any(|s| s.starts_with(r"\d{4}-\d{2}-\d{2}")));
+        assert!(complete_argument("unknown", "", &[]).is_empty());
+    }
+}
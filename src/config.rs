@@ -1,8 +1,12 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::error::{LogSearchError, Result};
+use crate::model::FileScanConfig;
+use crate::reader::EncodingOverrides;
+use crate::scanner::{DEFAULT_EXCLUDE_GLOBS, DEFAULT_INCLUDE_GLOBS};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -16,6 +20,58 @@ pub struct Config {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct LogSourceConfig {
     pub log_file_paths: Option<Vec<String>>,
+    /// 启动时检查 `log_file_paths` 中的每个路径是否存在且可读，问题记录为警告。
+    #[serde(default)]
+    pub validate_sources_on_start: bool,
+    /// 请求未给出 `exclude_globs` 时使用的排除模式；省略时为内置集合
+    /// （`**/node_modules/**`、`**/.git/**`），设为空列表可关闭。
+    #[serde(default)]
+    pub default_exclude_globs: Option<Vec<String>>,
+    /// 请求未给出 `root_path` 且未配置 `log_file_paths` 时扫描的目录（支持 ~ 与 $VAR）。
+    #[serde(default)]
+    pub default_root: Option<PathBuf>,
+    /// 遍历 `root_path` 时是否进入其下的符号链接目录（可能指向根目录之外）。默认不进入；
+    /// 根目录本身是符号链接时总会解析，其下的符号链接文件总会跳过。
+    #[serde(default)]
+    pub allow_symlinked_dirs: bool,
+}
+
+impl LogSourceConfig {
+    fn has_global_paths(&self) -> bool {
+        self.log_file_paths.as_ref().is_some_and(|v| !v.is_empty())
+    }
+
+    /// 请求未给出 `root_path` 且没有全局 `log_file_paths` 时使用的扫描根目录。
+    pub fn fallback_root(&self, scan: &FileScanConfig) -> Option<PathBuf> {
+        if !scan.root_path.as_os_str().is_empty() || self.has_global_paths() {
+            return None;
+        }
+        self.default_root.clone()
+    }
+
+    /// 补上默认扫描根目录与默认排除模式。
+    pub fn with_defaults(&self, scan: &FileScanConfig) -> FileScanConfig {
+        let scan = self.with_default_excludes(scan);
+        match self.fallback_root(&scan) {
+            Some(root_path) => FileScanConfig { root_path, ..scan },
+            None => scan,
+        }
+    }
+
+    /// 请求未指定排除模式时补上默认排除模式。
+    pub fn with_default_excludes(&self, scan: &FileScanConfig) -> FileScanConfig {
+        if !scan.exclude_globs.is_empty() {
+            return scan.clone();
+        }
+        let exclude_globs = match &self.default_exclude_globs {
+            Some(globs) => globs.clone(),
+            None => DEFAULT_EXCLUDE_GLOBS.iter().map(|s| s.to_string()).collect(),
+        };
+        FileScanConfig {
+            exclude_globs,
+            ..scan.clone()
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +79,25 @@ pub struct ServerConfig {
     pub mode: ServerMode,
     pub http_addr: Option<String>,
     pub http_port: Option<u16>,
+    /// `/watch` 允许的最大并发订阅数，默认 16。
+    #[serde(default)]
+    pub max_watchers: Option<usize>,
+    /// `/watch` 轮询文件追加内容的间隔（毫秒），默认 1000。
+    #[serde(default)]
+    pub watch_poll_ms: Option<u64>,
+    /// 设置后 `/watch` 要求请求头 `Authorization: Bearer <watch_token>`，否则返回 401。
+    #[serde(default)]
+    pub watch_token: Option<String>,
+    /// `/sse` 允许的最大并发会话数，默认 64。
+    #[serde(default)]
+    pub max_sse_sessions: Option<usize>,
+    /// `/sse` 发送 keep-alive 注释的间隔（秒），默认 15；反向代理空闲超时较短时可调小。
+    #[serde(default)]
+    pub sse_keepalive_secs: Option<u64>,
+    /// HTTP 连接在该秒数内没有任何读写时被关闭（进行中的请求先完成），省略表示不限制。
+    /// 应大于 `sse_keepalive_secs`，否则空闲的 `/sse` 连接也会被关闭。
+    #[serde(default)]
+    pub http_idle_timeout_secs: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,6 +113,32 @@ pub struct LogParserConfig {
     #[serde(alias = "line_start_regex")]
     pub default_log_start_pattern: Option<String>,
     pub default_timestamp_regex: Option<String>,
+    /// 日志级别从低到高的顺序，同级别的别名用 `|` 分隔，如 "WARN|WARNING"。
+    /// 未配置时使用 TRACE < DEBUG < INFO < WARN < ERROR < FATAL。
+    #[serde(default)]
+    pub level_order: Option<Vec<String>>,
+    /// 提取级别的正则，取第一个捕获组（没有捕获组时取整个匹配）；默认按 level_order 中的名称全词匹配。
+    #[serde(default)]
+    pub level_regex: Option<String>,
+    /// 命名的时间戳正则；请求的 `timestamp_regex` 与 `default_timestamp_regex` 可写作 `@名称` 引用。
+    #[serde(default)]
+    pub timestamp_presets: BTreeMap<String, String>,
+}
+
+impl LogParserConfig {
+    /// 把 `@名称` 形式的时间戳正则替换为 `timestamp_presets` 中的定义，其他值原样返回。
+    pub fn resolve_timestamp_regex<'a>(&'a self, pattern: &'a str) -> Result<&'a str> {
+        let Some(name) = pattern.strip_prefix('@') else {
+            return Ok(pattern);
+        };
+        self.timestamp_presets.get(name).map(String::as_str).ok_or_else(|| {
+            let known: Vec<String> = self.timestamp_presets.keys().map(|k| format!("@{k}")).collect();
+            LogSearchError::InvalidRequest(format!(
+                "unknown timestamp preset '@{name}' (configured: {})",
+                if known.is_empty() { "none".to_string() } else { known.join(", ") }
+            ))
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,9 +147,87 @@ pub struct SearchConfig {
     pub max_page_size: usize,
     pub default_timeout_ms: u64,
     pub max_concurrent_files: usize,
+    /// 读取缓冲区大小（字节），`read_buffer_size`/`decompress_buffer_size` 未设置时使用。
     pub buffer_size: usize,
+    /// 读取磁盘文件（普通文件及 gzip 的压缩数据）的缓冲区大小，默认同 `buffer_size`。启动时生效。
+    #[serde(default)]
+    pub read_buffer_size: Option<usize>,
+    /// gzip 解压输出的缓冲区大小，默认同 `buffer_size`；解压为主的负载可单独调大。启动时生效。
+    #[serde(default)]
+    pub decompress_buffer_size: Option<usize>,
+    /// 单次搜索允许扫描的最大文件数；超过时在读取内容前直接报错。
+    #[serde(default)]
+    pub max_files: Option<usize>,
+    /// 记录分隔符字节（如 0 表示 `\0`），默认换行符。
+    #[serde(default)]
+    pub line_delimiter: Option<u8>,
+    /// 响应中派生时间戳（直方图、时间范围等）使用的 IANA 时区，如 "Asia/Shanghai"；默认 UTC。
+    #[serde(default)]
+    pub output_tz: Option<String>,
+    /// 单个文件读取遇到瞬时 IO 错误（EIO/EAGAIN 等）时的重试次数，默认 2。
+    #[serde(default)]
+    pub read_retries: Option<u32>,
+    /// 首次重试前的等待时间（毫秒），之后每次翻倍，默认 100。
+    #[serde(default)]
+    pub read_retry_delay_ms: Option<u64>,
+    /// 临时文件目录（结果落盘、解包等），默认系统临时目录。
+    #[serde(default)]
+    pub temp_dir: Option<PathBuf>,
+    /// 请求中正则模式的最大长度（字节），默认 4096。
+    #[serde(default)]
+    pub max_regex_len: Option<usize>,
+    /// 全局噪声正则（健康检查、心跳等）：作为隐式的 `none` 条件作用于每次搜索，
+    /// 请求可用 `ignore_global_excludes` 关闭。
+    #[serde(default)]
+    pub global_exclude_patterns: Vec<String>,
+    /// 命中条目数占扫描行数的比例超过该值时，响应置 `too_broad`，默认 0.5。
+    #[serde(default)]
+    pub too_broad_ratio: Option<f64>,
+    /// 按路径指定编码，先于自动检测生效；按顺序取第一个匹配的规则。
+    #[serde(default)]
+    pub encoding_overrides: Vec<EncodingOverride>,
+    /// 单个 gzip 文件解压后允许的最大字节数；超出的文件停止读取并记为失败文件。默认不限制。
+    #[serde(default)]
+    pub max_decompressed_bytes: Option<u64>,
+    /// 单次搜索所有文件累计读取的最大字节数（gzip 按解压后计）；达到后停止读取，
+    /// 返回已收集的命中并置 `read_budget_exceeded`。默认不限制。
+    #[serde(default)]
+    pub max_total_read_bytes: Option<u64>,
+    /// 请求允许的最大页码，默认 1000；更深的结果应通过 `next_cursor` 翻页。
+    #[serde(default)]
+    pub max_page: Option<usize>,
+    /// 单次搜索响应中 `failed_files` 最多列出的条目数，默认 100；超出的只计入 `failed_files_truncated_count`。
+    #[serde(default)]
+    pub max_failed_files_reported: Option<usize>,
+    /// 所有搜索合计同时打开的日志文件数上限，默认 64；与 `max_concurrent_files` 独立，
+    /// 用于 `ulimit -n` 较低的主机。启动时读取，热加载不生效。
+    #[serde(default)]
+    pub max_open_files: Option<usize>,
+    /// 单文件超时（请求的 `hard_timeout_ms` 或 `default_timeout_ms`）的下限，过小的值被提高到该值。默认不限制。
+    #[serde(default)]
+    pub min_timeout_ms: Option<u64>,
+    /// 单文件超时的上限：更大的值被降低到该值，为 0（不限时）时也按该值计时。默认不限制。
+    #[serde(default)]
+    pub max_timeout_ms: Option<u64>,
+    /// 遇到按文件编码无法解码的字节序列时，该文件记为失败文件，而不是替换为 U+FFFD 继续读取。
+    /// 对普通文件与 gzip 文件一致生效，默认关闭（容错解码）。
+    #[serde(default)]
+    pub strict_utf8: bool,
 }
 
+/// 单条编码覆盖规则：路径匹配 `glob` 的文件按 `encoding`（WHATWG 标签，如 "gbk"、"utf-8"）解码。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncodingOverride {
+    pub glob: String,
+    pub encoding: String,
+}
+
+const DEFAULT_MAX_REGEX_LEN: usize = 4096;
+const DEFAULT_MAX_PAGE: usize = 1000;
+const DEFAULT_MAX_FAILED_FILES_REPORTED: usize = 100;
+const DEFAULT_MAX_OPEN_FILES: usize = 64;
+const DEFAULT_TOO_BROAD_RATIO: f64 = 0.5;
+
 impl Default for SearchConfig {
     fn default() -> Self {
         Self {
@@ -57,10 +236,77 @@ impl Default for SearchConfig {
             default_timeout_ms: 1_000,
             max_concurrent_files: 4,
             buffer_size: 64 * 1024,
+            read_buffer_size: None,
+            decompress_buffer_size: None,
+            max_files: None,
+            line_delimiter: None,
+            output_tz: None,
+            read_retries: None,
+            read_retry_delay_ms: None,
+            temp_dir: None,
+            max_regex_len: None,
+            global_exclude_patterns: Vec::new(),
+            too_broad_ratio: None,
+            encoding_overrides: Vec::new(),
+            max_decompressed_bytes: None,
+            max_total_read_bytes: None,
+            max_page: None,
+            max_failed_files_reported: None,
+            max_open_files: None,
+            min_timeout_ms: None,
+            max_timeout_ms: None,
+            strict_utf8: false,
         }
     }
 }
 
+impl SearchConfig {
+    pub fn max_regex_len(&self) -> usize {
+        self.max_regex_len.unwrap_or(DEFAULT_MAX_REGEX_LEN)
+    }
+
+    pub fn max_page(&self) -> usize {
+        self.max_page.unwrap_or(DEFAULT_MAX_PAGE)
+    }
+
+    pub fn max_failed_files_reported(&self) -> usize {
+        self.max_failed_files_reported
+            .unwrap_or(DEFAULT_MAX_FAILED_FILES_REPORTED)
+    }
+
+    pub fn read_buffer_size(&self) -> usize {
+        self.read_buffer_size.unwrap_or(self.buffer_size)
+    }
+
+    pub fn decompress_buffer_size(&self) -> usize {
+        self.decompress_buffer_size.unwrap_or(self.buffer_size)
+    }
+
+    pub fn max_open_files(&self) -> usize {
+        self.max_open_files.unwrap_or(DEFAULT_MAX_OPEN_FILES)
+    }
+
+    /// 实际生效的单文件超时（毫秒）：请求值优先于 `default_timeout_ms`，再按
+    /// `min_timeout_ms`/`max_timeout_ms` 收紧；`None` 表示不限时。
+    pub fn effective_timeout_ms(&self, requested: Option<u64>) -> Option<u64> {
+        let timeout = requested.unwrap_or(self.default_timeout_ms);
+        if timeout == 0 {
+            return self.max_timeout_ms;
+        }
+        let timeout = timeout.max(self.min_timeout_ms.unwrap_or(0));
+        Some(self.max_timeout_ms.map_or(timeout, |max| timeout.min(max)))
+    }
+
+    pub fn too_broad_ratio(&self) -> f64 {
+        self.too_broad_ratio.unwrap_or(DEFAULT_TOO_BROAD_RATIO)
+    }
+
+    /// 所有临时文件的创建位置。
+    pub fn temp_dir(&self) -> PathBuf {
+        self.temp_dir.clone().unwrap_or_else(env::temp_dir)
+    }
+}
+
 impl Config {
     pub fn load_from_path(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path)
@@ -90,6 +336,32 @@ impl Config {
         if let Ok(port) = env::var("LOG_SEARCH_MCP__SERVER__HTTP_PORT") {
             self.server.http_port = Some(parse_num(&port, "http_port")?);
         }
+        if let Ok(root) = env::var("LOG_SEARCH_MCP__LOG_SOURCES__DEFAULT_ROOT") {
+            self.log_sources.default_root = Some(PathBuf::from(root));
+        }
+        if let Ok(v) = env::var("LOG_SEARCH_MCP__LOG_SOURCES__ALLOW_SYMLINKED_DIRS") {
+            self.log_sources.allow_symlinked_dirs = v.parse().map_err(|_| {
+                LogSearchError::ConfigError(format!("invalid boolean for allow_symlinked_dirs: {v}"))
+            })?;
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SERVER__MAX_WATCHERS") {
+            self.server.max_watchers = Some(parse_num(&n, "max_watchers")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SERVER__WATCH_POLL_MS") {
+            self.server.watch_poll_ms = Some(parse_num(&n, "watch_poll_ms")?);
+        }
+        if let Ok(token) = env::var("LOG_SEARCH_MCP__SERVER__WATCH_TOKEN") {
+            self.server.watch_token = Some(token);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SERVER__MAX_SSE_SESSIONS") {
+            self.server.max_sse_sessions = Some(parse_num(&n, "max_sse_sessions")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SERVER__SSE_KEEPALIVE_SECS") {
+            self.server.sse_keepalive_secs = Some(parse_num(&n, "sse_keepalive_secs")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SERVER__HTTP_IDLE_TIMEOUT_SECS") {
+            self.server.http_idle_timeout_secs = Some(parse_num(&n, "http_idle_timeout_secs")?);
+        }
         if let Ok(pat) = env::var("LOG_SEARCH_MCP__LOG_PARSER__DEFAULT_LOG_START_PATTERN") {
             self.log_parser.default_log_start_pattern = Some(pat);
         }
@@ -111,10 +383,75 @@ impl Config {
         if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__BUFFER_SIZE") {
             self.search.buffer_size = parse_num(&n, "buffer_size")?;
         }
-        Ok(self.validate()?)
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__MAX_FILES") {
+            self.search.max_files = Some(parse_num(&n, "max_files")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__LINE_DELIMITER") {
+            self.search.line_delimiter = Some(parse_num(&n, "line_delimiter")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__READ_RETRIES") {
+            self.search.read_retries = Some(parse_num(&n, "read_retries")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__READ_RETRY_DELAY_MS") {
+            self.search.read_retry_delay_ms = Some(parse_num(&n, "read_retry_delay_ms")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__MAX_REGEX_LEN") {
+            self.search.max_regex_len = Some(parse_num(&n, "max_regex_len")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__MAX_PAGE") {
+            self.search.max_page = Some(parse_num(&n, "max_page")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__MAX_FAILED_FILES_REPORTED") {
+            self.search.max_failed_files_reported = Some(parse_num(&n, "max_failed_files_reported")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__READ_BUFFER_SIZE") {
+            self.search.read_buffer_size = Some(parse_num(&n, "read_buffer_size")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__DECOMPRESS_BUFFER_SIZE") {
+            self.search.decompress_buffer_size = Some(parse_num(&n, "decompress_buffer_size")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__MIN_TIMEOUT_MS") {
+            self.search.min_timeout_ms = Some(parse_num(&n, "min_timeout_ms")?);
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__MAX_TIMEOUT_MS") {
+            self.search.max_timeout_ms = Some(parse_num(&n, "max_timeout_ms")?);
+        }
+        if let Ok(v) = env::var("LOG_SEARCH_MCP__SEARCH__STRICT_UTF8") {
+            self.search.strict_utf8 = v
+                .parse()
+                .map_err(|_| LogSearchError::ConfigError(format!("invalid boolean for strict_utf8: {v}")))?;
+        }
+        if let Ok(n) = env::var("LOG_SEARCH_MCP__SEARCH__MAX_OPEN_FILES") {
+            self.search.max_open_files = Some(parse_num(&n, "max_open_files")?);
+        }
+        if let Ok(dir) = env::var("LOG_SEARCH_MCP__SEARCH__TEMP_DIR") {
+            self.search.temp_dir = Some(PathBuf::from(dir));
+        }
+        if let Ok(tz) = env::var("LOG_SEARCH_MCP__SEARCH__OUTPUT_TZ") {
+            self.search.output_tz = Some(tz);
+        }
+        self.expand_paths().validate()
+    }
+
+    /// 展开配置中路径字段里的 `~` 与环境变量。
+    fn expand_paths(mut self) -> Self {
+        if let Some(paths) = &mut self.log_sources.log_file_paths {
+            for p in paths.iter_mut() {
+                *p = expand_path(Path::new(p)).to_string_lossy().into_owned();
+            }
+        }
+        if let Some(dir) = &self.search.temp_dir {
+            self.search.temp_dir = Some(expand_path(dir));
+        }
+        self
     }
 
     pub fn validate(self) -> Result<Self> {
+        if let Some(pattern) = &self.log_parser.default_timestamp_regex {
+            self.log_parser
+                .resolve_timestamp_regex(pattern)
+                .map_err(|e| LogSearchError::ConfigError(format!("log_parser.default_timestamp_regex: {e}")))?;
+        }
         if let Some(port) = self.server.http_port {
             if port == 0 {
                 return Err(LogSearchError::ConfigError(
@@ -122,6 +459,21 @@ impl Config {
                 ));
             }
         }
+        if self.server.watch_poll_ms == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "server.watch_poll_ms must be > 0".into(),
+            ));
+        }
+        if self.server.sse_keepalive_secs == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "server.sse_keepalive_secs must be > 0".into(),
+            ));
+        }
+        if self.server.http_idle_timeout_secs == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "server.http_idle_timeout_secs must be > 0".into(),
+            ));
+        }
         if self.search.default_page_size == 0 {
             return Err(LogSearchError::ConfigError(
                 "search.default_page_size must be > 0".into(),
@@ -142,10 +494,245 @@ impl Config {
                 "search.buffer_size must be > 0".into(),
             ));
         }
+        if let Some(tz) = &self.search.output_tz {
+            if tz.parse::<chrono_tz::Tz>().is_err() {
+                return Err(LogSearchError::ConfigError(format!(
+                    "search.output_tz is not a valid IANA time zone: {tz}"
+                )));
+            }
+        }
+        if self.search.max_page == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "search.max_page must be > 0".into(),
+            ));
+        }
+        if self.search.max_failed_files_reported == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "search.max_failed_files_reported must be > 0".into(),
+            ));
+        }
+        if self.search.read_buffer_size == Some(0) || self.search.decompress_buffer_size == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "search.read_buffer_size and search.decompress_buffer_size must be > 0".into(),
+            ));
+        }
+        if self.search.min_timeout_ms == Some(0) || self.search.max_timeout_ms == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "search.min_timeout_ms and search.max_timeout_ms must be > 0".into(),
+            ));
+        }
+        if let (Some(min), Some(max)) = (self.search.min_timeout_ms, self.search.max_timeout_ms) {
+            if min > max {
+                return Err(LogSearchError::ConfigError(format!(
+                    "search.min_timeout_ms ({min}) must not exceed search.max_timeout_ms ({max})"
+                )));
+            }
+        }
+        if self.search.max_open_files == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "search.max_open_files must be > 0".into(),
+            ));
+        }
+        if self.search.max_regex_len == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "search.max_regex_len must be > 0".into(),
+            ));
+        }
+        if self.search.max_decompressed_bytes == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "search.max_decompressed_bytes must be > 0".into(),
+            ));
+        }
+        if self.search.max_total_read_bytes == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "search.max_total_read_bytes must be > 0".into(),
+            ));
+        }
+        if self.search.max_files == Some(0) {
+            return Err(LogSearchError::ConfigError(
+                "search.max_files must be > 0".into(),
+            ));
+        }
+        if let Some(ratio) = self.search.too_broad_ratio {
+            if !(ratio > 0.0 && ratio <= 1.0) {
+                return Err(LogSearchError::ConfigError(
+                    "search.too_broad_ratio must be in (0, 1]".into(),
+                ));
+            }
+        }
+        for pattern in &self.search.global_exclude_patterns {
+            if let Err(e) = regex::Regex::new(pattern) {
+                return Err(LogSearchError::ConfigError(format!(
+                    "search.global_exclude_patterns contains an invalid regex {pattern:?}: {e}"
+                )));
+            }
+        }
+        EncodingOverrides::new(&self.search.encoding_overrides)?;
         Ok(self)
     }
 }
 
+/// `server_info` 返回的有效配置视图：只包含影响可搜索范围和默认行为的字段。
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerInfo {
+    pub mode: ServerMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_addr: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_port: Option<u16>,
+    /// 未给出 `root_path` 时搜索的文件。
+    pub log_file_paths: Vec<String>,
+    /// 未给出 `root_path` 且没有 `log_file_paths` 时扫描的目录。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_root: Option<PathBuf>,
+    /// 未给出 `include_globs` 时的包含模式。
+    pub default_include_globs: Vec<String>,
+    /// 未给出 `exclude_globs` 时的排除模式。
+    pub default_exclude_globs: Vec<String>,
+    pub allow_symlinked_dirs: bool,
+    pub default_page_size: usize,
+    pub max_page_size: usize,
+    pub max_page: usize,
+    pub max_failed_files_reported: usize,
+    pub default_timeout_ms: u64,
+    pub min_timeout_ms: Option<u64>,
+    pub max_timeout_ms: Option<u64>,
+    pub max_concurrent_files: usize,
+    pub max_open_files: usize,
+    pub max_files: Option<usize>,
+    pub max_regex_len: usize,
+    pub max_decompressed_bytes: Option<u64>,
+    pub strict_utf8: bool,
+    pub output_tz: Option<String>,
+    pub global_exclude_patterns: Vec<String>,
+    pub default_log_start_pattern: Option<String>,
+    pub default_timestamp_regex: Option<String>,
+    /// 可在 `timestamp_regex` 中以 `@名称` 引用的时间戳正则。
+    pub timestamp_presets: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// 供客户端查看的有效配置（默认值已展开）。
+    pub fn server_info(&self) -> ServerInfo {
+        let scan = self.log_sources.with_default_excludes(&FileScanConfig {
+            root_path: PathBuf::new(),
+            include_globs: Vec::new(),
+            exclude_globs: Vec::new(),
+            filename_query: None,
+            content_type_filter: Default::default(),
+            line_range: None,
+        });
+        ServerInfo {
+            mode: self.server.mode.clone(),
+            http_addr: self.server.http_addr.clone(),
+            http_port: self.server.http_port,
+            log_file_paths: self.log_sources.log_file_paths.clone().unwrap_or_default(),
+            default_root: self.log_sources.default_root.clone(),
+            default_include_globs: DEFAULT_INCLUDE_GLOBS.iter().map(|s| s.to_string()).collect(),
+            default_exclude_globs: scan.exclude_globs,
+            allow_symlinked_dirs: self.log_sources.allow_symlinked_dirs,
+            default_page_size: self.search.default_page_size,
+            max_page_size: self.search.max_page_size,
+            max_page: self.search.max_page(),
+            max_failed_files_reported: self.search.max_failed_files_reported(),
+            default_timeout_ms: self.search.default_timeout_ms,
+            min_timeout_ms: self.search.min_timeout_ms,
+            max_timeout_ms: self.search.max_timeout_ms,
+            max_concurrent_files: self.search.max_concurrent_files,
+            max_open_files: self.search.max_open_files(),
+            max_files: self.search.max_files,
+            max_regex_len: self.search.max_regex_len(),
+            max_decompressed_bytes: self.search.max_decompressed_bytes,
+            strict_utf8: self.search.strict_utf8,
+            output_tz: self.search.output_tz.clone(),
+            global_exclude_patterns: self.search.global_exclude_patterns.clone(),
+            default_log_start_pattern: self.log_parser.default_log_start_pattern.clone(),
+            default_timestamp_regex: self.log_parser.default_timestamp_regex.clone(),
+            timestamp_presets: self.log_parser.timestamp_presets.clone(),
+        }
+    }
+
+    /// 检查全局日志路径：未配置任何路径，或某个路径不存在、不是文件、不可读时各返回一条说明。
+    pub fn source_problems(&self) -> Vec<String> {
+        let paths = self.log_sources.log_file_paths.as_deref().unwrap_or_default();
+        if paths.is_empty() {
+            return vec!["no global log files configured (log_sources.log_file_paths is empty)".to_string()];
+        }
+        paths
+            .iter()
+            .filter_map(|p| {
+                let path = Path::new(p);
+                if !path.exists() {
+                    Some(format!("log source {p} does not exist"))
+                } else if !path.is_file() {
+                    Some(format!("log source {p} is not a file"))
+                } else {
+                    std::fs::File::open(path)
+                        .err()
+                        .map(|e| format!("log source {p} is not readable: {e}"))
+                }
+            })
+            .collect()
+    }
+
+    /// 启动时检查临时目录可写：实际创建并删除一个探测文件。
+    pub fn check_temp_dir(&self) -> Result<()> {
+        let dir = self.search.temp_dir();
+        let probe = dir.join(format!(".log-search-mcp-probe-{}", std::process::id()));
+        std::fs::write(&probe, b"")
+            .and_then(|_| std::fs::remove_file(&probe))
+            .map_err(|e| {
+                LogSearchError::ConfigError(format!(
+                    "search.temp_dir {} is not writable: {e}",
+                    dir.display()
+                ))
+            })
+    }
+}
+
+/// 展开路径开头的 `~`（当前用户主目录）以及其中的 `$VAR` / `${VAR}`。
+/// 未定义的变量与非 UTF-8 路径保持原样。
+pub fn expand_path(path: &Path) -> PathBuf {
+    let Some(s) = path.to_str() else {
+        return path.to_path_buf();
+    };
+    if !s.contains(['~', '$']) {
+        return path.to_path_buf();
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    if rest == "~" || rest.starts_with("~/") {
+        if let Ok(home) = env::var("HOME") {
+            out.push_str(&home);
+            rest = &rest[1..];
+        }
+    }
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let (name, consumed) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let len = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..len], len)
+            }
+        };
+        match (!name.is_empty()).then(|| env::var(name).ok()).flatten() {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[pos..pos + 1 + consumed]),
+        }
+        rest = &after[consumed..];
+    }
+    out.push_str(rest);
+    PathBuf::from(out)
+}
+
 fn parse_server_mode(s: &str) -> Result<ServerMode> {
     match s.to_ascii_lowercase().as_str() {
         "stdio" => Ok(ServerMode::Stdio),
@@ -171,6 +758,41 @@ mod tests {
     use std::fs;
     use tempfile::tempdir;
 
+    #[test]
+    fn temp_dir_must_be_writable() {
+        let dir = tempdir().unwrap();
+        let mut cfg = Config {
+            server: ServerConfig {
+                mode: ServerMode::Stdio,
+                http_addr: None,
+                http_port: None,
+                max_watchers: None,
+                watch_poll_ms: None,
+                watch_token: None,
+                max_sse_sessions: None,
+                sse_keepalive_secs: None,
+                http_idle_timeout_secs: None,
+            },
+            log_parser: LogParserConfig {
+                default_log_start_pattern: None,
+                default_timestamp_regex: None,
+                level_order: None,
+                level_regex: None,
+                timestamp_presets: Default::default(),
+            },
+            search: SearchConfig {
+                temp_dir: Some(dir.path().to_path_buf()),
+                ..SearchConfig::default()
+            },
+            log_sources: LogSourceConfig::default(),
+        };
+        assert!(cfg.check_temp_dir().is_ok());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        cfg.search.temp_dir = Some(dir.path().join("missing"));
+        assert!(cfg.check_temp_dir().is_err());
+    }
+
     #[test]
     fn load_yaml_and_env_override() {
         let dir = tempdir().unwrap();
@@ -233,4 +855,103 @@ search:
         let err = Config::load_from_path(&path).unwrap_err().to_string();
         assert!(err.contains("default_page_size"));
     }
+
+    #[test]
+    fn timeout_bounds_clamp_requested_timeouts() {
+        let mut search = SearchConfig::default();
+        assert_eq!(search.effective_timeout_ms(Some(0)), None);
+        assert_eq!(search.effective_timeout_ms(None), Some(1_000));
+
+        search.min_timeout_ms = Some(100);
+        search.max_timeout_ms = Some(5_000);
+        assert_eq!(search.effective_timeout_ms(Some(0)), Some(5_000));
+        assert_eq!(search.effective_timeout_ms(Some(10)), Some(100));
+        assert_eq!(search.effective_timeout_ms(Some(60_000)), Some(5_000));
+        assert_eq!(search.effective_timeout_ms(None), Some(1_000));
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        fs::write(
+            &path,
+            "server: {mode: stdio}\nlog_parser: {}\nsearch: {default_page_size: 20, max_page_size: 200, default_timeout_ms: 1000, max_concurrent_files: 1, buffer_size: 1024, min_timeout_ms: 5000, max_timeout_ms: 100}\n",
+        )
+        .unwrap();
+        let err = Config::load_from_path(&path).unwrap_err().to_string();
+        assert!(err.contains("min_timeout_ms"));
+    }
+
+    #[test]
+    fn sse_keepalive_secs_rejects_zero_and_reads_env() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        let base = "log_parser: {}\nsearch: {default_page_size: 20, max_page_size: 200, default_timeout_ms: 1000, max_concurrent_files: 1, buffer_size: 1024}\n";
+        fs::write(&path, format!("server: {{mode: stdio, sse_keepalive_secs: 0}}\n{base}")).unwrap();
+        let err = Config::load_from_path(&path).unwrap_err().to_string();
+        assert!(err.contains("sse_keepalive_secs"));
+
+        fs::write(&path, format!("server: {{mode: stdio}}\n{base}")).unwrap();
+        env::set_var("LOG_SEARCH_MCP__SERVER__SSE_KEEPALIVE_SECS", "5");
+        let cfg = Config::load_from_path(&path);
+        env::remove_var("LOG_SEARCH_MCP__SERVER__SSE_KEEPALIVE_SECS");
+        assert_eq!(cfg.unwrap().server.sse_keepalive_secs, Some(5));
+    }
+
+    #[test]
+    fn expands_tilde_and_env_vars() {
+        env::set_var("LOG_SEARCH_MCP_TEST_APP", "billing");
+        let home = env::var("HOME").unwrap();
+        assert_eq!(expand_path(Path::new("~/logs")), PathBuf::from(format!("{home}/logs")));
+        assert_eq!(expand_path(Path::new("~")), PathBuf::from(&home));
+        assert_eq!(
+            expand_path(Path::new("$HOME/app/${LOG_SEARCH_MCP_TEST_APP}/logs")),
+            PathBuf::from(format!("{home}/app/billing/logs"))
+        );
+        // 未定义变量、非开头的 ~ 与孤立的 $ 保持原样
+        assert_eq!(
+            expand_path(Path::new("/a/$LOG_SEARCH_MCP_UNSET_VAR/~x/$")),
+            PathBuf::from("/a/$LOG_SEARCH_MCP_UNSET_VAR/~x/$")
+        );
+        assert_eq!(expand_path(Path::new("/a/${unclosed")), PathBuf::from("/a/${unclosed"));
+    }
+
+    #[test]
+    fn source_problems_report_missing_paths() {
+        let dir = tempdir().unwrap();
+        let present = dir.path().join("app.log");
+        fs::write(&present, "x").unwrap();
+        let mut cfg: Config = serde_yaml::from_str(
+            "server: {mode: stdio}\nlog_parser: {}\nsearch: {default_page_size: 20, max_page_size: 200, default_timeout_ms: 1000, max_concurrent_files: 1, buffer_size: 1024}\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.source_problems().len(), 1);
+
+        cfg.log_sources.log_file_paths = Some(vec![
+            present.to_string_lossy().into_owned(),
+            dir.path().join("missing.log").to_string_lossy().into_owned(),
+            dir.path().to_string_lossy().into_owned(),
+        ]);
+        let problems = cfg.source_problems();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("does not exist"));
+        assert!(problems[1].contains("is not a file"));
+    }
+
+    #[test]
+    fn server_info_expands_defaults() {
+        let mut cfg: Config = serde_yaml::from_str(
+            "server: {mode: http, http_port: 8080}\nlog_parser: {}\nsearch: {default_page_size: 20, max_page_size: 200, default_timeout_ms: 1000, max_concurrent_files: 1, buffer_size: 1024}\nlog_sources: {log_file_paths: [/var/log/app.log]}\n",
+        )
+        .unwrap();
+        let info = cfg.server_info();
+        assert_eq!(info.log_file_paths, vec!["/var/log/app.log"]);
+        assert_eq!(info.default_exclude_globs, DEFAULT_EXCLUDE_GLOBS);
+        assert_eq!(info.max_regex_len, DEFAULT_MAX_REGEX_LEN);
+        assert_eq!(info.default_page_size, 20);
+
+        cfg.log_sources.default_exclude_globs = Some(Vec::new());
+        let value = serde_json::to_value(cfg.server_info()).unwrap();
+        assert_eq!(value["mode"], "http");
+        assert_eq!(value["http_port"], 8080);
+        assert_eq!(value["default_exclude_globs"], serde_json::json!([]));
+    }
 }
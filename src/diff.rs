@@ -0,0 +1,128 @@
+use std::collections::{HashMap, HashSet};
+
+use regex::Regex;
+
+use crate::model::{DiffGroup, HitResult};
+
+/// 每次都会变化的部分：含数字的独立十六进制串（ID、UUID、地址），以及其余的数字串（时间戳、耗时、计数）。
+const VARIABLE_TOKEN: &str = r"\b(?:0[xX])?[0-9a-fA-F]*\d[0-9a-fA-F]*\b|\d+";
+
+/// 内容归一化器：把易变部分替换为 `#` 并压缩空白，使同一类日志在不同时间、不同请求下得到相同结果。
+pub struct ContentNormalizer {
+    variable: Regex,
+}
+
+impl Default for ContentNormalizer {
+    fn default() -> Self {
+        Self {
+            variable: Regex::new(VARIABLE_TOKEN).expect("built-in pattern"),
+        }
+    }
+}
+
+impl ContentNormalizer {
+    pub fn normalize(&self, content: &str) -> String {
+        let masked = self.variable.replace_all(content, "#");
+        masked.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// 按归一化内容分组，组按首次出现的顺序排列。
+    pub fn group(&self, hits: impl IntoIterator<Item = HitResult>) -> Vec<DiffGroup> {
+        let mut groups: Vec<DiffGroup> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for hit in hits {
+            let normalized = self.normalize(&hit.content);
+            match index.get(&normalized) {
+                Some(&i) => groups[i].count += 1,
+                None => {
+                    index.insert(normalized.clone(), groups.len());
+                    groups.push(DiffGroup {
+                        normalized,
+                        count: 1,
+                        example: hit,
+                    });
+                }
+            }
+        }
+        groups
+    }
+}
+
+/// 两侧分组的差集：返回 (只在 `current` 中, 只在 `baseline` 中, 共有种数)，
+/// 差集按条数从多到少排列，同条数保持首次出现顺序。
+pub fn diff_groups(
+    baseline: Vec<DiffGroup>,
+    current: Vec<DiffGroup>,
+) -> (Vec<DiffGroup>, Vec<DiffGroup>, usize) {
+    let baseline_keys: HashSet<&str> =
+        baseline.iter().map(|g| g.normalized.as_str()).collect();
+    let current_keys: HashSet<&str> =
+        current.iter().map(|g| g.normalized.as_str()).collect();
+    let common = baseline_keys.intersection(&current_keys).count();
+
+    let mut only_current: Vec<DiffGroup> = current
+        .iter()
+        .filter(|g| !baseline_keys.contains(g.normalized.as_str()))
+        .cloned()
+        .collect();
+    let mut only_baseline: Vec<DiffGroup> = baseline
+        .iter()
+        .filter(|g| !current_keys.contains(g.normalized.as_str()))
+        .cloned()
+        .collect();
+    only_current.sort_by_key(|g| std::cmp::Reverse(g.count));
+    only_baseline.sort_by_key(|g| std::cmp::Reverse(g.count));
+    (only_current, only_baseline, common)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(content: &str) -> HitResult {
+        HitResult {
+            file_path: "app.log".into(),
+            start_line: 1,
+            end_line: 1,
+            content: content.to_string(),
+            match_positions: Vec::new(),
+            timestamp: None,
+            context_before: Vec::new(),
+            context_after: Vec::new(),
+            matched_terms: Vec::new(),
+            content_start_line: None,
+            score: 0,
+        }
+    }
+
+    #[test]
+    fn normalization_masks_variable_tokens() {
+        let n = ContentNormalizer::default();
+        assert_eq!(
+            n.normalize("2024-01-01 10:00:00.123 ERROR  order 8812 failed id=550e8400-e29b-41d4-a716-446655440000"),
+            "#-#-# #:#:#.# ERROR order # failed id=#-#-#-#-#"
+        );
+        assert_eq!(n.normalize("user42 took 30ms at 0x7ffd"), "user# took #ms at #");
+    }
+
+    #[test]
+    fn diff_reports_new_and_vanished_groups() {
+        let n = ContentNormalizer::default();
+        let yesterday = n.group(vec![
+            hit("2024-01-01 ERROR timeout after 30s"),
+            hit("2024-01-01 ERROR disk full on /dev/sda1"),
+        ]);
+        let today = n.group(vec![
+            hit("2024-01-02 ERROR timeout after 31s"),
+            hit("2024-01-02 ERROR null pointer in handler"),
+            hit("2024-01-02 ERROR null pointer in handler"),
+        ]);
+        let (new, gone, common) = diff_groups(yesterday, today);
+        assert_eq!(common, 1);
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].count, 2);
+        assert!(new[0].example.content.contains("null pointer"));
+        assert_eq!(gone.len(), 1);
+        assert_eq!(gone[0].normalized, "#-#-# ERROR disk full on /dev/sda#");
+    }
+}
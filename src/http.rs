@@ -1,30 +1,59 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use axum::{
+    body::Body,
     extract::{
         rejection::{JsonRejection, QueryRejection},
+        ws::{Message, WebSocket, WebSocketUpgrade},
         Query, State,
     },
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
     response::{sse::{Event, Sse, KeepAlive}, IntoResponse},
     routing::{get, post},
     Json, Router,
 };
-use futures::stream::{Stream, StreamExt};
+use futures::stream::StreamExt;
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpListener;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
-use crate::model::{FileScanConfig, SearchRequest};
-use crate::search::SearchEngine;
+use crate::model::{ContentTypeFilter, FileScanConfig, SearchQuery, SearchRequest};
+use crate::render::hits_to_csv;
+use crate::search::{EngineStats, SearchEngine};
 use crate::{config::Config, error::Result};
 
+const DEFAULT_MAX_WATCHERS: usize = 16;
+const DEFAULT_WATCH_POLL_MS: u64 = 1_000;
+const DEFAULT_MAX_SSE_SESSIONS: usize = 64;
+
 #[derive(Clone)]
 pub struct AppState {
     pub engine: Arc<SearchEngine>,
     pub sessions: Arc<RwLock<HashMap<String, mpsc::UnboundedSender<Event>>>>,
+    /// 当前活跃的 `/watch` 订阅数。
+    pub watchers: Arc<AtomicUsize>,
+}
+
+/// 占用一个 watch 名额，释放时自动归还。
+struct WatcherSlot(Arc<AtomicUsize>);
+
+impl WatcherSlot {
+    fn acquire(counter: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        counter
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < max).then_some(n + 1))
+            .ok()
+            .map(|_| Self(counter.clone()))
+    }
+}
+
+impl Drop for WatcherSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -45,13 +74,29 @@ pub struct ListFilesQuery {
     pub include_globs: Vec<String>,
     #[serde(default)]
     pub exclude_globs: Vec<String>,
+    /// 文件名子串，`filename_regex=true` 时按正则匹配。
+    #[serde(default)]
+    pub filename_query: Option<String>,
+    #[serde(default)]
+    pub filename_regex: bool,
+    /// `text_only` 时跳过开头内容不像文本的文件。
+    #[serde(default)]
+    pub content_type_filter: ContentTypeFilter,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchOutputQuery {
+    /// `csv` 时以 CSV 返回本页命中，等同于 `Accept: text/csv`。
+    pub format: Option<String>,
 }
 
 async fn search_handler(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    output: Option<Query<SearchOutputQuery>>,
     payload: std::result::Result<Json<SearchRequest>, JsonRejection>,
 ) -> impl IntoResponse {
-    let req = match payload {
+    let mut req = match payload {
         Ok(Json(req)) => req,
         Err(e) => {
             return ErrorResponse {
@@ -60,9 +105,28 @@ async fn search_handler(
             .into_response()
         }
     };
+    if let Err(error) = apply_deadline_header(&headers, &mut req) {
+        return ErrorResponse { error }.into_response();
+    }
 
+    let csv = output
+        .and_then(|Query(q)| q.format)
+        .map(|f| f.eq_ignore_ascii_case("csv"))
+        .unwrap_or_else(|| {
+            headers
+                .get(header::ACCEPT)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.contains("text/csv"))
+        });
+    let fields = req.fields.clone();
     match state.engine.search(req).await {
-        Ok(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        Ok(resp) if csv => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+            hits_to_csv(&resp.page_hits()),
+        )
+            .into_response(),
+        Ok(resp) => (StatusCode::OK, Json(resp.to_json(fields.as_deref()))).into_response(),
         Err(e) => ErrorResponse {
             error: e.to_string(),
         }
@@ -70,6 +134,103 @@ async fn search_handler(
     }
 }
 
+/// 按 `X-Deadline` 收紧请求的 `total_timeout_ms`。
+fn apply_deadline_header(headers: &HeaderMap, req: &mut SearchRequest) -> std::result::Result<(), String> {
+    if let Some(budget) = deadline_budget_ms(headers)? {
+        req.total_timeout_ms = Some(match req.total_timeout_ms.filter(|ms| *ms > 0) {
+            Some(ms) => ms.min(budget),
+            None => budget,
+        });
+    }
+    Ok(())
+}
+
+/// `X-Deadline`（RFC 3339）距现在剩余的毫秒数，作为本次搜索的整体时间上限；
+/// 与请求中的 `total_timeout_ms` 取较小者。截止时间已过或格式错误时拒绝请求。
+fn deadline_budget_ms(headers: &HeaderMap) -> std::result::Result<Option<u64>, String> {
+    let Some(value) = headers.get("x-deadline") else {
+        return Ok(None);
+    };
+    let deadline = value
+        .to_str()
+        .ok()
+        .and_then(|v| chrono::DateTime::parse_from_rfc3339(v.trim()).ok())
+        .ok_or_else(|| format!("invalid X-Deadline header {value:?}: expected an RFC 3339 timestamp"))?;
+    let remaining = deadline.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    match u64::try_from(remaining.num_milliseconds()) {
+        Ok(ms) if ms > 0 => Ok(Some(ms)),
+        _ => Err(format!("X-Deadline {} has already passed", deadline.to_rfc3339())),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// URL 编码后的 SearchRequest JSON。
+    pub request: String,
+}
+
+/// `GET /search/export?request=<json>`：与 POST 相同，便于直接用浏览器或 curl 下载。
+async fn export_get_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    q: std::result::Result<Query<ExportQuery>, QueryRejection>,
+) -> axum::response::Response {
+    let req = match q
+        .map_err(|e| e.to_string())
+        .and_then(|Query(q)| serde_json::from_str(&q.request).map_err(|e| e.to_string()))
+    {
+        Ok(req) => req,
+        Err(e) => {
+            return ErrorResponse {
+                error: format!("invalid query: {e}"),
+            }
+            .into_response()
+        }
+    };
+    export_response(state, &headers, req).await
+}
+
+async fn export_post_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    payload: std::result::Result<Json<SearchRequest>, JsonRejection>,
+) -> axum::response::Response {
+    match payload {
+        Ok(Json(req)) => export_response(state, &headers, req).await,
+        Err(e) => ErrorResponse {
+            error: format!("invalid request body: {e}"),
+        }
+        .into_response(),
+    }
+}
+
+/// 以 NDJSON 流式返回全部命中，每行一个 HitResult。`X-Deadline` 与 `POST /search` 相同。
+async fn export_response(state: AppState, headers: &HeaderMap, mut req: SearchRequest) -> axum::response::Response {
+    if let Err(error) = apply_deadline_header(headers, &mut req) {
+        return ErrorResponse { error }.into_response();
+    }
+    let hits = match state.engine.clone().search_export(req).await {
+        Ok(hits) => hits,
+        Err(e) => {
+            return ErrorResponse {
+                error: e.to_string(),
+            }
+            .into_response()
+        }
+    };
+    let body = hits.map(|hit| {
+        serde_json::to_vec(&hit).map(|mut line| {
+            line.push(b'\n');
+            line
+        })
+    });
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
 async fn list_files_handler(
     State(state): State<AppState>,
     q: std::result::Result<Query<ListFilesQuery>, QueryRejection>,
@@ -87,6 +248,16 @@ async fn list_files_handler(
         root_path: q.root_path.into(),
         include_globs: q.include_globs,
         exclude_globs: q.exclude_globs,
+        filename_query: q.filename_query.map(|query| SearchQuery {
+            query: Some(query),
+            regex: q.filename_regex,
+            case_sensitive: false,
+            whole_word: false,
+            whole_line: false,
+            per_physical_line: false,
+        }),
+        content_type_filter: q.content_type_filter,
+        line_range: None,
     };
     match state.engine.list_files(&config) {
         Ok(files) => {
@@ -103,7 +274,25 @@ async fn list_files_handler(
     }
 }
 
-async fn sse_handler(State(state): State<AppState>) -> Sse<impl Stream<Item = std::result::Result<Event, axum::Error>>> {
+/// SSE 连接断开（响应流被丢弃）时从会话表中移除对应会话。
+struct SseSessionGuard {
+    sessions: Arc<RwLock<HashMap<String, mpsc::UnboundedSender<Event>>>>,
+    session_id: String,
+}
+
+impl Drop for SseSessionGuard {
+    fn drop(&mut self) {
+        self.sessions.write().unwrap().remove(&self.session_id);
+    }
+}
+
+async fn sse_handler(State(state): State<AppState>) -> axum::response::Response {
+    let server = state.engine.config_snapshot().server;
+    let max = server.max_sse_sessions.unwrap_or(DEFAULT_MAX_SSE_SESSIONS);
+    let keep_alive = match server.sse_keepalive_secs {
+        Some(secs) => KeepAlive::new().interval(Duration::from_secs(secs)),
+        None => KeepAlive::default(),
+    };
     let (tx, rx) = mpsc::unbounded_channel();
     let session_id = format!("{}", chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0));
     
@@ -114,10 +303,131 @@ async fn sse_handler(State(state): State<AppState>) -> Sse<impl Stream<Item = st
     // MCP spec usually expects relative URI reference.
     let _ = tx.send(Event::default().event("endpoint").data(endpoint_url));
     
-    state.sessions.write().unwrap().insert(session_id.clone(), tx);
-    
-    let stream = UnboundedReceiverStream::new(rx).map(Ok::<_, axum::Error>);
-    Sse::new(stream).keep_alive(KeepAlive::default())
+    {
+        // 计数与插入在同一把写锁内完成，避免并发连接越过上限
+        let mut sessions = state.sessions.write().unwrap();
+        if sessions.len() >= max {
+            return (StatusCode::SERVICE_UNAVAILABLE, "too many sse sessions").into_response();
+        }
+        sessions.insert(session_id.clone(), tx);
+    }
+    let guard = SseSessionGuard {
+        sessions: state.sessions.clone(),
+        session_id,
+    };
+
+    let stream = UnboundedReceiverStream::new(rx).map(move |event| {
+        let _ = &guard;
+        Ok::<_, axum::Error>(event)
+    });
+    Sse::new(stream).keep_alive(keep_alive).into_response()
+}
+
+#[derive(Debug, Serialize)]
+struct Metrics {
+    sse_sessions: usize,
+    watchers: usize,
+    engine: EngineStats,
+}
+
+/// `/metrics`：当前活跃的 SSE 会话与 `/watch` 订阅数。
+async fn metrics_handler(State(state): State<AppState>) -> Json<Metrics> {
+    Json(Metrics {
+        sse_sessions: state.sessions.read().unwrap().len(),
+        watchers: state.watchers.load(Ordering::SeqCst),
+        engine: state.engine.stats(),
+    })
+}
+
+/// `/watch`：客户端连接后发送一条 SearchRequest JSON，
+/// 服务端先返回一次完整搜索结果，再持续推送匹配文件中新追加的命中。
+/// 配置了 `server.watch_token` 时要求 `Authorization: Bearer <token>`。
+async fn watch_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> axum::response::Response {
+    let server = state.engine.config_snapshot().server;
+    if let Some(token) = &server.watch_token {
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        if bearer != Some(token.as_str()) {
+            return (StatusCode::UNAUTHORIZED, "invalid watch token").into_response();
+        }
+    }
+    let max = server.max_watchers.unwrap_or(DEFAULT_MAX_WATCHERS);
+    let Some(slot) = WatcherSlot::acquire(&state.watchers, max) else {
+        return (StatusCode::SERVICE_UNAVAILABLE, "too many watchers").into_response();
+    };
+    let poll = Duration::from_millis(server.watch_poll_ms.unwrap_or(DEFAULT_WATCH_POLL_MS));
+    let engine = state.engine.clone();
+    ws.on_upgrade(move |socket| run_watch(socket, engine, poll, slot))
+}
+
+async fn run_watch(mut socket: WebSocket, engine: Arc<SearchEngine>, poll: Duration, _slot: WatcherSlot) {
+    let request: SearchRequest = loop {
+        match socket.recv().await {
+            Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                Ok(req) => break req,
+                Err(e) => {
+                    let _ = send_json(&mut socket, serde_json::json!({ "type": "error", "error": format!("invalid request: {e}") })).await;
+                    return;
+                }
+            },
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+            Some(Ok(_)) => continue,
+        }
+    };
+
+    // 先建立基线再做首次搜索：两者之间追加的内容可能重复推送，但不会丢失。
+    let mut positions = match engine.tail_baseline(&request).await {
+        Ok(baseline) => baseline.positions,
+        Err(e) => {
+            let _ = send_json(&mut socket, serde_json::json!({ "type": "error", "error": e.to_string() })).await;
+            return;
+        }
+    };
+    match engine.search(request.clone()).await {
+        Ok(resp) => {
+            if send_json(&mut socket, serde_json::json!({ "type": "initial", "response": resp })).await.is_err() {
+                return;
+            }
+        }
+        Err(e) => {
+            let _ = send_json(&mut socket, serde_json::json!({ "type": "error", "error": e.to_string() })).await;
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+            _ = tokio::time::sleep(poll) => {
+                let update = match engine.search_incremental(&request, Some(positions.clone())).await {
+                    Ok(update) => update,
+                    Err(e) => {
+                        let _ = send_json(&mut socket, serde_json::json!({ "type": "error", "error": e.to_string() })).await;
+                        return;
+                    }
+                };
+                positions = update.positions;
+                for hit in update.hits {
+                    if send_json(&mut socket, serde_json::json!({ "type": "hit", "hit": hit })).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn send_json(socket: &mut WebSocket, value: serde_json::Value) -> std::result::Result<(), axum::Error> {
+    socket.send(Message::Text(value.to_string())).await
 }
 
 #[derive(Deserialize)]
@@ -153,16 +463,24 @@ pub fn build_router(engine: Arc<SearchEngine>) -> Router {
     let state = AppState { 
         engine,
         sessions: Arc::new(RwLock::new(HashMap::new())),
+        watchers: Arc::new(AtomicUsize::new(0)),
     };
     Router::new()
         .route("/search", post(search_handler))
+        .route("/search/export", get(export_get_handler).post(export_post_handler))
         .route("/files", get(list_files_handler))
         .route("/sse", get(sse_handler))
         .route("/message", post(message_handler))
+        .route("/watch", get(watch_handler))
+        .route("/metrics", get(metrics_handler))
         .with_state(state)
 }
 
-pub async fn serve_http(config: Config) -> Result<()> {
+/// 启动 HTTP 服务，`shutdown` 完成后停止接受新连接并等待已有请求结束。
+pub async fn serve_http<F>(config: Config, shutdown: F) -> Result<()>
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
     let config_arc = Arc::new(RwLock::new(config.clone()));
     let engine = Arc::new(SearchEngine::new(config_arc));
     let router = build_router(engine);
@@ -176,7 +494,147 @@ pub async fn serve_http(config: Config) -> Result<()> {
         .await
         .map_err(|e| crate::error::LogSearchError::ConfigError(format!("bind {addr} failed: {e}")))?;
     println!("HTTP server listening on http://{}", addr);
-    axum::serve(listener, router).await.map_err(|e| e.into())
+    match config.server.http_idle_timeout_secs {
+        Some(secs) => serve_with_idle_timeout(listener, router, Duration::from_secs(secs), shutdown).await,
+        None => axum::serve(listener, router)
+            .with_graceful_shutdown(shutdown)
+            .await
+            .map_err(|e| e.into()),
+    }
+}
+
+/// 与 `axum::serve` 相同的接受循环，另外关闭在 `idle` 内没有任何读写的连接。
+/// 关闭与停机都走 hyper 的优雅关闭：进行中的请求先完成，空闲的 keep-alive 连接立即断开。
+async fn serve_with_idle_timeout<F>(listener: TcpListener, router: Router, idle: Duration, shutdown: F) -> Result<()>
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    let (stop_tx, stop_rx) = tokio::sync::watch::channel(false);
+    tokio::pin!(shutdown);
+    let mut connections = tokio::task::JoinSet::new();
+    loop {
+        let (stream, remote) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::warn!("accept failed: {e}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+        let router = router.clone();
+        let mut stop_rx = stop_rx.clone();
+        connections.spawn(async move {
+            let activity = Arc::new(ActivityClock::new());
+            let io = hyper_util::rt::TokioIo::new(ActivityIo {
+                inner: stream,
+                activity: activity.clone(),
+            });
+            let service = hyper::service::service_fn(move |req: axum::http::Request<hyper::body::Incoming>| {
+                tower::ServiceExt::oneshot(router.clone(), req.map(Body::new))
+            });
+            let builder = hyper_util::server::conn::auto::Builder::new(hyper_util::rt::TokioExecutor::new());
+            let conn = builder.serve_connection_with_upgrades(io, service);
+            tokio::pin!(conn);
+            let mut closing = false;
+            loop {
+                let deadline = tokio::time::Instant::from_std(activity.last() + idle);
+                tokio::select! {
+                    result = conn.as_mut() => {
+                        if let Err(e) = result {
+                            tracing::debug!("connection {remote} ended: {e}");
+                        }
+                        break;
+                    }
+                    _ = tokio::time::sleep_until(deadline), if !closing => {
+                        if activity.last().elapsed() >= idle {
+                            tracing::debug!("closing idle connection {remote}");
+                            conn.as_mut().graceful_shutdown();
+                            closing = true;
+                        }
+                    }
+                    _ = stop_rx.changed(), if !closing => {
+                        conn.as_mut().graceful_shutdown();
+                        closing = true;
+                    }
+                }
+            }
+        });
+        // 回收已结束的连接任务
+        while connections.try_join_next().is_some() {}
+    }
+    drop(listener);
+    let _ = stop_tx.send(true);
+    while connections.join_next().await.is_some() {}
+    Ok(())
+}
+
+/// 连接最近一次读写的时刻。
+struct ActivityClock(std::sync::Mutex<std::time::Instant>);
+
+impl ActivityClock {
+    fn new() -> Self {
+        Self(std::sync::Mutex::new(std::time::Instant::now()))
+    }
+
+    fn touch(&self) {
+        *self.0.lock().unwrap() = std::time::Instant::now();
+    }
+
+    fn last(&self) -> std::time::Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// 读写成功时刷新 [`ActivityClock`] 的 TCP 流。
+struct ActivityIo {
+    inner: tokio::net::TcpStream,
+    activity: Arc<ActivityClock>,
+}
+
+impl tokio::io::AsyncRead for ActivityIo {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = std::pin::Pin::new(&mut self.inner).poll_read(cx, buf);
+        if buf.filled().len() > before {
+            self.activity.touch();
+        }
+        poll
+    }
+}
+
+impl tokio::io::AsyncWrite for ActivityIo {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        let poll = std::pin::Pin::new(&mut self.inner).poll_write(cx, buf);
+        if matches!(poll, std::task::Poll::Ready(Ok(n)) if n > 0) {
+            self.activity.touch();
+        }
+        poll
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
 }
 
 #[cfg(test)]
@@ -189,16 +647,20 @@ mod tests {
     use tower::util::ServiceExt;
 
     use crate::config::{Config, LogParserConfig, LogSourceConfig, SearchConfig, ServerConfig, ServerMode};
-    use crate::model::{SearchQuery, SearchResponse};
+    use crate::model::SearchResponse;
 
     fn create_test_engine(buffer_size: usize) -> Arc<SearchEngine> {
+        create_engine_with(|cfg| cfg.search.buffer_size = buffer_size)
+    }
+
+    fn create_engine_with(customize: impl FnOnce(&mut Config)) -> Arc<SearchEngine> {
         let mut cfg = Config {
-             server: ServerConfig { mode: ServerMode::Stdio, http_addr: None, http_port: None },
-             log_parser: LogParserConfig { default_log_start_pattern: None, default_timestamp_regex: None },
+             server: ServerConfig { mode: ServerMode::Stdio, http_addr: None, http_port: None, max_watchers: None, watch_poll_ms: None, watch_token: None, max_sse_sessions: None, sse_keepalive_secs: None, http_idle_timeout_secs: None },
+             log_parser: LogParserConfig { default_log_start_pattern: None, default_timestamp_regex: None, level_order: None, level_regex: None, timestamp_presets: Default::default() },
              search: SearchConfig::default(),
              log_sources: LogSourceConfig::default(),
         };
-        cfg.search.buffer_size = buffer_size;
+        customize(&mut cfg);
         Arc::new(SearchEngine::new(Arc::new(RwLock::new(cfg))))
     }
 
@@ -208,6 +670,8 @@ mod tests {
             regex: false,
             case_sensitive: false,
             whole_word: false,
+            whole_line: false,
+            per_physical_line: false,
         }
     }
 
@@ -223,6 +687,9 @@ mod tests {
             root_path: root.to_path_buf(),
             include_globs: vec!["**/*.log".to_string()],
             exclude_globs: Vec::new(),
+            filename_query: None,
+            content_type_filter: Default::default(),
+            line_range: None,
         };
         let direct_files = engine.list_files(&direct).unwrap();
         assert!(direct_files.contains(&log_path));
@@ -304,6 +771,253 @@ mod tests {
         assert!(result.hits[0].content.contains("traffic error"));
     }
 
+    #[tokio::test]
+    async fn search_returns_csv_when_requested() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("a.log"), "error, \"quoted\"\nok\n").unwrap();
+
+        let app = build_router(create_test_engine(16 * 1024));
+        let request_body = json!({
+            "scan_config": {
+                "root_path": root.to_string_lossy().replace('\\', "/"),
+                "include_globs": ["**/*.log"],
+                "exclude_globs": []
+            },
+            "logical_query": { "must": [sq("error")], "any": [], "none": [] },
+            "include_content": true
+        });
+        for (uri, accept) in [("/search?format=csv", "application/json"), ("/search", "text/csv")] {
+            let resp = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri(uri)
+                        .header("content-type", "application/json")
+                        .header("accept", accept)
+                        .body(Body::from(request_body.to_string()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.headers()["content-type"], "text/csv; charset=utf-8");
+            let body = to_bytes(resp.into_body(), 1024 * 1024).await.unwrap();
+            let text = String::from_utf8(body.to_vec()).unwrap();
+            let mut lines = text.lines();
+            assert_eq!(lines.next(), Some("file_path,start_line,end_line,content"));
+            assert!(lines.next().unwrap().ends_with(",1,1,\"error, \"\"quoted\"\"\""));
+        }
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_closed_after_timeout() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve_with_idle_timeout(
+            listener,
+            build_router(create_test_engine(1024)),
+            Duration::from_millis(300),
+            async move {
+                let _ = stop_rx.await;
+            },
+        ));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = vec![0u8; 4096];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 200"));
+
+        // keep-alive 连接空闲超过 300ms 后由服务端关闭
+        let closed = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if stream.read(&mut buf).await.unwrap_or(0) == 0 {
+                    break;
+                }
+            }
+        })
+        .await;
+        assert!(closed.is_ok());
+
+        stop_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn deadline_header_becomes_remaining_budget() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(deadline_budget_ms(&headers), Ok(None));
+
+        let deadline = chrono::Utc::now() + chrono::Duration::seconds(30);
+        headers.insert("x-deadline", deadline.to_rfc3339().parse().unwrap());
+        let budget = deadline_budget_ms(&headers).unwrap().unwrap();
+        assert!(budget > 25_000 && budget <= 30_000, "{budget}");
+
+        headers.insert("x-deadline", "2000-01-01T00:00:00Z".parse().unwrap());
+        assert!(deadline_budget_ms(&headers).unwrap_err().contains("already passed"));
+        headers.insert("x-deadline", "tomorrow".parse().unwrap());
+        assert!(deadline_budget_ms(&headers).unwrap_err().contains("RFC 3339"));
+    }
+
+    #[tokio::test]
+    async fn export_endpoint_streams_ndjson_hits() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("a.log"), "error one\nok\nerror two\n").unwrap();
+        std::fs::write(root.join("b.log"), "error three\n").unwrap();
+
+        let app = build_router(create_test_engine(16 * 1024));
+        let request_body = json!({
+            "scan_config": {
+                "root_path": root.to_string_lossy().replace('\\', "/"),
+                "include_globs": ["**/*.log"],
+                "exclude_globs": []
+            },
+            "logical_query": { "must": [sq("error")], "any": [], "none": [] },
+            "page_size": 1,
+            "include_content": true
+        });
+
+        let resp = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/search/export")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], "application/x-ndjson");
+        let body = to_bytes(resp.into_body(), 1024 * 1024).await.unwrap();
+        let hits: Vec<crate::model::HitResult> = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        // 不受 page_size 限制
+        assert_eq!(hits.len(), 3);
+
+        let encoded = urlencoding::encode(&request_body.to_string()).into_owned();
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/search/export?request={encoded}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = to_bytes(resp.into_body(), 1024 * 1024).await.unwrap();
+        assert_eq!(body.iter().filter(|b| **b == b'\n').count(), 3);
+    }
+
+    async fn metrics(app: &Router) -> serde_json::Value {
+        let resp = app
+            .clone()
+            .oneshot(Request::builder().uri("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = to_bytes(resp.into_body(), 1024).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn sse_sessions_are_capped_and_removed_on_disconnect() {
+        let app = build_router(create_engine_with(|cfg| cfg.server.max_sse_sessions = Some(1)));
+        let sse = || Request::builder().uri("/sse").body(Body::empty()).unwrap();
+
+        let first = app.clone().oneshot(sse()).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(metrics(&app).await["sse_sessions"], 1);
+
+        let second = app.clone().oneshot(sse()).await.unwrap();
+        assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // 丢弃响应流即视为客户端断开
+        drop(first);
+        assert_eq!(metrics(&app).await["sse_sessions"], 0);
+        let third = app.clone().oneshot(sse()).await.unwrap();
+        assert_eq!(third.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn watcher_slots_are_capped_and_released() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let first = WatcherSlot::acquire(&counter, 1).unwrap();
+        assert!(WatcherSlot::acquire(&counter, 1).is_none());
+        drop(first);
+        assert!(WatcherSlot::acquire(&counter, 1).is_some());
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn watch_requires_token_and_pushes_appended_hits() {
+        use futures::SinkExt;
+        use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Error as WsError, Message as WsMessage};
+
+        let dir = tempdir().unwrap();
+        let log_path = dir.path().join("app.log");
+        std::fs::write(&log_path, "error first\n").unwrap();
+        let engine = create_engine_with(|cfg| {
+            cfg.server.watch_poll_ms = Some(50);
+            cfg.server.watch_token = Some("secret".to_string());
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, build_router(engine)).await.unwrap() });
+        let url = format!("ws://{addr}/watch");
+
+        match tokio_tungstenite::connect_async(url.as_str()).await {
+            Err(WsError::Http(resp)) => assert_eq!(resp.status(), StatusCode::UNAUTHORIZED),
+            other => panic!("expected 401, got {:?}", other.map(|(_, resp)| resp.status())),
+        }
+
+        let mut request = url.into_client_request().unwrap();
+        request.headers_mut().insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        let (mut ws, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        let search = json!({
+            "scan_config": {
+                "root_path": dir.path().to_string_lossy().replace('\\', "/"),
+                "include_globs": ["**/*.log"],
+                "exclude_globs": []
+            },
+            "logical_query": { "must": [sq("error")], "any": [], "none": [] },
+            "page_size": 10,
+            "page": 1,
+            "include_content": true
+        });
+        ws.send(WsMessage::Text(search.to_string())).await.unwrap();
+
+        async fn next_frame(
+            ws: &mut (impl futures::Stream<Item = std::result::Result<WsMessage, WsError>> + Unpin),
+        ) -> serde_json::Value {
+            let msg = tokio::time::timeout(Duration::from_secs(5), ws.next()).await.unwrap().unwrap().unwrap();
+            serde_json::from_str(msg.to_text().unwrap()).unwrap()
+        }
+        let initial = next_frame(&mut ws).await;
+        assert_eq!(initial["type"], "initial");
+        assert_eq!(initial["response"]["total_hits"], 1);
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(&log_path).unwrap();
+        std::io::Write::write_all(&mut file, b"error second\n").unwrap();
+        let hit = next_frame(&mut ws).await;
+        assert_eq!(hit["type"], "hit");
+        assert!(hit["hit"]["content"].as_str().unwrap().contains("error second"));
+    }
+
     #[tokio::test]
     async fn search_endpoint_invalid_body_returns_400() {
         let engine = create_test_engine(16 * 1024);
@@ -0,0 +1,167 @@
+use regex::Regex;
+
+use crate::model::PatternProposal;
+
+/// 常见的行首形态，按具体程度从高到低排列；同样命中数时取靠前者。
+/// 第三项为可直接用作 timestamp_regex 的时间戳正则（仅时间戳类形态）。
+const CANDIDATES: &[(&str, &str, Option<&str>)] = &[
+    (
+        "iso8601",
+        r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}",
+        Some(r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|[+-]\d{2}:?\d{2})?"),
+    ),
+    (
+        "bracketed_iso8601",
+        r"\[\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}",
+        Some(r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|[+-]\d{2}:?\d{2})?"),
+    ),
+    (
+        "slash_date",
+        r"\d{4}/\d{2}/\d{2} \d{2}:\d{2}:\d{2}",
+        Some(r"\d{4}/\d{2}/\d{2} \d{2}:\d{2}:\d{2}"),
+    ),
+    (
+        "syslog",
+        r"[A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2}",
+        Some(r"[A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2}"),
+    ),
+    (
+        "apache_access",
+        r"\S+ \S+ \S+ \[\d{2}/[A-Z][a-z]{2}/\d{4}:\d{2}:\d{2}:\d{2}",
+        Some(r"\d{2}/[A-Z][a-z]{2}/\d{4}:\d{2}:\d{2}:\d{2} [+-]\d{4}"),
+    ),
+    (
+        "time_of_day",
+        r"\d{2}:\d{2}:\d{2}",
+        None,
+    ),
+    (
+        "bracketed_level",
+        r"\[(?:TRACE|DEBUG|INFO|WARN|WARNING|ERROR|FATAL)\]",
+        None,
+    ),
+    (
+        "level",
+        r"(?:TRACE|DEBUG|INFO|WARN|WARNING|ERROR|FATAL)\b",
+        None,
+    ),
+];
+
+/// 内置识别的时间戳正则（去重，按具体程度排列），用于补全 `timestamp_regex`。
+pub fn timestamp_regexes() -> Vec<&'static str> {
+    let mut out: Vec<&'static str> = Vec::new();
+    for re in CANDIDATES.iter().filter_map(|(_, _, ts)| *ts) {
+        if !out.contains(&re) {
+            out.push(re);
+        }
+    }
+    out
+}
+
+/// 内置识别的行首正则，用于补全 `log_start_pattern`。
+pub fn start_patterns() -> Vec<String> {
+    CANDIDATES
+        .iter()
+        .map(|(_, prefix, _)| format!("^{prefix}"))
+        .collect()
+}
+
+/// 看起来像多行条目续行的行：缩进、堆栈帧、异常链或闭合括号。
+fn looks_like_continuation(line: &str) -> bool {
+    line.starts_with([' ', '\t'])
+        || line.starts_with("at ")
+        || line.starts_with("Caused by")
+        || line.starts_with("...")
+        || line.starts_with(['}', ']', ')'])
+}
+
+/// 根据样本行推断多行日志的起始行正则。
+///
+/// 统计每种候选形态在行首出现的次数，取命中最多者。置信度为"命中行 + 形似续行的未命中行"
+/// 占非空行的比例：多行日志中续行本就不会命中，因此不计入反例。
+pub fn infer_log_start_pattern(lines: &[String]) -> Option<PatternProposal> {
+    let non_empty: Vec<&str> = lines
+        .iter()
+        .map(|l| l.as_str())
+        .filter(|l| !l.trim().is_empty())
+        .collect();
+    if non_empty.is_empty() {
+        return None;
+    }
+
+    let mut best: Option<(usize, usize, Vec<bool>)> = None;
+    for (idx, (_, prefix, _)) in CANDIDATES.iter().enumerate() {
+        let re = Regex::new(&format!("^{prefix}")).expect("built-in pattern");
+        let hits: Vec<bool> = non_empty.iter().map(|l| re.is_match(l)).collect();
+        let count = hits.iter().filter(|h| **h).count();
+        if count > 0 && best.as_ref().map(|(_, c, _)| count > *c).unwrap_or(true) {
+            best = Some((idx, count, hits));
+        }
+    }
+
+    let (idx, matched, hits) = best?;
+    let (kind, prefix, timestamp_regex) = CANDIDATES[idx];
+    let explained = hits
+        .iter()
+        .zip(&non_empty)
+        .filter(|(hit, line)| **hit || looks_like_continuation(line))
+        .count();
+    let example = non_empty
+        .iter()
+        .zip(&hits)
+        .find(|(_, hit)| **hit)
+        .map(|(line, _)| line.to_string());
+
+    Some(PatternProposal {
+        pattern: format!("^{prefix}"),
+        kind: kind.to_string(),
+        timestamp_regex: timestamp_regex.map(str::to_string),
+        confidence: explained as f64 / non_empty.len() as f64,
+        matched_lines: matched,
+        sampled_lines: non_empty.len(),
+        example,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn infers_iso_timestamp_with_stack_traces() {
+        let sample = lines(
+            "2024-01-01 10:00:00.123 INFO start\n\
+             2024-01-01 10:00:01.000 ERROR boom\n\
+             java.lang.IllegalStateException: bad\n\
+             \tat com.example.Main.run(Main.java:10)\n\
+             Caused by: java.io.IOException\n\
+             2024-01-01 10:00:02.000 INFO done\n",
+        );
+        let proposal = infer_log_start_pattern(&sample).unwrap();
+        assert_eq!(proposal.kind, "iso8601");
+        assert_eq!(proposal.matched_lines, 3);
+        assert_eq!(proposal.sampled_lines, 6);
+        assert!(proposal.confidence > 0.8 && proposal.confidence < 1.0);
+        assert!(proposal.timestamp_regex.is_some());
+
+        let re = Regex::new(&proposal.pattern).unwrap();
+        assert!(re.is_match("2024-01-01T10:00:00Z INFO x"));
+        assert!(!re.is_match("\tat frame"));
+    }
+
+    #[test]
+    fn infers_level_prefix_and_handles_no_signal() {
+        let sample = lines("[INFO] a\n[WARN] b\n  detail\n");
+        let proposal = infer_log_start_pattern(&sample).unwrap();
+        assert_eq!(proposal.kind, "bracketed_level");
+        assert_eq!(proposal.confidence, 1.0);
+        assert!(proposal.timestamp_regex.is_none());
+
+        assert!(infer_log_start_pattern(&lines("hello\nworld\n")).is_none());
+        assert!(infer_log_start_pattern(&[]).is_none());
+    }
+}
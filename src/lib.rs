@@ -9,5 +9,10 @@ pub mod reader;
 pub mod parser;
 pub mod query;
 pub mod search;
+pub mod infer;
+pub mod diff;
+pub mod render;
+pub mod complete;
+pub mod session;
 pub mod http;
 pub mod mcp;
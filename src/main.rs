@@ -1,6 +1,9 @@
 use std::env;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 use tokio::time::sleep;
 
 use log_search_mcp::config::Config;
@@ -58,6 +61,13 @@ async fn main() -> Result<()> {
         e
     })?;
     
+    config.check_temp_dir()?;
+    if config.log_sources.validate_sources_on_start {
+        for problem in config.source_problems() {
+            tracing::warn!("{problem}");
+        }
+    }
+
     if let Ok(mut file) = std::fs::OpenOptions::new().create(true).append(true).open(log_file_path) {
          let _ = writeln!(file, "Config loaded successfully.");
          let _ = writeln!(file, "Log files: {:?}", config.log_sources.log_file_paths);
@@ -73,62 +83,155 @@ async fn main() -> Result<()> {
     // 将配置包装在 Arc<RwLock> 中以支持热重载
     let config_arc = Arc::new(RwLock::new(config.clone()));
     
-    // 启动热重载任务
-    let config_path_owned = cfg_path.to_path_buf();
-    let config_for_update = config_arc.clone();
-    
+    // 关闭信号：收到 Ctrl-C / SIGTERM 或服务自行退出时置为 true
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let signal_tx = shutdown_tx.clone();
     tokio::spawn(async move {
-        let mut last_mtime = match std::fs::metadata(&config_path_owned) {
-            Ok(m) => m.modified().ok(),
-            Err(_) => None,
-        };
-        
-        loop {
-            sleep(Duration::from_secs(5)).await;
-            
-            match std::fs::metadata(&config_path_owned) {
-                Ok(m) => {
-                     let mtime = m.modified().ok();
-                     if mtime != last_mtime {
-                         // 简单的去抖动或直接重载
-                         eprintln!("Config changed, reloading...");
-                         match Config::load_from_path(&config_path_owned) {
-                             Ok(new_cfg) => {
-                                 let mut w = config_for_update.write().unwrap();
-                                 *w = new_cfg;
-                                 last_mtime = mtime;
-                                 eprintln!("Config reloaded successfully.");
-                             },
-                             Err(e) => {
-                                 eprintln!("Failed to reload config: {}", e);
-                             }
-                         }
-                     }
-                },
-                Err(_) => {} 
-            }
-        }
+        wait_for_signal().await;
+        eprintln!("Shutdown signal received.");
+        let _ = signal_tx.send(true);
     });
 
-    match config.server.mode {
+    // 启动热重载任务
+    let reload_task = spawn_config_reload(cfg_path.to_path_buf(), config_arc.clone(), shutdown_rx.clone());
+
+    let uses_stdio = !matches!(config.server.mode, log_search_mcp::config::ServerMode::Http);
+    let result = match config.server.mode {
         log_search_mcp::config::ServerMode::Http => {
-            serve_http(config).await?;
+            serve_http(config, shutdown_future(shutdown_rx.clone())).await
         }
         log_search_mcp::config::ServerMode::Stdio => {
             let engine = std::sync::Arc::new(log_search_mcp::search::SearchEngine::new(config_arc));
-            run_stdio(engine).await?;
+            tokio::select! {
+                res = run_stdio(engine) => res,
+                _ = shutdown_future(shutdown_rx.clone()) => Ok(()),
+            }
         }
         log_search_mcp::config::ServerMode::Both => {
             let engine = std::sync::Arc::new(log_search_mcp::search::SearchEngine::new(config_arc));
-            let engine2 = engine.clone();
             // 注意：serve_http 接收 Config 所有权，因此 HTTP 服务目前不支持热重载配置。
-            
-            let http_task = tokio::spawn(async move { serve_http(config).await });
-            let stdio_task = tokio::spawn(async move { run_stdio(engine2).await });
-            let _ = http_task.await.expect("http task panicked")?;
-            let _ = stdio_task.await.expect("stdio task panicked")?;
+            let http_task = tokio::spawn(serve_http(config, shutdown_future(shutdown_rx.clone())));
+            let stdio_rx = shutdown_rx.clone();
+            let stdio_task = tokio::spawn(async move {
+                tokio::select! {
+                    res = run_stdio(engine) => res,
+                    _ = shutdown_future(stdio_rx) => Ok(()),
+                }
+            });
+            let http_res = http_task.await.expect("http task panicked");
+            let stdio_res = stdio_task.await.expect("stdio task panicked");
+            http_res.and(stdio_res)
+        }
+    };
+
+    // 服务结束后通知后台任务退出，并等待热重载任务收尾
+    let _ = shutdown_tx.send(true);
+    let _ = reload_task.await;
+
+    // tokio 的 stdin 读取运行在阻塞线程上，运行时析构会一直等它读到下一行；
+    // 使用 stdio 时直接退出进程，避免关闭后仍挂起。
+    if uses_stdio {
+        if let Err(e) = &result {
+            eprintln!("Error: {e}");
         }
+        std::process::exit(if result.is_ok() { 0 } else { 1 });
     }
+    result?;
 
     Ok(())
 }
+
+/// 每 5 秒检查配置文件的修改时间，变化时重新加载；收到关闭信号后退出。
+fn spawn_config_reload(
+    config_path: PathBuf,
+    config: Arc<RwLock<Config>>,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last_mtime = match std::fs::metadata(&config_path) {
+            Ok(m) => m.modified().ok(),
+            Err(_) => None,
+        };
+
+        loop {
+            tokio::select! {
+                _ = sleep(Duration::from_secs(5)) => {}
+                _ = shutdown.wait_for(|stop| *stop) => break,
+            }
+
+            if let Ok(m) = std::fs::metadata(&config_path) {
+                let mtime = m.modified().ok();
+                if mtime != last_mtime {
+                    // 简单的去抖动或直接重载
+                    eprintln!("Config changed, reloading...");
+                    match Config::load_from_path(&config_path) {
+                        Ok(new_cfg) => {
+                            let mut w = config.write().unwrap();
+                            *w = new_cfg;
+                            last_mtime = mtime;
+                            eprintln!("Config reloaded successfully.");
+                        },
+                        Err(e) => {
+                            eprintln!("Failed to reload config: {}", e);
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// 关闭信号置位时完成的 future；发送端全部释放也视为关闭。
+async fn shutdown_future(mut shutdown: watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
+
+/// 等待 Ctrl-C，Unix 下同时等待 SIGTERM。
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn config_reload_stops_promptly_on_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::fs::write(
+            &path,
+            "server: {mode: stdio}\nlog_parser: {}\nsearch: {default_page_size: 20, max_page_size: 200, default_timeout_ms: 1000, max_concurrent_files: 1, buffer_size: 1024}\n",
+        )
+        .unwrap();
+        let config = Arc::new(RwLock::new(Config::load_from_path(&path).unwrap()));
+        let (tx, rx) = watch::channel(false);
+        let reload = spawn_config_reload(path, config, rx.clone());
+        let shutdown = tokio::spawn(shutdown_future(rx));
+        // 让两者都进入等待，再发出关闭信号
+        sleep(Duration::from_millis(50)).await;
+        tx.send(true).unwrap();
+
+        // 远早于 5 秒的检查间隔结束
+        tokio::time::timeout(Duration::from_secs(1), reload).await.unwrap().unwrap();
+        tokio::time::timeout(Duration::from_secs(1), shutdown).await.unwrap().unwrap();
+    }
+}
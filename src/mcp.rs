@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
@@ -5,7 +7,8 @@ use serde_json::Value;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 
 use crate::error::{LogSearchError, Result};
-use crate::model::{FileScanConfig, SearchRequest};
+use crate::model::{json_schema, ContentTypeFilter, FileScanConfig, LogicalQuery, RenderMode, SearchQuery, SearchRequest, TailPosition, TimeFilter};
+use crate::render::{render_grep, render_hits};
 use crate::search::SearchEngine;
 
 fn debug_log(msg: &str) {
@@ -56,7 +59,25 @@ pub async fn process_request(engine: Arc<SearchEngine>, req: RpcRequest) -> RpcR
         
         "list_log_files" => handle_list_files(&engine, &req).await,
         "search_logs" => handle_search(&engine, &req).await,
+        "prepare_search" => handle_prepare_search(&engine, &req),
+        "fetch_page" => handle_fetch_page(&engine, &req).await,
+        "why_file" => handle_why_file(&engine, &req),
+        "schema" => handle_schema(&req),
+        "engine_stats" => handle_engine_stats(&engine, &req),
+        "server_info" => handle_server_info(&engine, &req),
+        "diff_searches" => handle_diff_searches(&engine, &req).await,
+        "distinct_values" => handle_distinct_values(&engine, &req).await,
+        "error_summary" => handle_error_summary(&engine, &req).await,
+        "estimate_matches" => handle_estimate_matches(&engine, &req).await,
+        "head_file" => handle_head_file(&engine, &req).await,
+        "tail_file" => handle_tail_file(&engine, &req).await,
+        "infer_log_start_pattern" => handle_infer_pattern(&engine, &req).await,
+        "test_regex" => handle_test_regex(&engine, &req),
+        "search_new_logs" => handle_search_new(&engine, &req).await,
         "tools/list" | "list_tools" => handle_list_tools(&req),
+        "resources/list" => handle_list_resources(&engine, &req),
+        "resources/read" => handle_read_resource(&engine, &req).await,
+        "completion/complete" => handle_complete(&engine, &req),
         _ => RpcResponse {
             jsonrpc: "2.0",
             id: req.id,
@@ -109,7 +130,9 @@ fn handle_initialize(req: &RpcRequest) -> RpcResponse {
         result: Some(serde_json::json!({
             "protocolVersion": "2024-11-05",
             "capabilities": {
-                "tools": {}
+                "tools": {},
+                "resources": {},
+                "completions": {}
             },
             "serverInfo": {
                 "name": "log-search-mcp",
@@ -156,6 +179,21 @@ async fn handle_tool_call(engine: &SearchEngine, req: &RpcRequest) -> RpcRespons
             match p.name.as_str() {
                 "list_log_files" => handle_list_files(engine, &sub_req).await,
                 "search_logs" => handle_search(engine, &sub_req).await,
+                "prepare_search" => handle_prepare_search(engine, &sub_req),
+                "fetch_page" => handle_fetch_page(engine, &sub_req).await,
+                "why_file" => handle_why_file(engine, &sub_req),
+                "schema" => handle_schema(&sub_req),
+                "engine_stats" => handle_engine_stats(engine, &sub_req),
+                "server_info" => handle_server_info(engine, &sub_req),
+                "diff_searches" => handle_diff_searches(engine, &sub_req).await,
+                "distinct_values" => handle_distinct_values(engine, &sub_req).await,
+                "error_summary" => handle_error_summary(engine, &sub_req).await,
+                "estimate_matches" => handle_estimate_matches(engine, &sub_req).await,
+                "head_file" => handle_head_file(engine, &sub_req).await,
+                "tail_file" => handle_tail_file(engine, &sub_req).await,
+                "infer_log_start_pattern" => handle_infer_pattern(engine, &sub_req).await,
+                "test_regex" => handle_test_regex(engine, &sub_req),
+                "search_new_logs" => handle_search_new(engine, &sub_req).await,
                 _ => rpc_error(req, -32601, format!("tool not found: {}", p.name)),
             }
         }
@@ -169,8 +207,7 @@ async fn handle_tool_call(engine: &SearchEngine, req: &RpcRequest) -> RpcRespons
 async fn handle_list_files(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
     debug_log("handle_list_files called");
     let params: Result<ListFilesParams> = serde_json::from_value(req.params.clone())
-        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")))
-        .map_err(Into::into);
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
 
     match params {
         Ok(p) => {
@@ -182,6 +219,9 @@ async fn handle_list_files(engine: &SearchEngine, req: &RpcRequest) -> RpcRespon
                 root_path: p.root_path.into(),
                 include_globs: p.include_globs.unwrap_or_default(),
                 exclude_globs: p.exclude_globs.unwrap_or_default(),
+                filename_query: p.filename_query,
+                content_type_filter: p.content_type_filter,
+                line_range: None,
             };
             match engine.list_files(&cfg) {
                 Ok(files) => {
@@ -220,27 +260,43 @@ async fn handle_list_files(engine: &SearchEngine, req: &RpcRequest) -> RpcRespon
 async fn handle_search(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
     debug_log(&format!("handle_search: params={}", req.params));
     let params: Result<SearchRequest> = serde_json::from_value(req.params.clone())
-        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")))
-        .map_err(Into::into);
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
 
     match params {
         Ok(p) => {
             debug_log(&format!("Search request parsed: {:?}", p));
+            let fields = p.fields.clone();
+            let separator = p.context_separator.clone();
+            let render = p.render;
             match engine.search(p).await {
                 Ok(res) => {
                     debug_log(&format!("Search success. Hits: {}", res.hits.len()));
                     
                     // 将结果序列化为格式化的 JSON 字符串
-                    let content_text = serde_json::to_string_pretty(&res).unwrap_or_else(|_| "{}".to_string());
+                    let content_text = serde_json::to_string_pretty(&res.to_json(fields.as_deref())).unwrap_or_else(|_| "{}".to_string());
+                    let mut content = vec![serde_json::json!({
+                        "type": "text",
+                        "text": content_text
+                    })];
+                    // 指定分隔符时附带纯文本渲染，JSON 保持不变
+                    if let Some(separator) = separator {
+                        content.push(serde_json::json!({
+                            "type": "text",
+                            "text": render_hits(&res.page_hits(), &separator)
+                        }));
+                    }
+                    if render == Some(RenderMode::Grep) {
+                        content.push(serde_json::json!({
+                            "type": "text",
+                            "text": render_grep(&res.page_hits())
+                        }));
+                    }
                     
                     RpcResponse {
                         jsonrpc: "2.0",
                         id: req.id.clone(),
                         result: Some(serde_json::json!({
-                            "content": [{
-                                "type": "text",
-                                "text": content_text
-                            }],
+                            "content": content,
                             "isError": false
                         })),
                         error: None,
@@ -273,6 +329,371 @@ async fn handle_search(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
     }
 }
 
+fn handle_why_file(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_why_file: params={}", req.params));
+    let params: Result<WhyFileParams> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => {
+            let scan_config = p.scan_config.unwrap_or(FileScanConfig {
+                root_path: Default::default(),
+                include_globs: Vec::new(),
+                exclude_globs: Vec::new(),
+                filename_query: None,
+                content_type_filter: Default::default(),
+                line_range: None,
+            });
+            match engine.why_file(std::path::Path::new(&p.path), &scan_config) {
+                Ok(explanation) => {
+                    let text = serde_json::to_string_pretty(&explanation).unwrap_or_default();
+                    tool_result(req, text, false)
+                }
+                Err(e) => tool_result(req, format!("why_file failed: {e}"), true),
+            }
+        }
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+async fn handle_search_new(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_search_new: params={}", req.params));
+    let params: Result<SearchNewParams> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => match engine.search_incremental(&p.request, p.positions).await {
+            Ok(res) => {
+                let text = serde_json::to_string_pretty(&res).unwrap_or_default();
+                tool_result(req, text, false)
+            }
+            Err(e) => tool_result(req, format!("Search failed: {e}"), true),
+        },
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+async fn handle_head_file(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_head_file: params={}", req.params));
+    let params: Result<HeadFileParams> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => match engine.head_file(std::path::Path::new(&p.path), p.lines).await {
+            Ok(lines) => {
+                let text = serde_json::to_string_pretty(&serde_json::json!({
+                    "path": p.path,
+                    "lines": lines
+                }))
+                .unwrap_or_default();
+                tool_result(req, text, false)
+            }
+            Err(e) => tool_result(req, format!("head_file failed: {e}"), true),
+        },
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+async fn handle_tail_file(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_tail_file: params={}", req.params));
+    let params: Result<HeadFileParams> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => match engine.tail_file(std::path::Path::new(&p.path), p.lines).await {
+            Ok(lines) => {
+                let text = serde_json::to_string_pretty(&serde_json::json!({
+                    "path": p.path,
+                    "lines": lines
+                }))
+                .unwrap_or_default();
+                tool_result(req, text, false)
+            }
+            Err(e) => tool_result(req, format!("tail_file failed: {e}"), true),
+        },
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+async fn handle_infer_pattern(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_infer_pattern: params={}", req.params));
+    let params: Result<InferPatternParams> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => match engine
+            .infer_log_start_pattern(std::path::Path::new(&p.path), p.lines)
+            .await
+        {
+            Ok(Some(proposal)) => {
+                let text = serde_json::to_string_pretty(&proposal).unwrap_or_default();
+                tool_result(req, text, false)
+            }
+            Ok(None) => tool_result(
+                req,
+                "No common line prefix (timestamp or level) found in the sampled lines; the file is probably single-line or free-form.".to_string(),
+                false,
+            ),
+            Err(e) => tool_result(req, format!("infer_log_start_pattern failed: {e}"), true),
+        },
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+fn handle_test_regex(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_test_regex: params={}", req.params));
+    let params: Result<TestRegexParams> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => {
+            let result = engine.test_regex(&p.pattern, &p.sample_text, p.case_sensitive);
+            let text = serde_json::to_string_pretty(&result).unwrap_or_default();
+            tool_result(req, text, false)
+        }
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+fn resource_uri(path: &std::path::Path) -> String {
+    format!("file://{}", path.display())
+}
+
+/// 资源列表：配置的 `log_file_paths` 中存在的文件。
+fn handle_list_resources(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    let resources: Vec<Value> = engine
+        .resource_files()
+        .into_iter()
+        .map(|path| {
+            serde_json::json!({
+                "uri": resource_uri(&path),
+                "name": path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+                "mimeType": "text/plain",
+                "size": std::fs::metadata(&path).map(|m| m.len()).ok(),
+            })
+        })
+        .collect();
+    RpcResponse {
+        jsonrpc: "2.0",
+        id: req.id.clone(),
+        result: Some(serde_json::json!({ "resources": resources })),
+        error: None,
+    }
+}
+
+/// 读取资源内容；只允许读取 `resources/list` 中列出的文件。
+/// 除标准的 `uri` 外支持可选的 `byte_offset`、`start_line`/`lines` 分段读取，单次最多 [`MAX_RESOURCE_LINES`] 行。
+async fn handle_read_resource(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_read_resource: params={}", req.params));
+    let p: ReadResourceParams = match serde_json::from_value(req.params.clone()) {
+        Ok(p) => p,
+        Err(e) => return rpc_error(req, -32602, format!("invalid params: {e}")),
+    };
+    let path = p
+        .uri
+        .strip_prefix("file://")
+        .map(|raw| PathBuf::from(urlencoding::decode(raw).map(|d| d.into_owned()).unwrap_or_else(|_| raw.to_string())));
+    let allowed = engine.resource_files();
+    let Some(path) = path.filter(|p| {
+        let canonical = p.canonicalize().ok();
+        allowed
+            .iter()
+            .any(|a| a == p || (canonical.is_some() && a.canonicalize().ok() == canonical))
+    }) else {
+        return rpc_error(req, -32002, format!("resource not found: {}", p.uri));
+    };
+
+    match engine
+        .read_line_range(&path, p.byte_offset, p.start_line, p.lines.min(MAX_RESOURCE_LINES))
+        .await
+    {
+        Ok(lines) => {
+            let mut text = lines.join("\n");
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            RpcResponse {
+                jsonrpc: "2.0",
+                id: req.id.clone(),
+                result: Some(serde_json::json!({
+                    "contents": [{
+                        "uri": p.uri,
+                        "mimeType": "text/plain",
+                        "text": text
+                    }]
+                })),
+                error: None,
+            }
+        }
+        Err(e) => rpc_error(req, -32603, format!("failed to read resource: {e}")),
+    }
+}
+
+/// 参数补全：按参数名给出目录、glob 或时间戳形态候选，与 `ref` 指向的工具无关。
+fn handle_complete(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    let p: CompleteParams = match serde_json::from_value(req.params.clone()) {
+        Ok(p) => p,
+        Err(e) => return rpc_error(req, -32602, format!("invalid params: {e}")),
+    };
+    let configured = engine
+        .config_snapshot()
+        .log_sources
+        .log_file_paths
+        .unwrap_or_default();
+    let values = crate::complete::complete_argument(&p.argument.name, &p.argument.value, &configured);
+    RpcResponse {
+        jsonrpc: "2.0",
+        id: req.id.clone(),
+        result: Some(serde_json::json!({
+            "completion": {
+                "values": values,
+                "total": values.len(),
+                "hasMore": false
+            }
+        })),
+        error: None,
+    }
+}
+
+fn handle_schema(req: &RpcRequest) -> RpcResponse {
+    let text = serde_json::to_string_pretty(&json_schema::<SearchRequest>()).unwrap_or_default();
+    tool_result(req, text, false)
+}
+
+fn handle_engine_stats(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    let text = serde_json::to_string_pretty(&engine.stats()).unwrap_or_default();
+    tool_result(req, text, false)
+}
+
+fn handle_server_info(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    let text = serde_json::to_string_pretty(&engine.config_snapshot().server_info()).unwrap_or_default();
+    tool_result(req, text, false)
+}
+
+async fn handle_diff_searches(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_diff_searches: params={}", req.params));
+    let params: Result<DiffSearchesParams> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => match engine.diff_searches(&p.baseline, &p.current, p.limit).await {
+            Ok(diff) => {
+                let text = serde_json::to_string_pretty(&diff).unwrap_or_default();
+                tool_result(req, text, false)
+            }
+            Err(e) => tool_result(req, format!("diff_searches failed: {e}"), true),
+        },
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+fn handle_prepare_search(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_prepare_search: params={}", req.params));
+    let params: Result<SearchRequest> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => match engine.prepare_search(p) {
+            Ok(info) => tool_result(req, serde_json::to_string_pretty(&info).unwrap_or_default(), false),
+            Err(e) => tool_result(req, format!("prepare_search failed: {e}"), true),
+        },
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+async fn handle_fetch_page(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_fetch_page: params={}", req.params));
+    let params: Result<FetchPageParams> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => match engine
+            .fetch_page(p.session_id.as_deref(), &p.handle, p.page, p.page_size)
+            .await
+        {
+            Ok(res) => {
+                let text = serde_json::to_string_pretty(&res.to_json(p.fields.as_deref())).unwrap_or_default();
+                tool_result(req, text, false)
+            }
+            Err(e) => tool_result(req, format!("fetch_page failed: {e}"), true),
+        },
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+async fn handle_distinct_values(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_distinct_values: params={}", req.params));
+    let params: Result<DistinctValuesParams> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => match engine.distinct_values(&p.scan_config, &p.pattern, p.limit).await {
+            Ok(values) => {
+                let text = serde_json::to_string_pretty(&values).unwrap_or_default();
+                tool_result(req, text, false)
+            }
+            Err(e) => tool_result(req, format!("distinct_values failed: {e}"), true),
+        },
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+async fn handle_error_summary(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_error_summary: params={}", req.params));
+    let params: Result<ErrorSummaryParams> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => match engine
+            .error_summary(&p.scan_config, p.time_window.as_ref(), p.level_regex.as_deref(), p.limit)
+            .await
+        {
+            Ok(summary) => {
+                let text = serde_json::to_string_pretty(&summary).unwrap_or_default();
+                tool_result(req, text, false)
+            }
+            Err(e) => tool_result(req, format!("error_summary failed: {e}"), true),
+        },
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+async fn handle_estimate_matches(engine: &SearchEngine, req: &RpcRequest) -> RpcResponse {
+    debug_log(&format!("handle_estimate_matches: params={}", req.params));
+    let params: Result<EstimateMatchesParams> = serde_json::from_value(req.params.clone())
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid params: {e}")));
+
+    match params {
+        Ok(p) => match engine
+            .estimate_matches(&p.scan_config, &p.logical_query, p.sample_files, p.sample_bytes, p.sample_seed)
+            .await
+        {
+            Ok(estimate) => {
+                let text = serde_json::to_string_pretty(&estimate).unwrap_or_default();
+                tool_result(req, text, false)
+            }
+            Err(e) => tool_result(req, format!("estimate_matches failed: {e}"), true),
+        },
+        Err(e) => rpc_error(req, -32602, e.to_string()),
+    }
+}
+
+/// MCP 要求工具结果包装在 content 数组中。
+fn tool_result(req: &RpcRequest, text: String, is_error: bool) -> RpcResponse {
+    RpcResponse {
+        jsonrpc: "2.0",
+        id: req.id.clone(),
+        result: Some(serde_json::json!({
+            "content": [{
+                "type": "text",
+                "text": text
+            }],
+            "isError": is_error
+        })),
+        error: None,
+    }
+}
+
 async fn write_response(stdout: &mut tokio::io::Stdout, resp: RpcResponse) -> Result<()> {
     let line = serde_json::to_string(&resp).unwrap_or_else(|_| "{}".to_string());
     stdout.write_all(line.as_bytes()).await?;
@@ -296,9 +717,176 @@ struct ListFilesParams {
     pub root_path: String,
     pub include_globs: Option<Vec<String>>,
     pub exclude_globs: Option<Vec<String>>,
+    #[serde(default)]
+    pub filename_query: Option<SearchQuery>,
+    #[serde(default)]
+    pub content_type_filter: ContentTypeFilter,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhyFileParams {
+    pub path: String,
+    #[serde(default)]
+    pub scan_config: Option<FileScanConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HeadFileParams {
+    pub path: String,
+    #[serde(default = "default_head_lines")]
+    pub lines: usize,
+}
+
+fn default_head_lines() -> usize {
+    20
+}
+
+#[derive(Debug, Deserialize)]
+struct InferPatternParams {
+    pub path: String,
+    #[serde(default = "default_infer_lines")]
+    pub lines: usize,
+}
+
+fn default_infer_lines() -> usize {
+    200
+}
+
+#[derive(Debug, Deserialize)]
+struct TestRegexParams {
+    pub pattern: String,
+    pub sample_text: String,
+    #[serde(default = "default_case_sensitive")]
+    pub case_sensitive: bool,
+}
+
+fn default_case_sensitive() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct CompleteArgument {
+    pub name: String,
+    #[serde(default)]
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompleteParams {
+    pub argument: CompleteArgument,
+}
+
+/// resources/read 单次最多返回的行数。
+const MAX_RESOURCE_LINES: usize = 10_000;
+
+#[derive(Debug, Deserialize)]
+struct ReadResourceParams {
+    pub uri: String,
+    /// 解压后的字节偏移，从其后第一条完整记录开始读取；带 `.gzi` 索引的 gzip 可直接定位。
+    #[serde(default)]
+    pub byte_offset: u64,
+    /// 起始行（1 起，自 `byte_offset` 处起算），默认从头读取。
+    #[serde(default = "default_start_line")]
+    pub start_line: usize,
+    #[serde(default = "default_resource_lines")]
+    pub lines: usize,
+}
+
+fn default_start_line() -> usize {
+    1
+}
+
+fn default_resource_lines() -> usize {
+    MAX_RESOURCE_LINES
+}
+
+#[derive(Debug, Deserialize)]
+struct DiffSearchesParams {
+    pub baseline: SearchRequest,
+    pub current: SearchRequest,
+    #[serde(default = "default_diff_limit")]
+    pub limit: usize,
+}
+
+fn default_diff_limit() -> usize {
+    50
+}
+
+#[derive(Debug, Deserialize)]
+struct FetchPageParams {
+    pub handle: String,
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default = "default_fetch_page")]
+    pub page: usize,
+    #[serde(default)]
+    pub page_size: Option<usize>,
+    #[serde(default)]
+    pub fields: Option<Vec<String>>,
+}
+
+fn default_fetch_page() -> usize {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+struct DistinctValuesParams {
+    pub scan_config: FileScanConfig,
+    pub pattern: String,
+    #[serde(default = "default_distinct_limit")]
+    pub limit: usize,
+}
+
+fn default_distinct_limit() -> usize {
+    100
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorSummaryParams {
+    pub scan_config: FileScanConfig,
+    #[serde(default)]
+    pub time_window: Option<TimeFilter>,
+    #[serde(default)]
+    pub level_regex: Option<String>,
+    #[serde(default = "default_error_summary_limit")]
+    pub limit: usize,
+}
+
+fn default_error_summary_limit() -> usize {
+    20
+}
+
+#[derive(Debug, Deserialize)]
+struct EstimateMatchesParams {
+    pub scan_config: FileScanConfig,
+    pub logical_query: LogicalQuery,
+    #[serde(default = "default_estimate_sample_files")]
+    pub sample_files: usize,
+    #[serde(default)]
+    pub sample_bytes: Option<u64>,
+    #[serde(default)]
+    pub sample_seed: Option<u64>,
+}
+
+fn default_estimate_sample_files() -> usize {
+    20
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchNewParams {
+    #[serde(flatten)]
+    pub request: SearchRequest,
+    #[serde(default)]
+    pub positions: Option<HashMap<PathBuf, TailPosition>>,
 }
 
 fn handle_list_tools(req: &RpcRequest) -> RpcResponse {
+    let mut search_new_schema = json_schema::<SearchRequest>();
+    search_new_schema["properties"]["positions"] = serde_json::json!({
+        "type": ["object", "null"],
+        "description": "Explicit per-file positions ({path: {offset, line}}) from a previous call. When omitted, positions stored in the session are used and updated."
+    });
+
     let tools = vec![
         serde_json::json!({
             "name": "list_log_files",
@@ -308,90 +896,172 @@ fn handle_list_tools(req: &RpcRequest) -> RpcResponse {
                 "properties": {
                     "root_path": { "type": "string", "description": "Optional root path. If omitted, uses globally configured log files." },
                     "include_globs": { "type": "array", "items": { "type": "string" } },
-                    "exclude_globs": { "type": "array", "items": { "type": "string" } }
+                    "exclude_globs": { "type": "array", "items": { "type": "string" } },
+                    "filename_query": json_schema::<SearchQuery>(),
+                    "content_type_filter": json_schema::<ContentTypeFilter>()
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "why_file",
+            "description": "Explain whether a file would be searched: root containment, include/exclude glob matches, log_file_paths membership and extension recognition.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["path"],
+                "properties": {
+                    "path": { "type": "string", "description": "File path to explain." },
+                    "scan_config": json_schema::<FileScanConfig>()
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "head_file",
+            "description": "Return the first lines of a file (gzip and non-UTF-8 encodings are decoded) to inspect its format before searching, e.g. to choose log_start_pattern and timestamp_regex.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["path"],
+                "properties": {
+                    "path": { "type": "string", "description": "File path to preview." },
+                    "lines": { "type": "integer", "description": "Number of lines to return. Defaults to 20, at most 1000." }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "tail_file",
+            "description": "Return the last lines of a file (gzip and non-UTF-8 encodings are decoded). Plain files are read backwards from the end; gzip files are streamed keeping only the last lines in memory.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["path"],
+                "properties": {
+                    "path": { "type": "string", "description": "File path to read." },
+                    "lines": { "type": "integer", "description": "Number of lines to return. Defaults to 20, at most 1000." }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "infer_log_start_pattern",
+            "description": "Sample the first lines of a file and propose a log_start_pattern (and timestamp_regex when the prefix is a timestamp) for multiline parsing, with a confidence estimate.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["path"],
+                "properties": {
+                    "path": { "type": "string", "description": "File path to sample." },
+                    "lines": { "type": "integer", "description": "Number of lines to sample. Defaults to 200, at most 1000." }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "test_regex",
+            "description": "Compile a regex with the same rules as search (length limit, case sensitivity) and show its matches and capture groups on sample text. Syntax errors are reported with their position. Use it to iterate on a pattern before running an expensive search.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["pattern", "sample_text"],
+                "properties": {
+                    "pattern": { "type": "string" },
+                    "sample_text": { "type": "string", "description": "Text to match against, e.g. a few log lines from head_file." },
+                    "case_sensitive": { "type": "boolean", "description": "Defaults to true." }
                 }
             }
         }),
         serde_json::json!({
             "name": "search_logs",
             "description": "Search log files with logical queries, optional time filter and multiline pattern.",
+            "inputSchema": json_schema::<SearchRequest>()
+        }),
+        serde_json::json!({
+            "name": "prepare_search",
+            "description": "Validate a search_logs request, compile its patterns and scan the file list once, and store it in the session as a prepared search. Returns a handle for fetch_page.",
+            "inputSchema": json_schema::<SearchRequest>()
+        }),
+        serde_json::json!({
+            "name": "fetch_page",
+            "description": "Return a page of a prepared search. The first call searches the stored file list once and caches all ordered hits; later pages are served from the cache without reading files.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["handle"],
+                "properties": {
+                    "handle": { "type": "string", "description": "Handle returned by prepare_search." },
+                    "session_id": { "type": "string", "description": "Session the search was prepared in. Defaults to \"default\"." },
+                    "page": { "type": "integer", "description": "1-based page number. Defaults to 1." },
+                    "page_size": { "type": "integer", "description": "Overrides the prepared request's page_size." },
+                    "fields": { "type": "array", "items": { "type": "string" }, "description": "Only return these top-level response fields." }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "search_new_logs",
+            "description": "Search only records appended since the previous call in the same session. The first call records the current end of each file and returns no hits.",
+            "inputSchema": search_new_schema
+        }),
+        serde_json::json!({
+            "name": "schema",
+            "description": "Return the JSON Schema of the search_logs request, generated from the server's own types.",
+            "inputSchema": { "type": "object", "properties": {} }
+        }),
+        serde_json::json!({
+            "name": "diff_searches",
+            "description": "Run two searches (e.g. yesterday vs today) and return hits whose normalized content (digits, hex ids and timestamps masked) appears in only one of them, grouped with counts and an example hit.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["baseline", "current"],
+                "properties": {
+                    "baseline": json_schema::<SearchRequest>(),
+                    "current": json_schema::<SearchRequest>(),
+                    "limit": { "type": "integer", "description": "Maximum groups returned per side. Defaults to 50." }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "distinct_values",
+            "description": "Scan files and count the distinct values of the first capture group of a regex (e.g. error_code=(\\w+)), most frequent first. Only counts are kept, no hits are collected.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["scan_config", "pattern"],
+                "properties": {
+                    "scan_config": json_schema::<FileScanConfig>(),
+                    "pattern": { "type": "string", "description": "Regex with at least one capturing group; the first group is counted per line. Case-sensitive unless it uses (?i)." },
+                    "limit": { "type": "integer", "description": "Maximum values returned. Defaults to 100." }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "error_summary",
+            "description": "Summarize errors in a time window in one call: total count, most frequent messages (digits, hex ids and timestamps masked), first/last occurrence and an hourly histogram.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["scan_config"],
+                "properties": {
+                    "scan_config": json_schema::<FileScanConfig>(),
+                    "time_window": json_schema::<TimeFilter>(),
+                    "level_regex": { "type": "string", "description": "Regex selecting error entries. Case-sensitive unless it uses (?i). Defaults to ERROR/ERR/FATAL/CRITICAL as a whole word, ignoring entries whose first level is below ERROR." },
+                    "limit": { "type": "integer", "description": "Maximum message groups returned. Defaults to 20." }
+                }
+            }
+        }),
+        serde_json::json!({
+            "name": "estimate_matches",
+            "description": "Estimate how many entries a query would match without searching everything: searches a random sample of files (optionally only their first sample_bytes) and extrapolates a total with a rough 95% interval. Use it on large trees to decide whether a full search_logs is worthwhile.",
             "inputSchema": {
                 "type": "object",
                 "required": ["scan_config", "logical_query"],
                 "properties": {
-                    "scan_config": {
-                        "type": "object",
-                        "properties": {
-                            "root_path": { "type": "string", "description": "Root directory to scan. Optional if system logs are configured." },
-                            "include_globs": { "type": "array", "items": { "type": "string" } },
-                            "exclude_globs": { "type": "array", "items": { "type": "string" } }
-                        }
-                    },
-                    "logical_query": {
-                        "type": "object",
-                        "properties": {
-                            "must": { 
-                                "type": "array",
-                                "items": {
-                                    "anyOf": [
-                                        { "type": "string" },
-                                        {
-                                            "type": "object",
-                                            "properties": {
-                                                "query": { "type": "string" },
-                                                "regex": { "type": "boolean" },
-                                                "case_sensitive": { "type": "boolean" },
-                                                "whole_word": { "type": "boolean" }
-                                            }
-                                        }
-                                    ]
-                                }
-                            },
-                            "any": { 
-                                "type": "array",
-                                "items": {
-                                    "anyOf": [
-                                        { "type": "string" },
-                                        {
-                                            "type": "object",
-                                            "properties": {
-                                                "query": { "type": "string" },
-                                                "regex": { "type": "boolean" },
-                                                "case_sensitive": { "type": "boolean" },
-                                                "whole_word": { "type": "boolean" }
-                                            }
-                                        }
-                                    ]
-                                }
-                            },
-                            "none": { 
-                                "type": "array",
-                                "items": {
-                                    "anyOf": [
-                                        { "type": "string" },
-                                        {
-                                            "type": "object",
-                                            "properties": {
-                                                "query": { "type": "string" },
-                                                "regex": { "type": "boolean" },
-                                                "case_sensitive": { "type": "boolean" },
-                                                "whole_word": { "type": "boolean" }
-                                            }
-                                        }
-                                    ]
-                                }
-                            }
-                        }
-                    },
-                    "time_filter": { "type": ["object", "null"] },
-                    "log_start_pattern": { "type": ["string", "null"] },
-                    "page_size": { "type": "integer" },
-                    "page": { "type": "integer" },
-                    "max_hits": { "type": ["integer", "null"] },
-                    "hard_timeout_ms": { "type": ["integer", "null"] },
-                    "include_content": { "type": "boolean" }
+                    "scan_config": json_schema::<FileScanConfig>(),
+                    "logical_query": json_schema::<LogicalQuery>(),
+                    "sample_files": { "type": "integer", "description": "Number of files to sample. Defaults to 20." },
+                    "sample_bytes": { "type": "integer", "description": "Read at most this many bytes from the start of each sampled file; larger plain files are scaled up by size. Defaults to reading whole files." },
+                    "sample_seed": { "type": "integer", "description": "Random seed; the same seed and file list give the same sample." }
                 }
             }
+        }),
+        serde_json::json!({
+            "name": "server_info",
+            "description": "Return the effective server configuration: mode, configured log_file_paths, default include/exclude globs, page sizes, timeouts and other limits that apply to searches.",
+            "inputSchema": { "type": "object", "properties": {} }
+        }),
+        serde_json::json!({
+            "name": "engine_stats",
+            "description": "Return cumulative counters since the server started: searches run, files read, bytes read, hits returned and average search latency.",
+            "inputSchema": { "type": "object", "properties": {} }
         })
     ];
 
@@ -1,6 +1,7 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// 日志条目。多行聚合或单行均用该结构承载。
@@ -12,26 +13,65 @@ pub struct LogEntry {
     pub content: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FileScanConfig {
+    /// 扫描根目录；配置了全局 log_file_paths 时可省略。
     #[serde(default)]
     pub root_path: PathBuf,
+    /// 包含模式，为空时使用默认集合（*.log、*.gz）。
     #[serde(default)]
     pub include_globs: Vec<String>,
+    /// 排除模式。
     #[serde(default)]
     pub exclude_globs: Vec<String>,
+    /// 按文件名（不含目录）过滤，沿用查询项的匹配语义（可用正则、区分大小写等）。
+    /// 设置后 `include_globs` 为空时不再套用默认扩展名集合。
+    #[serde(default)]
+    pub filename_query: Option<SearchQuery>,
+    /// 按文件内容筛选：`text_only` 读取开头一小段，跳过看起来不是可解码文本的文件（如混入的二进制）。
+    #[serde(default)]
+    pub content_type_filter: ContentTypeFilter,
+    /// 只在每个文件的该行号区间（含两端，从 1 起）内匹配：起始行落在区间外的条目不会成为命中，
+    /// 但仍可作为上下文返回；越过区间末尾后不再继续读取该文件。
+    #[serde(default)]
+    pub line_range: Option<(usize, usize)>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 按内容类型筛选文件的方式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentTypeFilter {
+    /// 不检查内容。
+    #[default]
+    Any,
+    /// 只保留开头内容可解码为文本的文件；gzip 按解压后内容判断。
+    TextOnly,
+}
+
+impl FileScanConfig {
+    /// 展开 `root_path` 中的 `~` 与环境变量。
+    pub fn expanded(&self) -> Self {
+        Self {
+            root_path: crate::config::expand_path(&self.root_path),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(from = "SearchQueryInput")]
 pub struct SearchQuery {
     pub query: Option<String>,
     pub regex: bool,
     pub case_sensitive: bool,
     pub whole_word: bool,
+    /// 整行匹配：纯文本要求某一物理行去掉首尾空白后与查询完全相等，正则则对该行以 `^...$` 锚定。
+    pub whole_line: bool,
+    /// 多行条目中要求匹配落在同一物理行内，而不是跨行匹配拼接后的内容。
+    pub per_physical_line: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(untagged)]
 enum SearchQueryInput {
     Simple(String),
@@ -43,6 +83,10 @@ enum SearchQueryInput {
         case_sensitive: bool,
         #[serde(default)]
         whole_word: bool,
+        #[serde(default)]
+        whole_line: bool,
+        #[serde(default)]
+        per_physical_line: bool,
     },
 }
 
@@ -54,32 +98,45 @@ impl From<SearchQueryInput> for SearchQuery {
                 regex: false,
                 case_sensitive: false,
                 whole_word: false,
+                whole_line: false,
+                per_physical_line: false,
             },
             SearchQueryInput::Full {
                 query,
                 regex,
                 case_sensitive,
                 whole_word,
+                whole_line,
+                per_physical_line,
             } => SearchQuery {
                 query,
                 regex,
                 case_sensitive,
                 whole_word,
+                whole_line,
+                per_physical_line,
             },
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LogicalQuery {
     pub must: Vec<SearchQuery>,
     #[serde(default)]
     pub any: Vec<SearchQuery>,
     #[serde(default)]
     pub none: Vec<SearchQuery>,
+    /// `any` 中至少需要命中的项数，默认 1；为 0 时 `any` 仅用于高亮与计数。
+    #[serde(default = "default_any_min_matches")]
+    pub any_min_matches: usize,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+fn default_any_min_matches() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TimeFilter {
     #[serde(alias = "start_time", alias = "startTime", alias = "after")]
     pub time_start: Option<String>,
@@ -94,11 +151,15 @@ pub struct MatchPosition {
     pub length: usize,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SearchRequest {
     pub scan_config: FileScanConfig,
     pub logical_query: LogicalQuery,
     pub time_filter: Option<TimeFilter>,
+    /// 多个时间窗口 `[起, 止]`（闭区间，格式同 `time_filter`），条目时间落在任一窗口内即保留；
+    /// 与 `time_filter` 的起止时间同时生效。
+    #[serde(default)]
+    pub time_windows: Vec<(String, String)>,
     pub log_start_pattern: Option<String>,
     #[serde(default)]
     pub page_size: usize,
@@ -110,6 +171,253 @@ pub struct SearchRequest {
     pub hard_timeout_ms: Option<u64>,
     #[serde(default = "default_include_content")]
     pub include_content: bool,
+    /// 扫描后随机抽取最多 N 个文件进行搜索，用于快速估计分布。
+    #[serde(default)]
+    pub sample_files: Option<usize>,
+    /// 抽样随机种子；相同种子与文件列表得到相同样本。
+    #[serde(default)]
+    pub sample_seed: Option<u64>,
+    /// 会话标识；结果集等会话状态按该值隔离，缺省为 "default"。
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// 将本次全部命中的位置引用保存为会话内的命名结果集。
+    #[serde(default)]
+    pub save_as: Option<String>,
+    /// 以会话内已保存的结果集为基准筛选本次命中。
+    #[serde(default)]
+    pub refine_from: Option<String>,
+    #[serde(default)]
+    pub refine_mode: RefineMode,
+    /// 覆盖配置中的记录分隔符字节。
+    #[serde(default)]
+    pub line_delimiter: Option<u8>,
+    /// 整个响应中返回的匹配位置总数上限；达到后后续命中不再携带位置。
+    #[serde(default)]
+    pub max_total_positions: Option<usize>,
+    /// 日志行的结构化格式，决定 `field_query` 如何解析字段。
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// 按字段过滤：键为字段名，值沿用 SearchQuery 的匹配语义；所有字段都须匹配。
+    #[serde(default)]
+    pub field_query: HashMap<String, SearchQuery>,
+    /// 数值阈值条件，所有条件都须满足；条目中提取不到数值时不匹配。
+    #[serde(default)]
+    pub numeric_query: Vec<NumericQuery>,
+    /// 结果排序方式。
+    #[serde(default)]
+    pub sort_by: SortBy,
+    /// 按文件分组返回本页命中（填充 `grouped_hits`，此时 `hits` 为空）。
+    #[serde(default)]
+    pub group_by_file: bool,
+    /// 把全部命中（分页前）按模板分组：数字、UUID、十六进制地址、时间戳等易变部分替换为 `#`，
+    /// 结果填入 `templates`。
+    #[serde(default)]
+    pub template: bool,
+    /// 最低日志级别（如 "WARN"），低于该级别的条目被丢弃；级别顺序见 log_parser.level_order。
+    #[serde(default)]
+    pub min_level: Option<String>,
+    /// 每条命中附带其前面 N 条完整日志条目（多行模式下按条目而非按行计）。
+    #[serde(default)]
+    pub context_entries_before: usize,
+    /// 每条命中附带其后面 N 条完整日志条目。
+    #[serde(default)]
+    pub context_entries_after: usize,
+    /// 把前后文与命中条目按行序拼成一段 `content` 返回（`context_before`/`context_after` 为空），
+    /// `match_positions` 随之平移到拼接后的文本中，`content_start_line` 给出这段文本的首行行号。
+    #[serde(default)]
+    pub merge_context: bool,
+    /// 只返回列出的响应顶层字段（如 `["total_hits", "hits"]`），省略时返回全部字段。
+    #[serde(default)]
+    pub fields: Option<Vec<String>>,
+    /// 上一次响应中的 `next_cursor`：从会话保存的结果中直接取下一页，不重新搜索。
+    /// 此时除 `session_id`、`page_size` 及结果呈现相关选项外的参数被忽略。
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// 整个请求的墙钟时间上限（毫秒），到时返回已收集的命中并置 `timed_out`；0 或省略表示不限制。
+    #[serde(default)]
+    pub total_timeout_ms: Option<u64>,
+    /// 单个文件扫描或读取失败时的处理方式。
+    #[serde(default)]
+    pub on_file_error: ErrorMode,
+    /// 匹配前把条目中连续的空白（含换行）折叠为单个空格；返回的内容仍为原文，
+    /// 匹配位置映射回原文，跨越折叠空白的命中覆盖整段原始空白。
+    #[serde(default)]
+    pub normalize_whitespace: bool,
+    /// 读取后先去掉每行中的 ANSI 颜色等 CSI 控制序列，匹配、返回内容与匹配位置都基于去除后的文本。
+    #[serde(default)]
+    pub strip_ansi: bool,
+    /// 匹配前把条目内容与查询词都做 Unicode NFC 规范化，使组合形式与预组形式的字符（如 `e\u0301` 与 `é`）互相匹配。
+    /// 此时返回的 `content` 为规范化后的文本，匹配位置也基于它。
+    #[serde(default)]
+    pub unicode_normalize: bool,
+    /// 返回的命中路径改写为相对于该目录（支持 ~ 与 $VAR）；不在其下的文件仍为绝对路径。
+    #[serde(default)]
+    pub relative_to: Option<PathBuf>,
+    /// 本次请求输出派生时间戳（命中的 `timestamp`）所用的 IANA 时区，如 "Asia/Shanghai"；
+    /// 覆盖配置中的 `search.output_tz`，省略时沿用配置。
+    #[serde(default)]
+    pub display_tz: Option<String>,
+    /// 对活跃写入的文件做稳定的页码分页：第 1 页记录各文件当前大小并保存到会话，响应返回 `snapshot_id`；
+    /// 后续页带上该 `snapshot_id` 时只读到记录的大小（之后新出现的文件不读），结果不随文件增长漂移。
+    /// `cursor` 翻页本身就基于保存的结果，不需要此选项。
+    #[serde(default)]
+    pub snapshot_sizes: bool,
+    /// `snapshot_sizes` 第 1 页响应中的 `snapshot_id`；第 2 页起必须给出，不存在时请求报错。
+    #[serde(default)]
+    pub snapshot_id: Option<String>,
+    /// 跳过指定会话中已完整搜索过的文件，便于逐步扩大排查范围时每次只看新文件。
+    #[serde(default)]
+    pub exclude_session_files: Option<String>,
+    /// 每个文件只读取开头 N 个字节（gzip 按解压后计），用于快速检查格式或是否出现某内容。
+    #[serde(default)]
+    pub scan_first_bytes: Option<u64>,
+    /// 每个文件最多保留的命中数，避免单个嘈杂文件占满 `max_hits`。
+    #[serde(default)]
+    pub max_hits_per_file: Option<usize>,
+    /// 在响应中附带各阶段耗时（`timing`），用于判断慢在 IO 还是匹配。
+    #[serde(default)]
+    pub profile: bool,
+    /// 不套用配置中的 `search.global_exclude_patterns`。
+    #[serde(default)]
+    pub ignore_global_excludes: bool,
+    /// 把轮转文件（`app.log`、`app.log.1`、`app.log.2.gz`、`app.log-20240101` 等）归为一组，
+    /// 组内按从旧到新的顺序搜索并输出命中，便于跨轮转片段还原时间线。
+    #[serde(default)]
+    pub group_rotations: bool,
+    /// 设置后，MCP 结果在 JSON 之外附带一段纯文本渲染（命中连同上下文），
+    /// 相邻命中之间以该分隔符（如 "--"）所在的一行隔开；不影响结构化 JSON。
+    #[serde(default)]
+    pub context_separator: Option<String>,
+    /// 设置后，MCP 结果在 JSON 之外附带一段按该格式渲染的纯文本；不影响结构化 JSON。
+    #[serde(default)]
+    pub render: Option<RenderMode>,
+    /// 文件送入搜索的顺序；配合 `max_hits` 时靠前的文件优先贡献命中。
+    #[serde(default)]
+    pub scan_order: ScanOrder,
+}
+
+/// 扫描到的文件送入搜索任务的顺序。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanOrder {
+    /// 按路径字母序。
+    #[default]
+    PathAsc,
+    /// 最近修改的文件优先。
+    MtimeDesc,
+    /// 最早修改的文件优先。
+    MtimeAsc,
+}
+
+/// 命中结果的排序方式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    /// 按文件完成顺序输出，文件内保持行序；不保证跨文件顺序。
+    #[default]
+    Unordered,
+    /// 按条目时间戳升序合并各文件结果；需要可用的 timestamp_regex。
+    TimestampAsc,
+    /// 按命中的 `score` 降序；分数相同时按文件路径和行号排序。
+    Relevance,
+}
+
+/// 命中的纯文本渲染格式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderMode {
+    /// 类似 `grep -n --heading`：每个文件一行路径标题，其下每行带行号，
+    /// 命中行为 `行号:内容`、上下文行为 `行号-内容`，不相邻的片段之间以 `--` 隔开。
+    Grep,
+}
+
+/// 文件级错误的处理方式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorMode {
+    /// 记入响应的 `failed_files`，继续搜索其余文件。
+    #[default]
+    Collect,
+    /// 遇到第一个文件错误即中止搜索并返回该错误。
+    Fail,
+    /// 丢弃错误，继续搜索其余文件。
+    Ignore,
+}
+
+/// 日志行格式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// 纯文本，不解析字段。
+    #[default]
+    Text,
+    /// `key=value` 形式，值可用双引号包裹。
+    Logfmt,
+    /// 每个条目为一个 JSON 对象（JSONL），嵌套字段以 `a.b` 访问；
+    /// 以 `[` 开头的文件按 JSON 数组导出处理，每个元素为一个条目。
+    Json,
+    /// Apache/Nginx 的 common 或 combined 访问日志，字段为 `remote_addr`、`user`、`time`、
+    /// `method`、`path`、`protocol`、`status`、`bytes`，combined 另有 `referer`、`user_agent`。
+    Apache,
+}
+
+/// 数值阈值条件：从条目中提取数值并与 `value` 比较，如 `latency=(\d+)` 大于 500。
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct NumericQuery {
+    /// 提取数值的正则，取第一个捕获组（没有捕获组时取整个匹配）。
+    pub field_regex: String,
+    pub op: CompareOp,
+    pub value: f64,
+}
+
+/// 数值比较运算符。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareOp {
+    #[serde(alias = ">")]
+    Gt,
+    #[serde(alias = ">=")]
+    Gte,
+    #[serde(alias = "<")]
+    Lt,
+    #[serde(alias = "<=")]
+    Lte,
+    #[serde(alias = "==")]
+    Eq,
+    #[serde(alias = "!=")]
+    Ne,
+}
+
+impl CompareOp {
+    pub fn holds(self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Gte => lhs >= rhs,
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Lte => lhs <= rhs,
+            CompareOp::Eq => lhs == rhs,
+            CompareOp::Ne => lhs != rhs,
+        }
+    }
+}
+
+/// 基于已保存结果集的筛选方式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RefineMode {
+    /// 仅保留同时出现在结果集中的命中。
+    #[default]
+    Intersect,
+    /// 仅保留不在结果集中的命中。
+    Subtract,
+}
+
+/// 生成类型的 JSON Schema，子结构内联展开，便于直接用作 MCP `inputSchema`。
+pub fn json_schema<T: JsonSchema>() -> serde_json::Value {
+    let generator = schemars::generate::SchemaSettings::draft07()
+        .with(|s| s.inline_subschemas = true)
+        .into_generator();
+    generator.into_root_schema_for::<T>().to_value()
 }
 
 fn default_include_content() -> bool {
@@ -127,6 +435,125 @@ pub struct HitResult {
     pub end_line: usize,
     pub content: String,
     pub match_positions: Vec<MatchPosition>,
+    /// 条目时间戳，按请求的 display_tz 或 search.output_tz 格式化；无法提取时省略。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context_before: Vec<ContextEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context_after: Vec<ContextEntry>,
+    /// 在该条目中出现过的 must/any 查询词，按查询中的顺序排列。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matched_terms: Vec<String>,
+    /// `merge_context` 时 `content` 第一行的行号（最早一条前文的起始行）；`start_line`/`end_line` 仍指命中条目本身。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_start_line: Option<usize>,
+    /// 相关度：`10 × 命中的不同 must/any 查询词数 + must/any 匹配位置总数`，用于 `sort_by: relevance`。
+    #[serde(default)]
+    pub score: usize,
+}
+
+/// 命中前后的相邻日志条目。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextEntry {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub content: String,
+}
+
+impl From<&LogEntry> for ContextEntry {
+    fn from(entry: &LogEntry) -> Self {
+        Self {
+            start_line: entry.start_line,
+            end_line: entry.end_line,
+            content: entry.content.clone(),
+        }
+    }
+}
+
+/// 文件尾随读取位置：已消费的字节偏移及已读取的行数。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TailPosition {
+    pub offset: u64,
+    pub line: usize,
+}
+
+/// 推断出的多行日志起始行正则。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternProposal {
+    /// 建议的 log_start_pattern（已锚定行首）。
+    pub pattern: String,
+    /// 识别出的行首形态，如 "iso8601"、"syslog"。
+    pub kind: String,
+    /// 形态为时间戳时，建议的 timestamp_regex。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_regex: Option<String>,
+    /// 0~1，命中行与形似续行的行占样本非空行的比例。
+    pub confidence: f64,
+    pub matched_lines: usize,
+    pub sampled_lines: usize,
+    /// 第一条命中的样本行。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub example: Option<String>,
+}
+
+/// `test_regex` 的结果：正则能否编译，以及在样本文本上的匹配。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RegexTestResult {
+    pub valid: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 语法错误在模式中的位置。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_position: Option<RegexErrorPosition>,
+    pub matches: Vec<RegexSampleMatch>,
+    /// 匹配数超过返回上限，后续匹配未列出。
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegexErrorPosition {
+    /// 字节偏移（从 0 起）。
+    pub offset: usize,
+    /// 行号与列号（从 1 起，列按字符计）。
+    pub line: usize,
+    pub column: usize,
+}
+
+/// 样本中的一处匹配。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegexSampleMatch {
+    /// 匹配所在的样本行（从 1 起）。
+    pub line: usize,
+    pub offset: usize,
+    pub text: String,
+    /// 各捕获组（第 1 组起），未参与匹配的组为 `null`。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub captures: Vec<Option<String>>,
+    /// 命名捕获组。
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub named: HashMap<String, String>,
+}
+
+/// 文件为何被纳入/未被纳入扫描的解释结果。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileExplanation {
+    pub path: PathBuf,
+    pub exists: bool,
+    pub is_file: bool,
+    /// 是否出现在全局 log_file_paths 中。
+    pub in_log_file_paths: bool,
+    /// 是否位于 scan_config.root_path 之下。
+    pub under_root: bool,
+    /// 命中的包含模式（未配置时为默认模式）。
+    pub matched_include_globs: Vec<String>,
+    /// 命中的排除模式。
+    pub matched_exclude_globs: Vec<String>,
+    /// 扩展名是否属于默认识别的日志类型（.log / .gz）。
+    pub extension_recognized: bool,
+    /// 最终是否会被扫描。
+    pub included: bool,
+    pub reasons: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -140,4 +567,305 @@ pub struct SearchResponse {
     pub files_scanned: usize,
     pub timed_out: bool,
     pub failed_files: Vec<(PathBuf, String)>,
+    /// 超出 `search.max_failed_files_reported` 而未列入 `failed_files` 的失败文件数。
+    #[serde(default)]
+    pub failed_files_truncated_count: usize,
+    /// 启用抽样时实际被抽中的文件数。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files_sampled: Option<usize>,
+    /// 启用抽样时使用的随机种子，可回传以复现同一样本。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_seed: Option<u64>,
+    /// 每个 must/any 查询词出现在多少条命中中（分页前统计）。
+    #[serde(default)]
+    pub per_query_counts: HashMap<String, usize>,
+    /// `group_by_file` 时本页命中按文件分组，组按首次出现的顺序排列。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grouped_hits: Option<Vec<FileHits>>,
+    /// `template` 时全部命中（分页前）按模板分组的结果，按条数从多到少排列，
+    /// 最多 `MAX_TEMPLATE_GROUPS` 组。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub templates: Option<Vec<DiffGroup>>,
+    /// 请求给出 `session_id` 且还有后续结果时返回的游标，作为下一次请求的 `cursor` 传回即可取下一页。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// `profile` 时的各阶段耗时。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timing: Option<TimingBreakdown>,
+    /// 命中条目数 / 扫描的物理行数。
+    #[serde(default)]
+    pub match_ratio: f64,
+    /// `match_ratio` 超过 `search.too_broad_ratio`：查询词可能过于宽泛，应收紧条件。
+    #[serde(default)]
+    pub too_broad: bool,
+    /// 累计读取量达到 `search.max_total_read_bytes`，后续内容未被搜索。
+    #[serde(default)]
+    pub read_budget_exceeded: bool,
+    /// 实际生效的单文件超时（毫秒），已按 `search.min_timeout_ms`/`max_timeout_ms` 收紧；`null` 表示不限时。
+    #[serde(default)]
+    pub effective_timeout_ms: Option<u64>,
+    /// 所有文件任务累计读取的字节数（gzip 按解压后计）；读取失败的尝试（含被重试的）不计入。
+    #[serde(default)]
+    pub bytes_scanned: u64,
+    /// 所有文件任务累计扫描的物理行数，即 `match_ratio` 的分母；读取失败的尝试不计入。
+    #[serde(default)]
+    pub lines_scanned: usize,
+    /// `snapshot_sizes` 时本次使用的文件大小快照，翻页时作为 `snapshot_id` 传回。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_id: Option<String>,
+}
+
+/// 各阶段耗时（毫秒）。`scan_ms` 为文件扫描的墙钟时间；其余三项为所有文件任务之和，
+/// 并发搜索时可能超过 `execution_time_ms`。`read_ms` 含等待 IO 的时间，`parse_ms` 不含读取。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimingBreakdown {
+    pub scan_ms: u64,
+    pub read_ms: u64,
+    pub parse_ms: u64,
+    pub match_ms: u64,
+}
+
+/// `fields` 可选择的 SearchResponse 顶层字段。
+pub const SEARCH_RESPONSE_FIELDS: &[&str] = &[
+    "total_hits",
+    "page",
+    "page_size",
+    "total_pages",
+    "hits",
+    "execution_time_ms",
+    "files_scanned",
+    "timed_out",
+    "failed_files",
+    "failed_files_truncated_count",
+    "files_sampled",
+    "sample_seed",
+    "per_query_counts",
+    "grouped_hits",
+    "templates",
+    "next_cursor",
+    "timing",
+    "match_ratio",
+    "too_broad",
+    "read_budget_exceeded",
+    "effective_timeout_ms",
+    "bytes_scanned",
+    "lines_scanned",
+    "snapshot_id",
+];
+
+impl SearchResponse {
+    /// 本页的全部命中：`group_by_file` 时按组展开。
+    pub fn page_hits(&self) -> Vec<&HitResult> {
+        match &self.grouped_hits {
+            Some(groups) => groups.iter().flat_map(|g| &g.hits).collect(),
+            None => self.hits.iter().collect(),
+        }
+    }
+
+    /// 序列化为 JSON；`fields` 为 `Some` 时只保留其中列出的顶层字段。
+    pub fn to_json(&self, fields: Option<&[String]>) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let (Some(fields), serde_json::Value::Object(map)) = (fields, &mut value) {
+            map.retain(|k, _| fields.iter().any(|f| f == k));
+        }
+        value
+    }
+}
+
+/// 同一文件的一组命中。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileHits {
+    pub file_path: PathBuf,
+    pub hits: Vec<HitResult>,
+}
+
+/// 按文件分组，保持组的首次出现顺序及组内原有顺序。
+pub fn group_hits_by_file(hits: Vec<HitResult>) -> Vec<FileHits> {
+    let mut groups: Vec<FileHits> = Vec::new();
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
+    for hit in hits {
+        let i = *index.entry(hit.file_path.clone()).or_insert_with(|| {
+            groups.push(FileHits {
+                file_path: hit.file_path.clone(),
+                hits: Vec::new(),
+            });
+            groups.len() - 1
+        });
+        groups[i].hits.push(hit);
+    }
+    groups
+}
+
+/// 两次搜索按归一化内容做差集的结果。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchDiff {
+    pub baseline_total_hits: usize,
+    pub current_total_hits: usize,
+    /// 只出现在 current 中的内容（如"今天新出现的错误"），按条数从多到少排列。
+    pub only_in_current: Vec<DiffGroup>,
+    /// 只出现在 baseline 中的内容（已消失的错误）。
+    pub only_in_baseline: Vec<DiffGroup>,
+    /// 两边都出现的归一化内容种数。
+    pub common: usize,
+    /// 任一侧搜索超时或因 `max_hits` 截断，差集可能不完整。
+    pub truncated: bool,
+}
+
+/// `template` 时最多返回的模板组数。
+pub const MAX_TEMPLATE_GROUPS: usize = 100;
+
+/// 归一化内容相同的一组命中。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffGroup {
+    /// 数字、十六进制串替换为 `#` 并压缩空白后的内容。
+    pub normalized: String,
+    pub count: usize,
+    /// 该组中的第一条命中。
+    pub example: HitResult,
+}
+
+/// `error_summary` 的结果：时间窗口内错误条目的总数、高频消息、首末次出现时间与按小时分布。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorSummary {
+    pub total_errors: usize,
+    /// 按归一化内容分组的错误，按条数从多到少排列（截断到请求的 `limit`）。
+    pub top_messages: Vec<DiffGroup>,
+    /// 归一化后不同错误的种数（截断前）。
+    pub distinct_messages: usize,
+    /// 最早、最晚一条错误的时间戳；需要 `timestamp_regex` 才能提取。
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+    /// 按小时（输出时区的整点）统计的错误数，按时间升序。
+    pub hourly: Vec<HourCount>,
+    /// 未能提取时间戳、未计入 `hourly` 的错误数。
+    pub without_timestamp: usize,
+    /// 搜索超时或读取量达到 `max_total_read_bytes`，统计可能不完整。
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HourCount {
+    /// 该小时的起点（RFC 3339）。
+    pub hour: String,
+    pub count: usize,
+}
+
+/// `estimate_matches` 的结果：由随机抽样文件的命中数外推出的全量命中估计。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MatchEstimate {
+    /// 扫描范围内的文件总数。
+    pub files_total: usize,
+    /// 成功搜索并计入估计的抽样文件数（不含 `failed_files`）。
+    pub files_sampled: usize,
+    /// 抽样随机种子，可回传以复现同一样本。
+    pub sample_seed: u64,
+    /// 抽样文件中实际找到的命中条目数。
+    pub sampled_hits: usize,
+    /// 外推的全量命中条目数。
+    pub estimated_total: u64,
+    /// 约 95% 置信区间（正态近似，含有限总体修正）；下限不低于 `sampled_hits`。
+    /// 只有 1 个样本文件时无法估计方差，区间退化为 `estimated_total`。
+    pub low: u64,
+    pub high: u64,
+    /// 所有文件都被完整搜索，`estimated_total` 即精确值。
+    pub exact: bool,
+    /// 因 `sample_bytes` 只读了开头、但无法得知解压后大小而未按比例放大的 gzip 文件数；
+    /// 这些文件只按已读部分计入，估计值对它们偏低。
+    pub gzip_unscaled: usize,
+    /// 搜索失败或超时、不计入样本的文件。
+    pub failed_files: Vec<(PathBuf, String)>,
+}
+
+/// `prepare_search` 的结果：之后用 `handle` 调用 `fetch_page` 取页。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreparedSearchInfo {
+    pub handle: String,
+    pub session_id: String,
+    /// 扫描得到、将被搜索的文件数。
+    pub files: usize,
+    /// 扫描时无法访问的文件或目录数。
+    pub scan_errors: usize,
+}
+
+/// `distinct_values` 的统计结果。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DistinctValues {
+    /// 各取值及出现次数，按次数从多到少排列（截断到请求的 `limit`）。
+    pub values: Vec<ValueCount>,
+    /// 不同取值的总数（截断前）。
+    pub distinct: usize,
+    /// 捕获组有匹配的行数。
+    pub matched_lines: usize,
+    pub files_scanned: usize,
+    pub failed_files: Vec<(PathBuf, String)>,
+    /// 不同取值超过上限（之后出现的新值未计入），或读取量达到 `max_total_read_bytes` 而提前停止。
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_request_schema_tracks_fields() {
+        let schema = json_schema::<SearchRequest>();
+        let props = schema["properties"].as_object().unwrap();
+        assert!(props.contains_key("field_query"));
+        assert!(props.contains_key("refine_mode"));
+        assert_eq!(props["page"]["default"], 1);
+        let required: Vec<&str> = schema["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|v| v.as_str())
+            .collect();
+        assert_eq!(required, vec!["scan_config", "logical_query"]);
+        // 查询项既可以是字符串也可以是对象。
+        let must_items = &props["logical_query"]["properties"]["must"]["items"]["anyOf"];
+        assert_eq!(must_items[0]["type"], "string");
+    }
+
+    #[test]
+    fn response_fields_select_top_level_keys() {
+        let resp = SearchResponse {
+            total_hits: 1,
+            page: 1,
+            page_size: 10,
+            total_pages: 1,
+            hits: Vec::new(),
+            execution_time_ms: 3,
+            files_scanned: 1,
+            timed_out: false,
+            failed_files: vec![(PathBuf::from("/x.log"), "denied".into())],
+            failed_files_truncated_count: 0,
+            files_sampled: Some(1),
+            sample_seed: Some(7),
+            per_query_counts: HashMap::new(),
+            grouped_hits: Some(Vec::new()),
+            templates: Some(Vec::new()),
+            next_cursor: Some("c:1".into()),
+            timing: Some(TimingBreakdown::default()),
+            match_ratio: 0.1,
+            too_broad: false,
+            read_budget_exceeded: false,
+            effective_timeout_ms: Some(1000),
+            bytes_scanned: 128,
+            lines_scanned: 10,
+            snapshot_id: Some("s".into()),
+        };
+        let full = resp.to_json(None);
+        let keys: Vec<&str> = full.as_object().unwrap().keys().map(String::as_str).collect();
+        assert_eq!(keys.len(), SEARCH_RESPONSE_FIELDS.len());
+        assert!(keys.iter().all(|k| SEARCH_RESPONSE_FIELDS.contains(k)));
+
+        let fields = vec!["total_hits".to_string(), "hits".to_string()];
+        let trimmed = resp.to_json(Some(&fields));
+        assert_eq!(trimmed, serde_json::json!({ "total_hits": 1, "hits": [] }));
+    }
 }
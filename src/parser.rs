@@ -1,11 +1,14 @@
 use std::path::PathBuf;
 
 use async_stream::try_stream;
-use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
 use regex::Regex;
 
 use crate::error::Result;
-use crate::model::LogEntry;
+use crate::model::{LogEntry, LogFormat};
 
 /// 日志解析器：根据 log_start_pattern 决定单行解析还是多行聚合。
 #[derive(Clone, Default)]
@@ -30,6 +33,38 @@ impl LogParser {
         Ok(stream)
     }
 
+    /// 按日志格式解析：`json` 格式下首个非空行以 `[` 开头时视为 JSON 数组导出，
+    /// 逐个元素产出条目；否则（JSONL 等）与 [`Self::parse`] 相同。
+    pub async fn parse_with_format(
+        &self,
+        file_path: PathBuf,
+        mut lines: BoxStream<'static, Result<String>>,
+        log_start_pattern: Option<Regex>,
+        format: LogFormat,
+    ) -> Result<BoxStream<'static, Result<LogEntry>>> {
+        if format != LogFormat::Json {
+            return self.parse(file_path, lines, log_start_pattern).await;
+        }
+        let mut head = Vec::new();
+        while let Some(line) = lines.try_next().await? {
+            let blank = line.trim().is_empty();
+            head.push(line);
+            if !blank {
+                break;
+            }
+        }
+        let is_array = head
+            .last()
+            .is_some_and(|l| l.trim_start().starts_with('['));
+        let lines: BoxStream<'static, Result<String>> =
+            Box::pin(stream::iter(head.into_iter().map(Ok)).chain(lines));
+        if is_array {
+            Ok(parse_json_array(file_path, lines))
+        } else {
+            self.parse(file_path, lines, log_start_pattern).await
+        }
+    }
+
     async fn parse_single_line(
         &self,
         file_path: PathBuf,
@@ -68,6 +103,7 @@ impl LogParser {
                 let is_start = start_re.is_match(&line);
                 if is_start {
                     if !buf.is_empty() {
+                        // 每行保留了原始分隔符，直接拼接即可还原条目内容。
                         let content = buf.join("");
                         yield LogEntry {
                             file_path: file_path.clone(),
@@ -104,6 +140,86 @@ impl LogParser {
     }
 }
 
+/// 流式拆分顶层 JSON 数组的元素，每个元素（原文，去掉首尾空白）作为一个条目，
+/// 行号为元素起止所在行。只跟踪括号深度与字符串状态，不在内存中构建整个数组；
+/// 文件截断时最后一个不完整元素照常产出。
+fn parse_json_array(
+    file_path: PathBuf,
+    mut lines: BoxStream<'static, Result<String>>,
+) -> BoxStream<'static, Result<LogEntry>> {
+    Box::pin(try_stream! {
+        let mut line_no: usize = 0;
+        let mut depth: usize = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut buf = String::new();
+        let mut start_line: usize = 0;
+        let mut end_line: usize = 0;
+
+        while let Some(line) = lines.try_next().await? {
+            line_no += 1;
+            for c in line.chars() {
+                if in_string {
+                    buf.push(c);
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' {
+                        escaped = true;
+                    } else if c == '"' {
+                        in_string = false;
+                    }
+                    end_line = line_no;
+                    continue;
+                }
+                let element_done = match c {
+                    '[' if depth == 0 => {
+                        depth = 1;
+                        false
+                    }
+                    ']' if depth == 1 => {
+                        depth = 0;
+                        true
+                    }
+                    ',' if depth == 1 => true,
+                    c if c.is_whitespace() && buf.is_empty() => false,
+                    c => {
+                        match c {
+                            '[' | '{' => depth += 1,
+                            ']' | '}' => depth = depth.saturating_sub(1),
+                            '"' => in_string = true,
+                            _ => {}
+                        }
+                        if buf.is_empty() {
+                            start_line = line_no;
+                        }
+                        buf.push(c);
+                        end_line = line_no;
+                        false
+                    }
+                };
+                if element_done && !buf.trim().is_empty() {
+                    yield LogEntry {
+                        file_path: file_path.clone(),
+                        start_line,
+                        end_line,
+                        content: buf.trim_end().to_string(),
+                    };
+                    buf.clear();
+                }
+            }
+        }
+
+        if !buf.trim().is_empty() {
+            yield LogEntry {
+                file_path: file_path.clone(),
+                start_line,
+                end_line,
+                content: buf.trim_end().to_string(),
+            };
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
@@ -1,10 +1,14 @@
 use std::borrow::Cow;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
 use regex::{Regex, RegexBuilder};
+use unicode_normalization::UnicodeNormalization;
 
 use crate::error::Result;
-use crate::model::{LogicalQuery, MatchPosition, SearchQuery};
+use std::collections::HashMap;
+
+use crate::model::{CompareOp, LogFormat, LogicalQuery, MatchPosition, NumericQuery, SearchQuery};
 
 /// 用于高效应用时间过滤器的内部结构
 #[derive(Debug, Clone)]
@@ -12,31 +16,163 @@ pub struct ParsedTimeFilter {
     pub start: Option<DateTime<Utc>>,
     pub end: Option<DateTime<Utc>>,
     pub regex: Option<Regex>,
+    /// 额外的时间窗口（闭区间）；非空时时间须落在任一窗口内。
+    pub windows: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+/// 一次搜索内各条目共用的匹配上下文：预编译的整词正则（避免逐条目重复编译）与物理行分隔符。
+#[derive(Debug, Clone)]
+pub struct MatchContext {
+    /// 区分大小写的整词正则，按词索引。
+    words: HashMap<String, Regex>,
+    /// 不区分大小写的整词正则，按词索引。
+    words_ignore_case: HashMap<String, Regex>,
+    /// 条目内物理行的分隔符，`per_physical_line` 与 `whole_line` 按它切分，默认 `\n`。
+    line_delimiter: char,
+}
+
+impl Default for MatchContext {
+    fn default() -> Self {
+        Self {
+            words: HashMap::new(),
+            words_ignore_case: HashMap::new(),
+            line_delimiter: '\n',
+        }
+    }
+}
+
+impl MatchContext {
+    /// 为 `terms` 中所有纯文本 `whole_word` 条件编译正则。
+    pub fn new<'a>(terms: impl IntoIterator<Item = &'a SearchQuery>) -> Self {
+        let mut ctx = Self::default();
+        for q in terms {
+            let Some(word) = q.query.as_ref().filter(|_| q.whole_word && !q.regex) else {
+                continue;
+            };
+            let map = if q.case_sensitive { &mut ctx.words } else { &mut ctx.words_ignore_case };
+            if !map.contains_key(word) {
+                if let Some(re) = whole_word_regex(word, q.case_sensitive) {
+                    map.insert(word.clone(), re);
+                }
+            }
+        }
+        ctx
+    }
+
+    pub fn with_line_delimiter(mut self, delimiter: u8) -> Self {
+        self.line_delimiter = delimiter as char;
+        self
+    }
+
+    /// 预编译的整词正则；不在上下文中时（未经搜索计划的调用）现场编译。
+    fn word_regex(&self, word: &str, case_sensitive: bool) -> Option<Cow<'_, Regex>> {
+        let map = if case_sensitive { &self.words } else { &self.words_ignore_case };
+        match map.get(word) {
+            Some(re) => Some(Cow::Borrowed(re)),
+            None => whole_word_regex(word, case_sensitive).map(Cow::Owned),
+        }
+    }
 }
 
 /// 查询处理器：文本/正则匹配、逻辑组合和时间过滤。
 #[derive(Clone, Default)]
-pub struct QueryProcessor;
+pub struct QueryProcessor {
+    /// 正则模式的最大长度（字节），`None` 表示不限制。
+    max_regex_len: Option<usize>,
+}
 
 impl QueryProcessor {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    pub fn with_max_regex_len(mut self, max_regex_len: Option<usize>) -> Self {
+        self.max_regex_len = max_regex_len;
+        self
     }
 
-    pub fn matches(&self, text: &str, query: &LogicalQuery) -> bool {
-        if !query.must.iter().all(|q| self.single_match(text, q)) {
+    pub fn matches(&self, text: &str, query: &LogicalQuery, ctx: &MatchContext) -> bool {
+        if !query.must.iter().all(|q| self.single_match(text, q, ctx)) {
             return false;
         }
-        if !query.any.is_empty() && !query.any.iter().any(|q| self.single_match(text, q)) {
-            return false;
+        if !query.any.is_empty() && query.any_min_matches > 0 {
+            let needed = query.any_min_matches;
+            let mut found = 0;
+            for q in &query.any {
+                if self.single_match(text, q, ctx) {
+                    found += 1;
+                    if found >= needed {
+                        break;
+                    }
+                }
+            }
+            if found < needed {
+                return false;
+            }
         }
-        if query.none.iter().any(|q| self.single_match(text, q)) {
+        if query.none.iter().any(|q| self.single_match(text, q, ctx)) {
             return false;
         }
         true
     }
 
-    pub fn find_positions(&self, text: &str, query: &SearchQuery) -> Vec<MatchPosition> {
+    pub fn find_positions(&self, text: &str, query: &SearchQuery, ctx: &MatchContext) -> Vec<MatchPosition> {
+        if query.whole_line {
+            return self.whole_line_positions(text, query, ctx);
+        }
+        if query.per_physical_line {
+            // 逐行查找再换算回条目内偏移，排除跨行的正则匹配
+            let mut line_start = 0usize;
+            let mut positions = Vec::new();
+            for line in text.split_inclusive(ctx.line_delimiter) {
+                positions.extend(self.find_positions_in(line, query, ctx).into_iter().map(|p| MatchPosition {
+                    offset: line_start + p.offset,
+                    length: p.length,
+                }));
+                line_start += line.len();
+            }
+            return positions;
+        }
+        self.find_positions_in(text, query, ctx)
+    }
+
+    /// 整行匹配：逐个物理行去掉首尾空白后比较，命中位置覆盖去空白后的整行。
+    fn whole_line_positions(&self, text: &str, query: &SearchQuery, ctx: &MatchContext) -> Vec<MatchPosition> {
+        let Some(needle) = &query.query else {
+            return Vec::new();
+        };
+        let anchored = if query.regex {
+            match self.compile_regex(&format!("^(?:{needle})$"), query.case_sensitive) {
+                Ok(re) => Some(re),
+                Err(_) => return Vec::new(),
+            }
+        } else {
+            None
+        };
+        let lowered = needle.to_lowercase();
+        let mut positions = Vec::new();
+        let mut line_start = 0usize;
+        let delimiter = ctx.line_delimiter;
+        for segment in text.split_inclusive(delimiter) {
+            let line = segment.strip_suffix(delimiter).unwrap_or(segment);
+            let trimmed = line.trim();
+            let matched = match &anchored {
+                Some(re) => re.is_match(trimmed),
+                None if query.case_sensitive => trimmed == needle,
+                None => trimmed.to_lowercase() == lowered,
+            };
+            if matched {
+                positions.push(MatchPosition {
+                    offset: line_start + (line.len() - line.trim_start().len()),
+                    length: trimmed.len(),
+                });
+            }
+            line_start += segment.len();
+        }
+        positions
+    }
+
+    fn find_positions_in(&self, text: &str, query: &SearchQuery, ctx: &MatchContext) -> Vec<MatchPosition> {
         if query.query.is_none() {
             return Vec::new();
         }
@@ -53,8 +189,21 @@ impl QueryProcessor {
             }
             return Vec::new();
         }
+        if query.whole_word {
+            return ctx
+                .word_regex(needle, query.case_sensitive)
+                .map(|re| {
+                    re.find_iter(text)
+                        .map(|m| MatchPosition {
+                            offset: m.start(),
+                            length: m.end() - m.start(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+        }
 
-        // 纯文本匹配（可选全字匹配）
+        // 纯文本匹配
         let haystack = if query.case_sensitive {
             Cow::Borrowed(text)
         } else {
@@ -66,28 +215,6 @@ impl QueryProcessor {
             Cow::Owned(needle.to_lowercase())
         };
 
-        if query.whole_word {
-            let target = keyword.into_owned();
-            let bytes = haystack.as_bytes();
-            let needle = target.as_bytes();
-            let mut positions = Vec::new();
-            let mut idx = 0usize;
-            while idx + needle.len() <= bytes.len() {
-                if &bytes[idx..idx + needle.len()] == needle {
-                    let before_ok = idx == 0 || !is_word(bytes[idx - 1]);
-                    let after_ok = idx + needle.len() == bytes.len() || !is_word(bytes[idx + needle.len()]);
-                    if before_ok && after_ok {
-                        positions.push(MatchPosition {
-                            offset: idx,
-                            length: needle.len(),
-                        });
-                    }
-                }
-                idx += 1;
-            }
-            return positions;
-        }
-
         let mut positions = Vec::new();
         let mut start = 0usize;
         while let Some(pos) = haystack[start..].find(&*keyword) {
@@ -102,6 +229,17 @@ impl QueryProcessor {
     }
 
     pub fn compile_regex(&self, pattern: &str, case_sensitive: bool) -> Result<Regex> {
+        if let Some(max) = self.max_regex_len {
+            if pattern.len() > max {
+                return Err(crate::error::LogSearchError::RegexError {
+                    pattern: truncate_pattern(pattern),
+                    reason: format!(
+                        "pattern is {} bytes, exceeding search.max_regex_len ({max})",
+                        pattern.len()
+                    ),
+                });
+            }
+        }
         let mut builder = RegexBuilder::new(pattern);
         builder.case_insensitive(!case_sensitive);
         builder.build().map_err(|e| crate::error::LogSearchError::RegexError {
@@ -111,49 +249,58 @@ impl QueryProcessor {
     }
 
     pub fn apply_time_filter(&self, text: &str, filter: &Option<ParsedTimeFilter>) -> bool {
-        let Some(filter) = filter else { return true; };
-        let Some(re) = &filter.regex else { return true; };
-        
-        let ts_str = match re.find(text) {
-            Some(m) => &text[m.start()..m.end()],
-            None => return true, 
-        };
-        
-        // 尝试多种格式解析
-        // 优先 RFC3339, 其次常见的日志格式
-        let ts = if let Ok(dt) = DateTime::parse_from_rfc3339(ts_str) {
-            dt.with_timezone(&Utc)
-        } else if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(ts_str, "%Y-%m-%d %H:%M:%S") {
-            // 假设是本地时间，或者 UTC
-             DateTime::from_utc(dt, Utc)
-        } else if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(ts_str, "%Y-%m-%d %H:%M:%S%.3f") {
-             DateTime::from_utc(dt, Utc)
-        } else {
-             // 尝试把 T 换成空格
-             let normalized = ts_str.replace('T', " ");
-             if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(&normalized, "%Y-%m-%d %H:%M:%S") {
-                 DateTime::from_utc(dt, Utc)
-             } else if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(&normalized, "%Y-%m-%d %H:%M:%S%.3f") {
-                 DateTime::from_utc(dt, Utc)
-             } else {
-                 return true; // 解析失败，默认不过滤
-             }
+        // 没有时间戳或解析失败时默认不过滤
+        let ts = self.extract_timestamp(text, filter);
+        in_time_range(ts, filter)
+    }
+
+    /// 用过滤器中的时间戳正则提取并解析条目的时间。
+    pub fn extract_timestamp(
+        &self,
+        text: &str,
+        filter: &Option<ParsedTimeFilter>,
+    ) -> Option<DateTime<Utc>> {
+        let re = filter.as_ref()?.regex.as_ref()?;
+        let m = re.find(text)?;
+        parse_log_timestamp(m.as_str())
+    }
+
+    /// 按字段过滤条目：每个字段都必须存在且其值满足对应查询。
+    /// 纯文本格式下没有字段，只要给出了字段条件就不匹配。
+    pub fn matches_fields(
+        &self,
+        text: &str,
+        format: LogFormat,
+        field_query: &HashMap<String, SearchQuery>,
+        ctx: &MatchContext,
+    ) -> bool {
+        if field_query.is_empty() {
+            return true;
+        }
+        let fields = match format {
+            LogFormat::Text => return false,
+            LogFormat::Logfmt => parse_logfmt(text),
+            LogFormat::Json => parse_json_fields(text),
+            LogFormat::Apache => parse_access_log(text),
         };
+        field_query.iter().all(|(key, q)| {
+            fields
+                .iter()
+                .any(|(k, v)| k == key && self.single_match(v, q, ctx))
+        })
+    }
 
-        if let Some(start) = filter.start {
-            if ts < start {
-                return false;
-            }
+    fn single_match(&self, text: &str, query: &SearchQuery, ctx: &MatchContext) -> bool {
+        if query.whole_line && query.query.is_some() {
+            return !self.whole_line_positions(text, query, ctx).is_empty();
         }
-        if let Some(end) = filter.end {
-            if ts > end {
-                return false;
-            }
+        if query.per_physical_line && query.query.is_some() {
+            return text.split(ctx.line_delimiter).any(|line| self.match_text(line, query, ctx));
         }
-        true
+        self.match_text(text, query, ctx)
     }
 
-    fn single_match(&self, text: &str, query: &SearchQuery) -> bool {
+    fn match_text(&self, text: &str, query: &SearchQuery, ctx: &MatchContext) -> bool {
         let Some(pattern) = &query.query else {
             return true;
         };
@@ -166,13 +313,7 @@ impl QueryProcessor {
         }
 
         if query.whole_word {
-            let escaped = regex::escape(pattern);
-            return RegexBuilder::new(&format!(r"\b{escaped}\b"))
-                .case_insensitive(!query.case_sensitive)
-                .build()
-                .ok()
-                .map(|re| re.is_match(text))
-                .unwrap_or(false);
+            return ctx.word_regex(pattern, query.case_sensitive).is_some_and(|re| re.is_match(text));
         }
 
         if query.case_sensitive {
@@ -183,9 +324,402 @@ impl QueryProcessor {
     }
 }
 
-fn is_word(byte: u8) -> bool {
-    let c = byte as char;
-    c.is_alphanumeric() || c == '_'
+/// 错误信息中只保留模式开头，避免回显超长输入。
+fn truncate_pattern(pattern: &str) -> String {
+    const SHOWN: usize = 64;
+    match pattern.char_indices().nth(SHOWN) {
+        Some((idx, _)) => format!("{}…", &pattern[..idx]),
+        None => pattern.to_string(),
+    }
+}
+
+/// 默认级别顺序，从低到高；同级别别名以 `|` 分隔。
+pub const DEFAULT_LEVEL_ORDER: &[&str] = &[
+    "TRACE",
+    "DEBUG",
+    "INFO",
+    "WARN|WARNING",
+    "ERROR|ERR",
+    "FATAL|CRITICAL",
+];
+
+/// 最低日志级别过滤：提取条目中的级别并与阈值比较。
+#[derive(Debug, Clone)]
+pub struct LevelFilter {
+    regex: Regex,
+    ranks: HashMap<String, usize>,
+    min_rank: usize,
+}
+
+impl LevelFilter {
+    pub fn new(order: &[String], level_regex: Option<&str>, min_level: &str) -> Result<Self> {
+        let mut ranks = HashMap::new();
+        for (rank, names) in order.iter().enumerate() {
+            for name in names.split('|').map(str::trim).filter(|n| !n.is_empty()) {
+                ranks.insert(name.to_ascii_uppercase(), rank);
+            }
+        }
+        let min_rank = *ranks.get(&min_level.to_ascii_uppercase()).ok_or_else(|| {
+            crate::error::LogSearchError::InvalidRequest(format!(
+                "unknown min_level '{min_level}', expected one of: {}",
+                order.join(", ")
+            ))
+        })?;
+
+        let pattern = match level_regex {
+            Some(p) => p.to_string(),
+            None => {
+                let mut names: Vec<&String> = ranks.keys().collect();
+                // 长名称优先，避免 WARN 抢先匹配 WARNING
+                names.sort_by_key(|n| std::cmp::Reverse(n.len()));
+                let alternation: Vec<String> = names.iter().map(|n| regex::escape(n)).collect();
+                format!(r"(?i)\b({})\b", alternation.join("|"))
+            }
+        };
+        let regex = Regex::new(&pattern).map_err(|e| crate::error::LogSearchError::RegexError {
+            pattern: pattern.clone(),
+            reason: e.to_string(),
+        })?;
+        Ok(Self {
+            regex,
+            ranks,
+            min_rank,
+        })
+    }
+
+    /// 取条目中第一个识别出的级别；没有级别或级别未知的条目保留。
+    pub fn accepts(&self, text: &str) -> bool {
+        let Some(caps) = self.regex.captures(text) else {
+            return true;
+        };
+        let level = caps.get(1).or_else(|| caps.get(0)).map(|m| m.as_str());
+        match level.and_then(|l| self.ranks.get(&l.to_ascii_uppercase())) {
+            Some(rank) => *rank >= self.min_rank,
+            None => true,
+        }
+    }
+}
+
+/// 数值阈值过滤：提取条目中的数值并与阈值比较。
+#[derive(Debug, Clone)]
+pub struct NumericFilter {
+    regex: Regex,
+    op: CompareOp,
+    value: f64,
+}
+
+impl NumericFilter {
+    pub fn new(processor: &QueryProcessor, query: &NumericQuery) -> Result<Self> {
+        Ok(Self {
+            regex: processor.compile_regex(&query.field_regex, true)?,
+            op: query.op,
+            value: query.value,
+        })
+    }
+
+    /// 条目中任一处提取出的数值满足比较即接受；提取不到数值的条目不接受。
+    pub fn accepts(&self, text: &str) -> bool {
+        self.regex.captures_iter(text).any(|caps| {
+            caps.get(1)
+                .or_else(|| caps.get(0))
+                .and_then(|m| m.as_str().trim().parse::<f64>().ok())
+                .is_some_and(|n| self.op.holds(n, self.value))
+        })
+    }
+}
+
+/// 判断时间是否落在过滤范围内；没有时间的条目视为在范围内。
+pub fn in_time_range(ts: Option<DateTime<Utc>>, filter: &Option<ParsedTimeFilter>) -> bool {
+    let (Some(ts), Some(filter)) = (ts, filter) else {
+        return true;
+    };
+    if let Some(start) = filter.start {
+        if ts < start {
+            return false;
+        }
+    }
+    if let Some(end) = filter.end {
+        if ts > end {
+            return false;
+        }
+    }
+    filter.windows.is_empty() || filter.windows.iter().any(|(start, end)| *start <= ts && ts <= *end)
+}
+
+/// 请求给出了时间范围却没有任何时间戳正则时使用：ISO 8601 或 syslog（`Jan  5 10:00:00`）。
+pub const DEFAULT_TIMESTAMP_REGEX: &str =
+    r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:\d{2})?|\b[A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2}";
+
+/// 尝试多种格式解析日志中的时间戳：优先 RFC3339，其次常见的日志格式（视为 UTC），
+/// 最后是不带年份的 syslog 格式。
+pub fn parse_log_timestamp(ts_str: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(ts_str) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    // 尝试把 T 换成空格
+    let normalized = ts_str.replace('T', " ");
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M:%S%.3f"]
+        .iter()
+        .find_map(|fmt| chrono::NaiveDateTime::parse_from_str(&normalized, fmt).ok())
+        .map(|dt| DateTime::from_naive_utc_and_offset(dt, Utc))
+        .or_else(|| parse_syslog_timestamp(ts_str, Utc::now()))
+}
+
+/// 解析 syslog 的 `%b %e %H:%M:%S`（如 `Jan  5 10:00:00`）。格式不含年份，取 `now` 所在年份；
+/// 结果比 `now` 晚一天以上时视为去年的记录（如一月读取十二月的日志）。
+fn parse_syslog_timestamp(ts_str: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    use chrono::Datelike;
+
+    // %e 的日期以空格补齐，统一压缩为单个空格后按 %d 解析
+    let compact = ts_str.split_whitespace().collect::<Vec<_>>().join(" ");
+    let parse = |year: i32| {
+        chrono::NaiveDateTime::parse_from_str(&format!("{year} {compact}"), "%Y %b %d %H:%M:%S")
+            .ok()
+            .map(|dt| DateTime::from_naive_utc_and_offset(dt, Utc))
+    };
+    // 2 月 29 日在当前年份不存在时也按去年解析
+    match parse(now.year()) {
+        Some(ts) if ts <= now + chrono::Duration::days(1) => Some(ts),
+        _ => parse(now.year() - 1),
+    }
+}
+
+/// 按输出时区格式化派生时间戳（RFC3339，带偏移）；未指定时区时输出 UTC。
+pub fn format_output_time(ts: DateTime<Utc>, tz: Option<&Tz>) -> String {
+    match tz {
+        Some(tz) => ts.with_timezone(tz).to_rfc3339_opts(SecondsFormat::Millis, false),
+        None => ts.to_rfc3339_opts(SecondsFormat::Millis, true),
+    }
+}
+
+/// 解析 IANA 时区名称，如 "Asia/Shanghai"。
+pub fn parse_output_tz(name: &str) -> Result<Tz> {
+    name.parse::<Tz>().map_err(|_| {
+        crate::error::LogSearchError::InvalidRequest(format!("unknown time zone: {name}"))
+    })
+}
+
+/// 解析 logfmt 键值对：`key=value`、`key="quoted value"`（支持 `\"` 转义），
+/// 无 `=` 的裸键值为空串。多行条目按所有行一并解析。
+pub fn parse_logfmt(text: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            if let Some(escaped) = chars.next() {
+                                value.push(escaped);
+                            }
+                        }
+                        '"' => break,
+                        _ => value.push(c),
+                    }
+                }
+            } else {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    value.push(c);
+                }
+            }
+        }
+        if !key.is_empty() {
+            pairs.push((key, value));
+        }
+    }
+    pairs
+}
+
+/// 把 JSON 对象展开为字段列表：嵌套对象以 `.` 连接键名，字符串取原值，
+/// 其余值（数字、布尔、数组、null）取其 JSON 文本。非对象或无法解析时没有字段。
+pub fn parse_json_fields(text: &str) -> Vec<(String, String)> {
+    fn flatten(prefix: &str, value: &serde_json::Value, out: &mut Vec<(String, String)>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (k, v) in map {
+                    let key = if prefix.is_empty() {
+                        k.clone()
+                    } else {
+                        format!("{prefix}.{k}")
+                    };
+                    flatten(&key, v, out);
+                }
+            }
+            serde_json::Value::String(s) => out.push((prefix.to_string(), s.clone())),
+            other => out.push((prefix.to_string(), other.to_string())),
+        }
+    }
+
+    let mut fields = Vec::new();
+    if let Ok(value @ serde_json::Value::Object(_)) = serde_json::from_str(text.trim()) {
+        flatten("", &value, &mut fields);
+    }
+    fields
+}
+
+/// 解析 common/combined 访问日志行：
+/// `%h %l %u [%t] "%r" %>s %b` 以及 combined 追加的 `"%{Referer}i" "%{User-agent}i"`。
+/// 请求行拆为 `method`、`path`、`protocol`（同时保留完整的 `request`），`bytes` 为 `-` 时记为 `0`。
+/// 不符合该格式的行没有字段。
+pub fn parse_access_log(text: &str) -> Vec<(String, String)> {
+    let mut rest = text.trim();
+    let mut tokens = Vec::new();
+    while !rest.is_empty() && tokens.len() < 9 {
+        let (token, tail) = match rest.as_bytes()[0] {
+            b'[' => match rest.find(']') {
+                Some(end) => (&rest[1..end], &rest[end + 1..]),
+                None => return Vec::new(),
+            },
+            b'"' => match closing_quote(&rest[1..]) {
+                Some(end) => (&rest[1..end + 1], &rest[end + 2..]),
+                None => return Vec::new(),
+            },
+            _ => match rest.find(' ') {
+                Some(end) => (&rest[..end], &rest[end..]),
+                None => (rest, ""),
+            },
+        };
+        tokens.push(token.replace("\\\"", "\""));
+        rest = tail.trim_start();
+    }
+    if tokens.len() < 7 || !tokens[5].bytes().all(|b| b.is_ascii_digit()) {
+        return Vec::new();
+    }
+
+    const NAMES: [&str; 9] = [
+        "remote_addr", "ident", "user", "time", "request", "status", "bytes", "referer", "user_agent",
+    ];
+    let mut fields: Vec<(String, String)> = NAMES
+        .iter()
+        .zip(&tokens)
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect();
+    if fields[6].1 == "-" {
+        fields[6].1 = "0".to_string();
+    }
+    let mut request = tokens[4].split(' ');
+    for name in ["method", "path", "protocol"] {
+        if let Some(part) = request.next() {
+            fields.push((name.to_string(), part.to_string()));
+        }
+    }
+    fields
+}
+
+/// 引号内容的结束位置（跳过 `\"` 转义）。
+fn closing_quote(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// 去掉 ANSI CSI 控制序列（`ESC [` 参数字节、中间字节与结束字节，如颜色码 `\x1b[31m`）。
+/// 没有控制序列时不复制。
+pub fn strip_ansi(text: &str) -> Cow<'_, str> {
+    if !text.contains('\x1b') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' || chars.peek() != Some(&'[') {
+            out.push(c);
+            continue;
+        }
+        chars.next();
+        // 参数与中间字节位于 0x20-0x3F，遇到 0x40-0x7E 的结束字节为止
+        for c in chars.by_ref() {
+            if !('\x20'..='\x3f').contains(&c) {
+                break;
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// NFC 规范化（组合字符与预组字符统一为预组形式）；已是 NFC 时不复制。
+pub fn nfc(text: &str) -> Cow<'_, str> {
+    if unicode_normalization::is_nfc(text) {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(text.nfc().collect())
+    }
+}
+
+/// 空白折叠后的文本副本：连续空白（含换行）折叠为单个空格，
+/// 并记录副本中每个字节对应的原文区间，用于把匹配位置映射回原文。
+pub struct NormalizedText {
+    pub text: String,
+    spans: Vec<(usize, usize)>,
+}
+
+impl NormalizedText {
+    pub fn new(original: &str) -> Self {
+        let mut text = String::with_capacity(original.len());
+        let mut spans = Vec::with_capacity(original.len());
+        let mut chars = original.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            if c.is_whitespace() {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, next)) = chars.peek() {
+                    if !next.is_whitespace() {
+                        break;
+                    }
+                    end = i + next.len_utf8();
+                    chars.next();
+                }
+                text.push(' ');
+                spans.push((start, end));
+            } else {
+                text.push(c);
+                for b in 0..c.len_utf8() {
+                    spans.push((start + b, start + b + 1));
+                }
+            }
+        }
+        Self { text, spans }
+    }
+
+    /// 把副本上的匹配位置映射为原文上的位置；折叠的空白按整段原文计入。
+    pub fn map_position(&self, pos: &MatchPosition) -> MatchPosition {
+        let text_end = self.spans.last().map(|s| s.1).unwrap_or(0);
+        if pos.length == 0 || pos.offset >= self.spans.len() {
+            let offset = self.spans.get(pos.offset).map(|s| s.0).unwrap_or(text_end);
+            return MatchPosition { offset, length: 0 };
+        }
+        let start = self.spans[pos.offset].0;
+        let last = (pos.offset + pos.length - 1).min(self.spans.len() - 1);
+        MatchPosition {
+            offset: start,
+            length: self.spans[last].1 - start,
+        }
+    }
+}
+
+/// 纯文本全字匹配所用的正则：转义后两端加 `\b`。词边界按 Unicode 单词字符判断，
+/// 因此汉字等 CJK 字符与字母一样算作单词的一部分。
+fn whole_word_regex(word: &str, case_sensitive: bool) -> Option<Regex> {
+    RegexBuilder::new(&format!(r"\b{}\b", regex::escape(word)))
+        .case_insensitive(!case_sensitive)
+        .build()
+        .ok()
 }
 
 #[cfg(test)]
@@ -199,6 +733,8 @@ mod tests {
             regex: false,
             case_sensitive: false,
             whole_word: false,
+            whole_line: false,
+            per_physical_line: false,
         }
     }
 
@@ -209,10 +745,277 @@ mod tests {
             must: vec![sq("error")],
             any: vec![sq("traffic"), sq("network")],
             none: vec![sq("fatal")],
+            any_min_matches: 1,
+        };
+        assert!(qp.matches("traffic error occurred", &query, &MatchContext::default()));
+        assert!(!qp.matches("info traffic ok", &query, &MatchContext::default())); // must not satisfied
+        assert!(!qp.matches("traffic fatal error", &query, &MatchContext::default())); // none matched
+    }
+
+    #[test]
+    fn json_fields_are_flattened() {
+        let fields = parse_json_fields(r#"{"level":"warn","n":3,"ctx":{"id":"a1","ok":true}}"#);
+        assert!(fields.contains(&("level".to_string(), "warn".to_string())));
+        assert!(fields.contains(&("n".to_string(), "3".to_string())));
+        assert!(fields.contains(&("ctx.ok".to_string(), "true".to_string())));
+        assert!(parse_json_fields("level=warn").is_empty());
+        assert!(parse_json_fields("[1, 2]").is_empty());
+    }
+
+    #[test]
+    fn logfmt_pairs_and_field_matching() {
+        let line = r#"ts=2024-01-01 level=error msg="db \"main\" down" dur=1.2s retry"#;
+        let pairs = parse_logfmt(line);
+        assert_eq!(pairs[1], ("level".to_string(), "error".to_string()));
+        assert_eq!(pairs[2], ("msg".to_string(), r#"db "main" down"#.to_string()));
+        assert_eq!(pairs[4], ("retry".to_string(), String::new()));
+
+        let qp = QueryProcessor::new();
+        let mut fields = HashMap::new();
+        fields.insert("level".to_string(), sq("error"));
+        assert!(qp.matches_fields(line, LogFormat::Logfmt, &fields, &MatchContext::default()));
+        assert!(!qp.matches_fields("level=info msg=error", LogFormat::Logfmt, &fields, &MatchContext::default()));
+        assert!(!qp.matches_fields(line, LogFormat::Text, &fields, &MatchContext::default()));
+
+        fields.insert(
+            "dur".to_string(),
+            SearchQuery {
+                query: Some(r"^\d+\.\d+s$".into()),
+                regex: true,
+                case_sensitive: false,
+                whole_word: false,
+                whole_line: false,
+                per_physical_line: false,
+            },
+        );
+        assert!(qp.matches_fields(line, LogFormat::Logfmt, &fields, &MatchContext::default()));
+    }
+
+    #[test]
+    fn access_log_fields_are_extracted() {
+        let combined = r#"203.0.113.7 - alice [10/Oct/2000:13:55:36 -0700] "GET /api/orders?id=3 HTTP/1.1" 503 - "https://example.com/" "curl/8.0 \"x\"""#;
+        let fields = parse_access_log(combined);
+        let get = |k: &str| fields.iter().find(|(n, _)| n == k).map(|(_, v)| v.as_str());
+        assert_eq!(get("remote_addr"), Some("203.0.113.7"));
+        assert_eq!(get("user"), Some("alice"));
+        assert_eq!(get("time"), Some("10/Oct/2000:13:55:36 -0700"));
+        assert_eq!(get("method"), Some("GET"));
+        assert_eq!(get("path"), Some("/api/orders?id=3"));
+        assert_eq!(get("status"), Some("503"));
+        assert_eq!(get("bytes"), Some("0"));
+        assert_eq!(get("user_agent"), Some(r#"curl/8.0 "x""#));
+
+        let common = r#"::1 - - [10/Oct/2000:13:55:36 +0000] "POST /login HTTP/2.0" 200 2326"#;
+        assert_eq!(parse_access_log(common).len(), 10);
+        assert!(parse_access_log("2024-01-01 ERROR not an access log").is_empty());
+
+        let qp = QueryProcessor::new();
+        let mut query = HashMap::new();
+        query.insert("status".to_string(), sq("503"));
+        query.insert("method".to_string(), sq("get"));
+        assert!(qp.matches_fields(combined, LogFormat::Apache, &query, &MatchContext::default()));
+        assert!(!qp.matches_fields(common, LogFormat::Apache, &query, &MatchContext::default()));
+    }
+
+    #[test]
+    fn any_min_matches_requires_n_of_m() {
+        let qp = QueryProcessor::new();
+        let mut query = LogicalQuery {
+            must: vec![],
+            any: vec![sq("db"), sq("timeout"), sq("retry")],
+            none: vec![],
+            any_min_matches: 2,
+        };
+        assert!(qp.matches("db timeout", &query, &MatchContext::default()));
+        assert!(!qp.matches("db refused", &query, &MatchContext::default()));
+        assert!(qp.matches("retry db timeout", &query, &MatchContext::default()));
+
+        query.any_min_matches = 0;
+        assert!(qp.matches("nothing related", &query, &MatchContext::default()));
+    }
+
+    #[test]
+    fn per_physical_line_keeps_matches_within_one_line() {
+        let qp = QueryProcessor::new();
+        let entry = "2024-01-01 ERROR request failed\n  caused by timeout\n";
+        let mut q = SearchQuery {
+            query: Some(r"failed\s+caused".to_string()),
+            regex: true,
+            case_sensitive: false,
+            whole_word: false,
+            whole_line: false,
+            per_physical_line: false,
+        };
+        let logical = |q: &SearchQuery| LogicalQuery {
+            must: vec![q.clone()],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        // \s 可以匹配换行，整条目匹配时会跨行命中
+        assert!(qp.matches(entry, &logical(&q), &MatchContext::default()));
+        assert_eq!(qp.find_positions(entry, &q, &MatchContext::default()).len(), 1);
+
+        q.per_physical_line = true;
+        assert!(!qp.matches(entry, &logical(&q), &MatchContext::default()));
+        assert!(qp.find_positions(entry, &q, &MatchContext::default()).is_empty());
+
+        q.query = Some("timeout".to_string());
+        q.regex = false;
+        assert!(qp.matches(entry, &logical(&q), &MatchContext::default()));
+        let pos = qp.find_positions(entry, &q, &MatchContext::default());
+        assert_eq!(&entry[pos[0].offset..pos[0].offset + pos[0].length], "timeout");
+    }
+
+    #[test]
+    fn normalized_positions_map_back_to_original() {
+        let qp = QueryProcessor::new();
+        let entry = "connection\t  refused by\n   peer";
+        let norm = NormalizedText::new(entry);
+        assert_eq!(norm.text, "connection refused by peer");
+
+        let q = sq("connection refused by peer");
+        assert!(qp.find_positions(entry, &q, &MatchContext::default()).is_empty());
+        let pos: Vec<_> = qp
+            .find_positions(&norm.text, &q, &MatchContext::default())
+            .iter()
+            .map(|p| norm.map_position(p))
+            .collect();
+        assert_eq!(pos.len(), 1);
+        assert_eq!(&entry[pos[0].offset..pos[0].offset + pos[0].length], entry);
+
+        let pos = norm.map_position(&qp.find_positions(&norm.text, &sq("peer"), &MatchContext::default())[0]);
+        assert_eq!(&entry[pos.offset..pos.offset + pos.length], "peer");
+    }
+
+    #[test]
+    fn level_filter_follows_standard_order() {
+        let order: Vec<String> = DEFAULT_LEVEL_ORDER.iter().map(|s| s.to_string()).collect();
+        let lines = [
+            ("2024-01-01 TRACE tick", 0),
+            ("2024-01-01 DEBUG cache miss", 1),
+            ("2024-01-01 INFO started with error budget", 2),
+            ("2024-01-01 WARNING disk 90%", 3),
+            ("2024-01-01 [error] db down", 4),
+            ("2024-01-01 FATAL out of memory", 5),
+        ];
+        for (min, min_rank) in [("trace", 0), ("DEBUG", 1), ("info", 2), ("WARN", 3), ("ERROR", 4), ("fatal", 5)] {
+            let filter = LevelFilter::new(&order, None, min).unwrap();
+            for (line, rank) in lines {
+                assert_eq!(filter.accepts(line), rank >= min_rank, "{min}: {line}");
+            }
+        }
+        let filter = LevelFilter::new(&order, None, "WARN").unwrap();
+        assert!(filter.accepts("continuation line without level"));
+        assert!(LevelFilter::new(&order, None, "NOTICE").is_err());
+    }
+
+    #[test]
+    fn numeric_filter_compares_captures() {
+        let qp = QueryProcessor::new();
+        let query: NumericQuery =
+            serde_json::from_str(r#"{"field_regex": "latency=(\\d+(?:\\.\\d+)?)ms", "op": ">", "value": 500}"#)
+                .unwrap();
+        assert_eq!(query.op, CompareOp::Gt);
+        let filter = NumericFilter::new(&qp, &query).unwrap();
+        assert!(filter.accepts("GET /a latency=523ms"));
+        assert!(!filter.accepts("GET /a latency=500ms"));
+        assert!(filter.accepts("retry latency=12ms\nfinal latency=900.5ms"));
+        assert!(!filter.accepts("GET /a status=200"));
+
+        let lte = NumericFilter::new(
+            &qp,
+            &NumericQuery {
+                field_regex: r"\b\d{3}\b".into(),
+                op: CompareOp::Lte,
+                value: 299.0,
+            },
+        )
+        .unwrap();
+        // 没有捕获组时取整个匹配
+        assert!(lte.accepts("GET / 200"));
+        assert!(!lte.accepts("GET / 404"));
+
+        let invalid = NumericQuery {
+            field_regex: "(".into(),
+            op: CompareOp::Eq,
+            value: 1.0,
         };
-        assert!(qp.matches("traffic error occurred", &query));
-        assert!(!qp.matches("info traffic ok", &query)); // must not satisfied
-        assert!(!qp.matches("traffic fatal error", &query)); // none matched
+        assert!(NumericFilter::new(&qp, &invalid).is_err());
+    }
+
+    #[test]
+    fn strip_ansi_removes_color_codes() {
+        assert!(matches!(strip_ansi("plain error"), Cow::Borrowed(_)));
+        assert_eq!(
+            strip_ansi("\x1b[1;31mERROR\x1b[0m db \x1b[38;5;208mdown\x1b[m\n"),
+            "ERROR db down\n"
+        );
+        // 不是 CSI 的 ESC 原样保留
+        assert_eq!(strip_ansi("a\x1bb"), "a\x1bb");
+    }
+
+    #[test]
+    fn compile_regex_rejects_long_patterns() {
+        let qp = QueryProcessor::new().with_max_regex_len(Some(8));
+        assert!(qp.compile_regex("a{1,3}b", true).is_ok());
+        let err = qp.compile_regex(&"a".repeat(100), true).unwrap_err();
+        assert!(err.to_string().contains("max_regex_len"));
+        assert!(QueryProcessor::new().compile_regex(&"a".repeat(100), true).is_ok());
+    }
+
+    #[test]
+    fn whole_line_requires_exact_trimmed_line() {
+        let qp = QueryProcessor::new();
+        let logical = |q: SearchQuery| LogicalQuery {
+            must: vec![q],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let text = "2024-01-01 ERROR boom\n  Done  \nDone later";
+        let mut q = sq("done");
+        q.whole_line = true;
+        let positions = qp.find_positions(text, &q, &MatchContext::default());
+        assert_eq!(positions.len(), 1);
+        assert_eq!(&text[positions[0].offset..positions[0].offset + positions[0].length], "Done");
+        assert!(qp.matches(text, &logical(q.clone()), &MatchContext::default()));
+
+        q.case_sensitive = true;
+        assert!(!qp.matches(text, &logical(q.clone()), &MatchContext::default()));
+
+        let mut re = sq(r"Done \w+");
+        re.regex = true;
+        re.whole_line = true;
+        assert_eq!(qp.find_positions(text, &re, &MatchContext::default()).len(), 1);
+        re.query = Some("ERROR".into());
+        assert!(!qp.matches(text, &logical(re), &MatchContext::default()));
+    }
+
+    #[test]
+    fn physical_lines_follow_the_line_delimiter() {
+        let qp = QueryProcessor::new();
+        let ctx = MatchContext::default().with_line_delimiter(b'\0');
+        let text = "2024-01-01 ERROR boom\0  Done  \0caused by timeout\0";
+        let mut whole = sq("done");
+        whole.whole_line = true;
+        let positions = qp.find_positions(text, &whole, &ctx);
+        assert_eq!(positions.len(), 1);
+        assert_eq!(&text[positions[0].offset..positions[0].offset + positions[0].length], "Done");
+        // 按默认的 \n 切分时整条目只有一行，不会命中
+        assert!(qp.find_positions(text, &whole, &MatchContext::default()).is_empty());
+
+        let mut per_line = sq(r"boom.*caused");
+        per_line.regex = true;
+        per_line.per_physical_line = true;
+        let logical = LogicalQuery {
+            must: vec![per_line],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        assert!(!qp.matches(text, &logical, &ctx));
+        assert!(qp.matches(text, &logical, &MatchContext::default()));
     }
 
     #[test]
@@ -223,22 +1026,77 @@ mod tests {
             regex: false,
             case_sensitive: false,
             whole_word: true,
+            whole_line: false,
+            per_physical_line: false,
         };
-        let positions = qp.find_positions("err and terror", &query);
+        let positions = qp.find_positions("err and terror", &query, &MatchContext::default());
         assert_eq!(positions.len(), 1);
         assert_eq!(positions[0].offset, 0);
 
+        // 词边界按 Unicode 判断：前面的多字节字符不会被误当作边界，忽略大小写时偏移仍指向原文
+        let cjk = SearchQuery {
+            query: Some("错误".into()),
+            ..query.clone()
+        };
+        let text = "数据库错误 | 发生 错误 了 | 错误:timeout";
+        let positions = qp.find_positions(text, &cjk, &MatchContext::default());
+        assert_eq!(
+            positions.iter().map(|p| p.offset).collect::<Vec<_>>(),
+            vec![text.find("发生").unwrap() + "发生 ".len(), text.rfind("错误").unwrap()]
+        );
+        let logical = LogicalQuery {
+            must: vec![cjk],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 0,
+        };
+        assert!(!qp.matches("数据库错误", &logical, &MatchContext::default()));
+        // 预编译的上下文与现场编译结果一致
+        let ctx = MatchContext::new(&logical.must);
+        assert!(matches!(ctx.word_regex("错误", false), Some(Cow::Borrowed(_))));
+        assert_eq!(qp.find_positions(text, &logical.must[0], &ctx).len(), 2);
+        assert!(!qp.matches("数据库错误", &logical, &ctx));
+        let word = SearchQuery {
+            query: Some("err".into()),
+            ..query.clone()
+        };
+        let positions = qp.find_positions("İstanbul ERR", &word, &MatchContext::default());
+        assert_eq!((positions[0].offset, positions[0].length), ("İstanbul ".len(), 3));
+        assert!(qp.find_positions("caféerr", &word, &MatchContext::default()).is_empty());
+
         let re_query = SearchQuery {
             query: Some(r"t[a-z]{3}or".into()),
             regex: true,
             case_sensitive: false,
             whole_word: false,
+            whole_line: false,
+            per_physical_line: false,
         };
-        let re_pos = qp.find_positions("err and terror", &re_query);
+        let re_pos = qp.find_positions("err and terror", &re_query, &MatchContext::default());
         assert_eq!(re_pos.len(), 1);
         assert_eq!(re_pos[0].offset, 8);
     }
 
+    #[test]
+    fn syslog_timestamps_assume_current_year() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 2, 12, 0, 0).unwrap();
+        assert_eq!(
+            parse_syslog_timestamp("Jan  2 10:00:00", now),
+            Some(Utc.with_ymd_and_hms(2024, 1, 2, 10, 0, 0).unwrap())
+        );
+        // 年末的记录在新年读取时属于去年
+        assert_eq!(
+            parse_syslog_timestamp("Dec 31 23:59:59", now),
+            Some(Utc.with_ymd_and_hms(2023, 12, 31, 23, 59, 59).unwrap())
+        );
+        assert!(parse_syslog_timestamp("Foo 31 23:59:59", now).is_none());
+
+        let re = Regex::new(DEFAULT_TIMESTAMP_REGEX).unwrap();
+        let m = re.find("Jan  5 10:00:00 host sshd[1]: accepted").unwrap();
+        assert_eq!(m.as_str(), "Jan  5 10:00:00");
+        assert!(parse_log_timestamp(m.as_str()).is_some());
+    }
+
     #[test]
     fn time_filter_respects_range() {
         let qp = QueryProcessor::new();
@@ -254,10 +1112,24 @@ mod tests {
                     .unwrap(),
             ),
             regex: Regex::new(r"\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}Z").ok(),
+            windows: Vec::new(),
         };
         let log_in = "2024-01-01T12:00:00Z something";
         let log_out = "2024-01-03T00:00:00Z late";
         assert!(qp.apply_time_filter(log_in, &Some(tf.clone())));
         assert!(!qp.apply_time_filter(log_out, &Some(tf)));
     }
+
+    #[test]
+    fn output_time_uses_configured_zone() {
+        let ts = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).single().unwrap();
+        assert_eq!(format_output_time(ts, None), "2024-01-01T12:00:00.000Z");
+
+        let tz = parse_output_tz("Asia/Shanghai").unwrap();
+        assert_eq!(
+            format_output_time(ts, Some(&tz)),
+            "2024-01-01T20:00:00.000+08:00"
+        );
+        assert!(parse_output_tz("Mars/Base").is_err());
+    }
 }
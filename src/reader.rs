@@ -1,25 +1,205 @@
-use std::path::Path;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 use async_compression::tokio::bufread::GzipDecoder;
 use async_stream::try_stream;
 use chardetng::EncodingDetector;
 use encoding_rs::Encoding;
 use futures::stream::{self, BoxStream};
+use futures::TryStreamExt;
+use globset::{Glob, GlobMatcher};
 use tokio::fs::File;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
 use tokio::io::SeekFrom;
+use tracing::warn;
 
+use crate::config::EncodingOverride;
 use crate::error::{LogSearchError, Result};
+use crate::model::TailPosition;
+
+/// 单次追加读取的最大字节数，超出部分留到下一次读取。
+const MAX_APPEND_CHUNK: u64 = 16 * 1024 * 1024;
+
+/// 追加读取结果：新出现的完整记录以及读取后的位置。
+#[derive(Debug, Default)]
+pub struct AppendedChunk {
+    pub lines: Vec<String>,
+    pub next: TailPosition,
+}
 
 /// 文件读取器：流式读取行，支持自动编码检测和 gzip 支持。
 #[derive(Clone)]
 pub struct FileReader {
+    /// 读取磁盘文件（含 gzip 压缩数据）的缓冲区大小。
     pub buffer_size: usize,
+    /// gzip 解压输出的缓冲区大小，默认与 `buffer_size` 相同。
+    pub decompress_buffer_size: usize,
+    /// 记录分隔符，默认 `\n`；返回的每一行都保留该分隔符。
+    pub line_delimiter: u8,
+    /// 每个文件最多读取的字节数（gzip 按解压后计）；跨越上限的那一行仍完整返回。
+    pub max_bytes: Option<u64>,
+    /// 累计读取的字节数（gzip 按解压后计），由同一读取器的所有克隆共享。
+    bytes_read: Arc<AtomicU64>,
+    /// 按路径指定的编码，命中时跳过自动检测。
+    pub encoding_overrides: EncodingOverrides,
+    /// gzip 文件解压后允许的最大字节数，超出时读取以错误结束，防止高压缩比文件耗尽内存或时间。
+    pub max_decompressed_bytes: Option<u64>,
+    /// 与其他读取器共享的总读取预算（如一次搜索的所有文件）。
+    pub shared_budget: Option<SharedReadBudget>,
+    /// 只读取文件前若干字节（按磁盘上的原始字节计，不含之后追加的内容），用于分页时固定文件快照；
+    /// 不作用于 gzip 文件。
+    pub size_limit: Option<u64>,
+    /// 无法按文件编码解码的字节序列是否作为错误结束读取；默认替换为 U+FFFD 继续读取。
+    /// 普通文件与 gzip 文件行为一致。
+    pub strict_utf8: bool,
+    /// 额外记录本读取器读取的字节数，供重试前用 [`FileReader::release`] 撤销失败尝试的计数。
+    attempt_bytes: Option<Arc<AtomicU64>>,
+}
+
+/// 多个读取器共享的读取预算：累计读取量（gzip 按解压后计）达到上限后，
+/// 所有使用它的读取流都停止产出后续行。
+#[derive(Clone, Default)]
+pub struct SharedReadBudget {
+    used: Arc<AtomicU64>,
+    limit: Option<u64>,
+}
+
+impl SharedReadBudget {
+    /// `limit` 为 `None` 时只计数、不限制。
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            used: Arc::new(AtomicU64::new(0)),
+            limit,
+        }
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.limit.is_some_and(|limit| self.used() >= limit)
+    }
+
+    fn remaining(&self) -> u64 {
+        self.limit.map_or(u64::MAX, |limit| limit.saturating_sub(self.used()))
+    }
+}
+
+/// 编译后的编码覆盖规则（见 [`EncodingOverride`]），克隆开销为一次引用计数。
+#[derive(Clone, Default)]
+pub struct EncodingOverrides(Arc<Vec<(GlobMatcher, &'static Encoding)>>);
+
+impl EncodingOverrides {
+    pub fn new(rules: &[EncodingOverride]) -> Result<Self> {
+        let mut compiled = Vec::with_capacity(rules.len());
+        for rule in rules {
+            let matcher = Glob::new(&rule.glob)
+                .map_err(|e| {
+                    LogSearchError::ConfigError(format!("invalid encoding override glob {:?}: {e}", rule.glob))
+                })?
+                .compile_matcher();
+            let encoding = Encoding::for_label(rule.encoding.trim().as_bytes()).ok_or_else(|| {
+                LogSearchError::ConfigError(format!("unknown encoding {:?} for glob {:?}", rule.encoding, rule.glob))
+            })?;
+            compiled.push((matcher, encoding));
+        }
+        Ok(Self(Arc::new(compiled)))
+    }
+
+    /// 第一个匹配 `path` 的规则给出的编码。
+    pub fn lookup(&self, path: &Path) -> Option<&'static Encoding> {
+        self.0
+            .iter()
+            .find(|(matcher, _)| matcher.is_match(path))
+            .map(|(_, encoding)| *encoding)
+    }
 }
 
 impl FileReader {
     pub fn new(buffer_size: usize) -> Self {
-        Self { buffer_size }
+        Self {
+            buffer_size,
+            decompress_buffer_size: buffer_size,
+            line_delimiter: b'\n',
+            max_bytes: None,
+            bytes_read: Arc::new(AtomicU64::new(0)),
+            encoding_overrides: EncodingOverrides::default(),
+            max_decompressed_bytes: None,
+            shared_budget: None,
+            size_limit: None,
+            strict_utf8: false,
+            attempt_bytes: None,
+        }
+    }
+
+    /// 本读取器及其克隆累计读取的字节数。
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    pub fn with_decompress_buffer_size(mut self, size: usize) -> Self {
+        self.decompress_buffer_size = size;
+        self
+    }
+
+    pub fn with_line_delimiter(mut self, delimiter: u8) -> Self {
+        self.line_delimiter = delimiter;
+        self
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn with_encoding_overrides(mut self, overrides: EncodingOverrides) -> Self {
+        self.encoding_overrides = overrides;
+        self
+    }
+
+    pub fn with_max_decompressed_bytes(mut self, limit: Option<u64>) -> Self {
+        self.max_decompressed_bytes = limit;
+        self
+    }
+
+    pub fn with_shared_budget(mut self, budget: Option<SharedReadBudget>) -> Self {
+        self.shared_budget = budget;
+        self
+    }
+
+    pub fn with_size_limit(mut self, limit: Option<u64>) -> Self {
+        self.size_limit = limit;
+        self
+    }
+
+    pub fn with_strict_utf8(mut self, strict: bool) -> Self {
+        self.strict_utf8 = strict;
+        self
+    }
+
+    pub fn with_attempt_counter(mut self, counter: Arc<AtomicU64>) -> Self {
+        self.attempt_bytes = Some(counter);
+        self
+    }
+
+    /// 撤销 `n` 字节的计数：从累计读取字节数与共享预算中扣除，用于丢弃失败的读取尝试。
+    pub fn release(&self, n: u64) {
+        saturating_sub(&self.bytes_read, n);
+        if let Some(shared) = &self.shared_budget {
+            saturating_sub(&shared.used, n);
+        }
+    }
+
+    fn byte_budget(&self) -> ByteBudget {
+        ByteBudget {
+            remaining: self.max_bytes.unwrap_or(u64::MAX),
+            counter: self.bytes_read.clone(),
+            shared: self.shared_budget.clone(),
+            attempt: self.attempt_bytes.clone(),
+        }
     }
 
     /// 流式读取文本行，支持自动编码检测；gz 文件解码为 UTF-8。
@@ -28,69 +208,475 @@ impl FileReader {
             return self.read_gzip_lines(path).await;
         }
         let mut file = File::open(path).await.map_err(LogSearchError::from)?;
-        let encoding = self.detect_encoding(&mut file).await?;
+        let encoding = self.detect_encoding(path, &mut file).await?;
+        let size_limit = match self.size_limit {
+            Some(limit) => limit.saturating_sub(file.stream_position().await?),
+            None => u64::MAX,
+        };
+        let mut file = file.take(size_limit);
         if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
             let mut buf = Vec::new();
-            file.read_to_end(&mut buf).await?;
-            let (cow, _, _) = encoding.decode(&buf);
+            let mut budget = self.byte_budget();
+            (&mut file).take(budget.allowance()).read_to_end(&mut buf).await?;
+            budget.consume(buf.len());
+            let (cow, _, had_errors) = encoding.decode(&buf);
+            if had_errors && self.strict_utf8 {
+                return Err(undecodable(path, encoding));
+            }
             let content = cow.into_owned();
+            let delimiter = self.line_delimiter as char;
             let lines: Vec<String> = content
-                .split_inclusive('\n')
+                .split_inclusive(delimiter)
                 .map(|s| s.to_string())
                 .collect();
             return Ok(Box::pin(stream::iter(lines.into_iter().map(Ok))));
         }
 
         let reader = BufReader::with_capacity(self.buffer_size, file);
-        let stream = try_stream! {
-            let mut reader = reader;
-            let mut buf = Vec::new();
-            loop {
-                buf.clear();
-                let n = reader.read_until(b'\n', &mut buf).await?;
-                if n == 0 {
-                    break;
-                }
-                let (cow, _, _) = encoding.decode(&buf);
-                yield cow.into_owned();
+        Ok(decoded_lines(reader, path.to_path_buf(), encoding, self.line_delimiter, self.byte_budget(), self.strict_utf8))
+    }
+
+    /// 从解压后第 `offset` 字节处开始读取：丢弃 `offset` 所在的不完整记录，
+    /// 从其后第一条完整记录开始返回；`offset` 恰为记录开头时从该记录开始。
+    ///
+    /// gzip 文件若存在 bgzip 索引（同名 `.gzi` 旁路文件），直接定位到 `offset` 之前最近的块再解压，
+    /// 否则从头解压并跳过前面的字节。跳过的字节同样计入读取预算与 `max_decompressed_bytes`，
+    /// 偏移超出 `max_decompressed_bytes` 时报错。UTF-16 文件不支持按偏移读取。
+    pub async fn read_lines_from(&self, path: &Path, offset: u64) -> Result<BoxStream<'static, Result<String>>> {
+        if offset == 0 {
+            return self.read_lines(path).await;
+        }
+        // 从前一个字节开始读并丢弃到第一个分隔符，使记录开头处的偏移不会丢掉该记录
+        let target = offset - 1;
+        let delimiter = self.line_delimiter;
+
+        if is_gz(path) {
+            let limit = self.max_decompressed_bytes.unwrap_or(u64::MAX);
+            if target > limit {
+                return Err(LogSearchError::FileAccessError {
+                    path: path.to_path_buf(),
+                    reason: format!("byte offset {offset} exceeds max_decompressed_bytes ({limit})"),
+                });
             }
-        };
-        Ok(Box::pin(stream))
+            let (compressed, uncompressed) = match GziIndex::load(path).await? {
+                Some(index) => index.block_for(target),
+                None => (0, 0),
+            };
+            let mut file = File::open(path).await.map_err(LogSearchError::from)?;
+            file.seek(SeekFrom::Start(compressed)).await?;
+            let mut decoder = GzipDecoder::new(BufReader::with_capacity(self.buffer_size, file));
+            decoder.multiple_members(true);
+            let mut decoder = BufReader::with_capacity(self.decompress_buffer_size, decoder);
+            let mut budget = self.byte_budget();
+            let skip = target - uncompressed;
+            let skipped = tokio::io::copy(&mut (&mut decoder).take(skip.min(budget.allowance())), &mut tokio::io::sink()).await?;
+            budget.consume(skipped as usize);
+            if skipped < skip {
+                // 文件在偏移之前结束，或跳过时已耗尽读取预算
+                return Ok(Box::pin(stream::empty()));
+            }
+            let mut partial = Vec::new();
+            (&mut decoder).take((limit - target).saturating_add(1)).read_until(delimiter, &mut partial).await?;
+            budget.consume(partial.len());
+            let position = target + partial.len() as u64;
+            return Ok(gzip_lines(decoder, path.to_path_buf(), delimiter, budget, self.max_decompressed_bytes, self.strict_utf8, position));
+        }
+
+        let mut file = File::open(path).await.map_err(LogSearchError::from)?;
+        let encoding = self.detect_encoding(path, &mut file).await?;
+        if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+            return Err(LogSearchError::InvalidRequest(format!(
+                "byte offsets are not supported for UTF-16 files: {}",
+                path.display()
+            )));
+        }
+        let start = target.max(file.stream_position().await?);
+        file.seek(SeekFrom::Start(start)).await?;
+        let mut reader = BufReader::with_capacity(self.buffer_size, file);
+        if start == target {
+            reader.read_until(delimiter, &mut Vec::new()).await?;
+        }
+        Ok(decoded_lines(reader, path.to_path_buf(), encoding, delimiter, self.byte_budget(), self.strict_utf8))
     }
 
+    /// 空文件不产生任何行；被截断的 gzip 在截断处结束并记录警告，已解出的行照常返回。
     async fn read_gzip_lines(&self, path: &Path) -> Result<BoxStream<'static, Result<String>>> {
         let file = File::open(path).await.map_err(LogSearchError::from)?;
+        if file.metadata().await?.len() == 0 {
+            warn!("empty gzip file, treated as no lines: {}", path.display());
+            return Ok(Box::pin(stream::empty()));
+        }
         let reader = BufReader::with_capacity(self.buffer_size, file);
-        let decoder = GzipDecoder::new(reader);
-        let mut decoder = BufReader::with_capacity(self.buffer_size, decoder);
-        let path_buf = path.to_path_buf();
+        let mut decoder = GzipDecoder::new(reader);
+        // 多成员 gzip（拼接文件、bgzip）需逐个成员解压，否则在第一个成员末尾就会停止
+        decoder.multiple_members(true);
+        let decoder = BufReader::with_capacity(self.decompress_buffer_size, decoder);
+        Ok(gzip_lines(decoder, path.to_path_buf(), self.line_delimiter, self.byte_budget(), self.max_decompressed_bytes, self.strict_utf8, 0))
+    }
 
-        let stream = try_stream! {
-            let mut buf = Vec::new();
-            loop {
-                buf.clear();
-                let n = decoder.read_until(b'\n', &mut buf).await?;
-                if n == 0 {
+    /// 读取文件最后 `count` 条记录（保留分隔符）。
+    ///
+    /// 普通文件从末尾按块向前查找分隔符，只读取需要的部分；gzip 与 UTF-16 文件无法从末尾定位，
+    /// 改为顺序解码并只在环形缓冲中保留最后 `count` 条，内存占用与文件大小无关。
+    pub async fn read_tail(&self, path: &Path, count: usize) -> Result<Vec<String>> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        if !is_gz(path) {
+            let mut file = File::open(path).await.map_err(LogSearchError::from)?;
+            let encoding = self.detect_encoding(path, &mut file).await?;
+            if encoding != encoding_rs::UTF_16LE && encoding != encoding_rs::UTF_16BE {
+                let start = file.stream_position().await?;
+                return self.read_tail_backward(path, file, start, encoding, count).await;
+            }
+        }
+
+        let mut lines = self.read_lines(path).await?;
+        let mut ring = VecDeque::with_capacity(count);
+        while let Some(line) = lines.try_next().await? {
+            if ring.len() == count {
+                ring.pop_front();
+            }
+            ring.push_back(line);
+        }
+        Ok(ring.into())
+    }
+
+    /// 从文件末尾向前按块读取，直到找到 `count` 条记录的起点（不早于 `start`，即 BOM 之后）。
+    async fn read_tail_backward(
+        &self,
+        path: &Path,
+        mut file: File,
+        start: u64,
+        encoding: &'static Encoding,
+        count: usize,
+    ) -> Result<Vec<String>> {
+        let len = file.metadata().await?.len();
+        let chunk = self.buffer_size.max(1) as u64;
+        let mut tail: Vec<u8> = Vec::new();
+        let mut pos = len;
+        // 末尾的分隔符属于最后一条记录，不作为记录边界计数
+        let mut boundaries = 0usize;
+        let mut skip_trailing = true;
+        let mut begin = None;
+        while pos > start && begin.is_none() {
+            let read_from = pos.saturating_sub(chunk).max(start);
+            let mut block = vec![0u8; (pos - read_from) as usize];
+            file.seek(SeekFrom::Start(read_from)).await?;
+            file.read_exact(&mut block).await?;
+            for i in (0..block.len()).rev() {
+                if block[i] != self.line_delimiter {
+                    skip_trailing = false;
+                    continue;
+                }
+                if skip_trailing {
+                    skip_trailing = false;
+                    continue;
+                }
+                boundaries += 1;
+                if boundaries == count {
+                    begin = Some(i + 1);
                     break;
                 }
-                let line = String::from_utf8(buf.clone()).map_err(|e| LogSearchError::EncodingError { path: path_buf.clone(), reason: e.to_string() })?;
-                yield line;
+            }
+            let keep = begin.unwrap_or(0);
+            block.drain(..keep);
+            block.extend_from_slice(&tail);
+            tail = block;
+            pos = read_from;
+        }
+
+        // 先按原始字节切分再逐条解码，与顺序读取的解码规则（含 strict_utf8）一致
+        tail.split_inclusive(|b| *b == self.line_delimiter)
+            .map(|record| decode_record(record, path, encoding, self.strict_utf8))
+            .collect()
+    }
+
+    /// 计算文件当前的尾随位置：最后一个完整记录之后的偏移及记录数。
+    /// gzip 文件以压缩后大小作为偏移。
+    pub async fn tail_position(&self, path: &Path) -> Result<TailPosition> {
+        if is_gz(path) {
+            let len = tokio::fs::metadata(path).await?.len();
+            let line = self.read_lines(path).await?.try_fold(0usize, |n, _| async move { Ok(n + 1) }).await?;
+            return Ok(TailPosition { offset: len, line });
+        }
+
+        let mut file = File::open(path).await.map_err(LogSearchError::from)?;
+        let len = file.metadata().await?.len();
+        let encoding = self.detect_encoding(path, &mut file).await?;
+        let start = file.stream_position().await?;
+        if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+            let line = self.read_lines(path).await?.try_fold(0usize, |n, _| async move { Ok(n + 1) }).await?;
+            return Ok(TailPosition { offset: len, line });
+        }
+
+        let mut reader = BufReader::with_capacity(self.buffer_size, file.take(len - start));
+        let mut offset = start;
+        let mut consumed = start;
+        let mut line = 0usize;
+        loop {
+            let buf = reader.fill_buf().await?;
+            if buf.is_empty() {
+                break;
+            }
+            for (i, b) in buf.iter().enumerate() {
+                if *b == self.line_delimiter {
+                    line += 1;
+                    offset = consumed + i as u64 + 1;
+                }
+            }
+            let n = buf.len();
+            consumed += n as u64;
+            reader.consume(n);
+        }
+        Ok(TailPosition { offset, line })
+    }
+
+    /// 读取 `from` 之后新追加的完整记录（以分隔符结尾），末尾未写完的记录留待下次读取。
+    /// gzip 文件无法按偏移续读：大小变化时整体解压并跳过已读记录。
+    pub async fn read_appended(&self, path: &Path, from: TailPosition) -> Result<AppendedChunk> {
+        if is_gz(path) {
+            let len = tokio::fs::metadata(path).await?.len();
+            if len == from.offset {
+                return Ok(AppendedChunk { lines: Vec::new(), next: from });
+            }
+            let mut stream = self.read_lines(path).await?;
+            let mut lines = Vec::new();
+            let mut line = 0usize;
+            while let Some(l) = stream.try_next().await? {
+                line += 1;
+                if line > from.line {
+                    lines.push(l);
+                }
+            }
+            return Ok(AppendedChunk { lines, next: TailPosition { offset: len, line } });
+        }
+
+        let mut file = File::open(path).await.map_err(LogSearchError::from)?;
+        let encoding = self.detect_encoding(path, &mut file).await?;
+        let start = from.offset.max(file.stream_position().await?);
+        file.seek(SeekFrom::Start(start)).await?;
+        let mut buf = Vec::new();
+        file.take(MAX_APPEND_CHUNK).read_to_end(&mut buf).await?;
+
+        let (lines, consumed) = if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+            let (cow, _, had_errors) = encoding.decode(&buf);
+            if had_errors && self.strict_utf8 {
+                return Err(undecodable(path, encoding));
+            }
+            let delimiter = self.line_delimiter as char;
+            let lines: Vec<String> = cow
+                .split_inclusive(delimiter)
+                .filter(|l| l.ends_with(delimiter))
+                .map(|l| l.to_string())
+                .collect();
+            let consumed = lines.iter().map(|l| l.encode_utf16().count() * 2).sum::<usize>();
+            (lines, consumed)
+        } else {
+            match buf.iter().rposition(|b| *b == self.line_delimiter) {
+                Some(last) => {
+                    let lines = buf[..=last]
+                        .split_inclusive(|b| *b == self.line_delimiter)
+                        .map(|l| match encoding.decode(l) {
+                            (_, _, true) if self.strict_utf8 => Err(undecodable(path, encoding)),
+                            (cow, _, _) => Ok(cow.into_owned()),
+                        })
+                        .collect::<Result<_>>()?;
+                    (lines, last + 1)
+                }
+                None => (Vec::new(), 0),
             }
         };
-        Ok(Box::pin(stream))
+
+        let next = TailPosition {
+            offset: start + consumed as u64,
+            line: from.line + lines.len(),
+        };
+        Ok(AppendedChunk { lines, next })
     }
 
-    /// 检测文件编码，默认为 UTF-8。检测到 BOM 后重新定位文件游标。
-    async fn detect_encoding(&self, file: &mut File) -> Result<&'static Encoding> {
+    /// 检测文件编码，默认为 UTF-8；`encoding_overrides` 命中时直接采用指定编码。
+    /// 检测到 BOM 后重新定位文件游标（覆盖编码只跳过与之一致的 BOM）。
+    async fn detect_encoding(&self, path: &Path, file: &mut File) -> Result<&'static Encoding> {
         let mut buf = vec![0u8; 8192];
         let read = file.read(&mut buf).await?;
-        let (encoding, bom_len) = detect_from_prefix(&buf[..read]);
+        let (encoding, bom_len) = match self.encoding_overrides.lookup(path) {
+            Some(forced) => match Encoding::for_bom(&buf[..read]) {
+                Some((bom_encoding, len)) if bom_encoding == forced => (forced, len),
+                _ => (forced, 0),
+            },
+            None => detect_from_prefix(&buf[..read]),
+        };
         file.seek(SeekFrom::Start(bom_len as u64)).await?;
         Ok(encoding)
     }
 }
 
-fn is_gz(path: &Path) -> bool {
+/// 读取字节预算：记录已读字节，单文件上限或共享预算用完后停止产出后续行。
+struct ByteBudget {
+    remaining: u64,
+    counter: Arc<AtomicU64>,
+    shared: Option<SharedReadBudget>,
+    attempt: Option<Arc<AtomicU64>>,
+}
+
+impl ByteBudget {
+    fn exhausted(&self) -> bool {
+        self.allowance() == 0
+    }
+
+    /// 还可读取的字节数。
+    fn allowance(&self) -> u64 {
+        let shared = self.shared.as_ref().map_or(u64::MAX, SharedReadBudget::remaining);
+        self.remaining.min(shared)
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.remaining = self.remaining.saturating_sub(n as u64);
+        self.counter.fetch_add(n as u64, Ordering::Relaxed);
+        if let Some(shared) = &self.shared {
+            shared.used.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        if let Some(attempt) = &self.attempt {
+            attempt.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+fn saturating_sub(counter: &AtomicU64, n: u64) {
+    let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| Some(v.saturating_sub(n)));
+}
+
+/// 按 `encoding` 解码的逐行流；已读字节达到 `budget` 后停止。`strict` 时遇到无法解码的字节以错误结束。
+fn decoded_lines<R>(
+    mut reader: R,
+    path: PathBuf,
+    encoding: &'static Encoding,
+    delimiter: u8,
+    mut budget: ByteBudget,
+    strict: bool,
+) -> BoxStream<'static, Result<String>>
+where
+    R: AsyncBufRead + Unpin + Send + 'static,
+{
+    Box::pin(try_stream! {
+        let mut buf = Vec::new();
+        while !budget.exhausted() {
+            buf.clear();
+            let n = reader.read_until(delimiter, &mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            budget.consume(n);
+            yield decode_record(&buf, &path, encoding, strict)?;
+        }
+    })
+}
+
+/// 按 `encoding` 解码一条记录；`strict` 时遇到无法解码的字节返回错误，否则替换为 U+FFFD。
+fn decode_record(record: &[u8], path: &Path, encoding: &'static Encoding, strict: bool) -> Result<String> {
+    let (cow, _, had_errors) = encoding.decode(record);
+    if had_errors && strict {
+        return Err(undecodable(path, encoding));
+    }
+    Ok(cow.into_owned())
+}
+
+/// 解压后的 UTF-8 逐行流；截断处结束并记录警告，解压字节达到 `budget` 后停止。
+/// 无效的 UTF-8 与普通文件一样容错替换，`strict` 时以错误结束。
+fn gzip_lines<R>(
+    decoder: R,
+    path: PathBuf,
+    delimiter: u8,
+    mut budget: ByteBudget,
+    max_decompressed: Option<u64>,
+    strict: bool,
+    position: u64,
+) -> BoxStream<'static, Result<String>>
+where
+    R: AsyncBufRead + Unpin + Send + 'static,
+{
+    // `position` 为 `decoder` 起点在解压数据中的偏移，上限从文件开头算起；
+    // 多读 1 字节以区分"恰好读到上限"与"超出上限"，同时限制单行占用的内存
+    let limit = max_decompressed.unwrap_or(u64::MAX);
+    let mut decoder = decoder.take(limit.saturating_sub(position).saturating_add(1));
+    Box::pin(try_stream! {
+        let mut buf = Vec::new();
+        let mut decompressed = position;
+        while !budget.exhausted() {
+            buf.clear();
+            let n = match decoder.read_until(delimiter, &mut buf).await {
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    warn!("truncated gzip file, stopping early: {}", path.display());
+                    break;
+                }
+                Err(e) => Err(e)?,
+            };
+            if n == 0 {
+                break;
+            }
+            decompressed += n as u64;
+            if decompressed > limit {
+                Err(LogSearchError::FileAccessError {
+                    path: path.clone(),
+                    reason: format!("decompressed size exceeds max_decompressed_bytes ({limit}), file truncated"),
+                })?;
+            }
+            budget.consume(n);
+            if strict && std::str::from_utf8(&buf).is_err() {
+                Err(undecodable(&path, encoding_rs::UTF_8))?;
+            }
+            yield String::from_utf8_lossy(&buf).into_owned();
+        }
+    })
+}
+
+/// bgzip 索引（`.gzi`）：各块的（压缩偏移, 解压偏移），不含位于 0 的第一块。
+/// 格式为小端 u64 条目数，随后每条两个小端 u64。
+struct GziIndex(Vec<(u64, u64)>);
+
+impl GziIndex {
+    async fn load(path: &Path) -> Result<Option<Self>> {
+        let mut index_path = path.as_os_str().to_owned();
+        index_path.push(".gzi");
+        let bytes = match tokio::fs::read(&index_path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let words: Vec<u64> = bytes
+            .chunks_exact(8)
+            .map(|c| u64::from_le_bytes(c.try_into().expect("8-byte chunk")))
+            .collect();
+        let count = words.first().copied().unwrap_or(0) as usize;
+        if bytes.len() % 8 != 0 || words.len() != 1 + count * 2 {
+            warn!("malformed gzi index, falling back to linear decompression: {}", index_path.to_string_lossy());
+            return Ok(None);
+        }
+        Ok(Some(Self(words[1..].chunks_exact(2).map(|p| (p[0], p[1])).collect())))
+    }
+
+    /// 解压偏移不超过 `target` 的最后一个块。
+    fn block_for(&self, target: u64) -> (u64, u64) {
+        self.0
+            .iter()
+            .take_while(|(_, uncompressed)| *uncompressed <= target)
+            .last()
+            .copied()
+            .unwrap_or((0, 0))
+    }
+}
+
+/// `strict_utf8` 时遇到无法解码的字节序列返回的错误。
+fn undecodable(path: &Path, encoding: &'static Encoding) -> LogSearchError {
+    LogSearchError::EncodingError {
+        path: path.to_path_buf(),
+        reason: format!("invalid {} byte sequence (search.strict_utf8 is enabled)", encoding.name()),
+    }
+}
+
+pub(crate) fn is_gz(path: &Path) -> bool {
     matches!(path.extension().and_then(|s| s.to_str()), Some("gz"))
 }
 
@@ -104,12 +690,72 @@ fn detect_from_prefix(prefix: &[u8]) -> (&'static Encoding, usize) {
     if prefix.starts_with(&[0xFE, 0xFF]) {
         return (encoding_rs::UTF_16BE, 2);
     }
+    if let Some(encoding) = guess_bomless_utf16(prefix) {
+        return (encoding, 0);
+    }
 
     let mut detector = EncodingDetector::new();
     detector.feed(prefix, true);
     (detector.guess(None, true), 0)
 }
 
+/// 内容嗅探读取的字节数。
+const SNIFF_BYTES: u64 = 8192;
+
+/// 读取文件开头（gzip 按解压后内容）判断是否像可解码的文本：按检测出的编码解码后，
+/// 替换字符与除制表、换行、回车、换页、ESC 外的控制字符不超过 10%。空文件视为文本。
+pub fn looks_like_text(path: &Path) -> std::io::Result<bool> {
+    use std::io::Read;
+
+    let file = std::fs::File::open(path)?;
+    let mut prefix = Vec::new();
+    if is_gz(path) {
+        // 不完整或损坏的 gzip 只按已解出的部分判断
+        let _ = flate2::read::GzDecoder::new(file).take(SNIFF_BYTES).read_to_end(&mut prefix);
+    } else {
+        file.take(SNIFF_BYTES).read_to_end(&mut prefix)?;
+    }
+    let (encoding, bom_len) = detect_from_prefix(&prefix);
+    let body = &prefix[bom_len..];
+    if encoding != encoding_rs::UTF_16LE && encoding != encoding_rs::UTF_16BE && body.contains(&0) {
+        return Ok(false);
+    }
+    let (text, _) = encoding.decode_without_bom_handling(body);
+    let (mut total, mut suspicious) = (0usize, 0usize);
+    // 截断处可能切开一个多字节字符，末尾的替换字符不计
+    for c in text.trim_end_matches('\u{FFFD}').chars() {
+        total += 1;
+        let allowed_control = matches!(c, '\t' | '\n' | '\r' | '\x0c' | '\x1b');
+        if c == '\u{FFFD}' || (c.is_control() && !allowed_control) {
+            suspicious += 1;
+        }
+    }
+    Ok(suspicious * 10 <= total)
+}
+
+/// 无 BOM 的 UTF-16 判断：日志以 ASCII 为主，UTF-16 编码后每个字符的高字节为 0，
+/// 零字节集中出现在奇数位（LE）或偶数位（BE）。至少要有一定比例的零字节且另一侧几乎没有，
+/// 避免把含少量 NUL 的二进制或 UTF-8 文本误判。
+fn guess_bomless_utf16(prefix: &[u8]) -> Option<&'static Encoding> {
+    let pairs = prefix.len() / 2;
+    if pairs < 4 {
+        return None;
+    }
+    let (mut even_zeros, mut odd_zeros) = (0usize, 0usize);
+    for pair in prefix.chunks_exact(2) {
+        even_zeros += usize::from(pair[0] == 0);
+        odd_zeros += usize::from(pair[1] == 0);
+    }
+    let dominant = |zeros: usize, other: usize| zeros * 10 >= pairs * 4 && other * 20 <= pairs;
+    if dominant(odd_zeros, even_zeros) {
+        Some(encoding_rs::UTF_16LE)
+    } else if dominant(even_zeros, odd_zeros) {
+        Some(encoding_rs::UTF_16BE)
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -134,6 +780,22 @@ mod tests {
         assert_eq!(lines, vec!["first\n", "second\n"]);
     }
 
+    #[tokio::test]
+    async fn read_lines_with_custom_delimiter() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("journal.log");
+        std::fs::write(&path, "first\0second\nstill second\0").unwrap();
+
+        let reader = FileReader::new(16 * 1024).with_line_delimiter(0);
+        let mut stream = reader.read_lines(&path).await.unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = stream.next().await {
+            lines.push(line.unwrap());
+        }
+
+        assert_eq!(lines, vec!["first\0", "second\nstill second\0"]);
+    }
+
     #[tokio::test]
     async fn read_gzip_lines() {
         let dir = tempdir().unwrap();
@@ -155,6 +817,213 @@ mod tests {
         assert_eq!(lines, vec!["gz-line-1\n", " gz-line-2\n"]);
     }
 
+    #[tokio::test]
+    async fn invalid_utf8_is_lossy_unless_strict() {
+        let dir = tempdir().unwrap();
+        // 编码检测只看开头 8 KiB（此处恰为整行）：前面是合法 UTF-8，坏字节出现在其后
+        let mut content = "héllo!\n".repeat(2000).into_bytes();
+        content.extend_from_slice(b"bad \xff\xfe\n");
+        let plain = dir.path().join("app.log");
+        std::fs::write(&plain, &content).unwrap();
+        let gz = dir.path().join("app.log.gz");
+        {
+            let file = std::fs::File::create(&gz).unwrap();
+            let mut enc = GzEncoder::new(file, Compression::default());
+            enc.write_all(&content).unwrap();
+            enc.finish().unwrap();
+        }
+
+        for path in [&plain, &gz] {
+            let lines: Vec<String> = FileReader::new(1024)
+                .read_lines(path)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(lines.len(), 2001, "{}", path.display());
+            assert_eq!(lines[2000], "bad \u{FFFD}\u{FFFD}\n", "{}", path.display());
+
+            let stream = FileReader::new(1024).with_strict_utf8(true).read_lines(path).await.unwrap();
+            let err = stream.try_collect::<Vec<String>>().await.unwrap_err();
+            assert!(matches!(err, LogSearchError::EncodingError { .. }), "{}", path.display());
+
+            // 从末尾读取（普通文件向前按块查找）与顺序读取的解码规则一致
+            let tail = FileReader::new(1024).read_tail(path, 1).await.unwrap();
+            assert_eq!(tail, vec!["bad \u{FFFD}\u{FFFD}\n"], "{}", path.display());
+            let err = FileReader::new(1024).with_strict_utf8(true).read_tail(path, 1).await.unwrap_err();
+            assert!(matches!(err, LogSearchError::EncodingError { .. }), "{}", path.display());
+        }
+    }
+
+    #[tokio::test]
+    async fn read_tail_splits_on_raw_delimiter_bytes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        // 0x80 在 windows-1252 中解码为 '€'，不能按解码后的字符切分
+        std::fs::write(&path, b"a\x80b\x80c\x80").unwrap();
+        let rule = EncodingOverride { glob: "*.log".into(), encoding: "windows-1252".into() };
+        let reader = FileReader::new(1024)
+            .with_line_delimiter(0x80)
+            .with_encoding_overrides(EncodingOverrides::new(&[rule]).unwrap());
+        assert_eq!(reader.read_tail(&path, 2).await.unwrap(), vec!["b€", "c€"]);
+    }
+
+    #[tokio::test]
+    async fn gzip_decoder_buffer_is_sized_independently() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sample.log.gz");
+        let expected: Vec<String> = (0..2000).map(|i| format!("line {i}\n")).collect();
+        {
+            let file = std::fs::File::create(&path).unwrap();
+            let mut enc = GzEncoder::new(file, Compression::default());
+            enc.write_all(expected.concat().as_bytes()).unwrap();
+            enc.finish().unwrap();
+        }
+
+        for decompress in [16, 256 * 1024] {
+            let reader = FileReader::new(64).with_decompress_buffer_size(decompress);
+            let mut stream = reader.read_lines(&path).await.unwrap();
+            let mut lines = Vec::new();
+            while let Some(line) = stream.next().await {
+                lines.push(line.unwrap());
+            }
+            assert_eq!(lines, expected, "decompress_buffer_size={decompress}");
+        }
+    }
+
+    #[tokio::test]
+    async fn gzip_is_decoded_incrementally() {
+        // 截断的大 gzip：若整体解压到内存，首行之前就会失败；流式解码则能先产出前面的行。
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("big.log.gz");
+        let mut enc = GzEncoder::new(Vec::new(), Compression::fast());
+        for i in 0..200_000 {
+            writeln!(enc, "line {i} padding padding padding padding").unwrap();
+        }
+        let compressed = enc.finish().unwrap();
+        std::fs::write(&path, &compressed[..compressed.len() / 2]).unwrap();
+
+        let reader = FileReader::new(16 * 1024);
+        let mut stream = reader.read_lines(&path).await.unwrap();
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first, "line 0 padding padding padding padding\n");
+
+        let mut read = 1usize;
+        while let Some(line) = stream.next().await {
+            line.unwrap();
+            read += 1;
+        }
+        assert!(read > 1000 && read < 200_000);
+    }
+
+    async fn collect(mut stream: BoxStream<'static, Result<String>>) -> Vec<String> {
+        let mut lines = Vec::new();
+        while let Some(line) = stream.next().await {
+            lines.push(line.unwrap());
+        }
+        lines
+    }
+
+    #[tokio::test]
+    async fn bgzip_index_seeks_to_block() {
+        // 每块一个 gzip 成员，按 bgzip 布局写 .gzi；随后破坏第一块，证明读取确实跳过了它。
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("big.log.gz");
+        let blocks: [&[u8]; 3] = [b"a1\na2\n", b"b1\nb2\n", b"c1\n"];
+        let mut data = Vec::new();
+        let mut index = Vec::new();
+        let mut uncompressed = 0u64;
+        for (i, block) in blocks.iter().enumerate() {
+            if i > 0 {
+                index.push((data.len() as u64, uncompressed));
+            }
+            let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+            enc.write_all(block).unwrap();
+            data.extend(enc.finish().unwrap());
+            uncompressed += block.len() as u64;
+        }
+        std::fs::write(&path, &data).unwrap();
+        let reader = FileReader::new(1024);
+        // 多成员文件顺序读取也能读完全部块
+        assert_eq!(collect(reader.read_lines(&path).await.unwrap()).await.len(), 5);
+        // 无索引时线性解压：偏移 4 落在 "a2\n" 中间，从下一条完整记录开始
+        assert_eq!(
+            collect(reader.read_lines_from(&path, 4).await.unwrap()).await,
+            vec!["b1\n", "b2\n", "c1\n"]
+        );
+
+        let mut gzi = (index.len() as u64).to_le_bytes().to_vec();
+        for (c, u) in &index {
+            gzi.extend(c.to_le_bytes());
+            gzi.extend(u.to_le_bytes());
+        }
+        std::fs::write(dir.path().join("big.log.gz.gzi"), gzi).unwrap();
+        data[10..index[0].0 as usize - 8].fill(0);
+        std::fs::write(&path, &data).unwrap();
+
+        assert_eq!(
+            collect(reader.read_lines_from(&path, 9).await.unwrap()).await,
+            vec!["b2\n", "c1\n"]
+        );
+        assert_eq!(collect(reader.read_lines_from(&path, 10).await.unwrap()).await, vec!["c1\n"]);
+    }
+
+    #[tokio::test]
+    async fn plain_file_offset_starts_at_next_record() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let reader = FileReader::new(1024);
+        assert_eq!(collect(reader.read_lines_from(&path, 4).await.unwrap()).await, vec!["two\n", "three\n"]);
+        assert_eq!(collect(reader.read_lines_from(&path, 5).await.unwrap()).await, vec!["three\n"]);
+    }
+
+    #[tokio::test]
+    async fn max_bytes_stops_after_budget() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let gz = dir.path().join("app.log.gz");
+        let mut enc = GzEncoder::new(std::fs::File::create(&gz).unwrap(), Compression::default());
+        enc.write_all(b"one\ntwo\nthree\n").unwrap();
+        enc.finish().unwrap();
+
+        // 跨越上限的行完整返回，之后不再读取
+        let reader = FileReader::new(1024).with_max_bytes(Some(5));
+        assert_eq!(collect(reader.read_lines(&path).await.unwrap()).await, vec!["one\n", "two\n"]);
+        assert_eq!(collect(reader.read_lines(&gz).await.unwrap()).await, vec!["one\n", "two\n"]);
+        let reader = reader.with_max_bytes(Some(4));
+        assert_eq!(collect(reader.read_lines(&path).await.unwrap()).await, vec!["one\n"]);
+    }
+
+    #[tokio::test]
+    async fn empty_and_truncated_files_yield_no_lines() {
+        let dir = tempdir().unwrap();
+        let empty_log = dir.path().join("empty.log");
+        let empty_gz = dir.path().join("empty.log.gz");
+        let truncated_gz = dir.path().join("truncated.log.gz");
+        std::fs::write(&empty_log, b"").unwrap();
+        std::fs::write(&empty_gz, b"").unwrap();
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(b"only line\n").unwrap();
+        let compressed = enc.finish().unwrap();
+        // 只保留 gzip 头部
+        std::fs::write(&truncated_gz, &compressed[..10]).unwrap();
+
+        let reader = FileReader::new(16 * 1024);
+        for path in [&empty_log, &empty_gz, &truncated_gz] {
+            let lines: Vec<String> = reader
+                .read_lines(path)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert!(lines.is_empty(), "{}", path.display());
+        }
+    }
+
     #[tokio::test]
     async fn detect_utf16_with_bom() {
         let dir = tempdir().unwrap();
@@ -175,4 +1044,119 @@ mod tests {
 
         assert_eq!(lines, vec!["你好UTF16\n", "第二行\n"]);
     }
+
+    #[tokio::test]
+    async fn detect_bomless_utf16() {
+        let dir = tempdir().unwrap();
+        let content = "2024-01-01 INFO 启动\n2024-01-01 ERROR failed\n";
+        let le = dir.path().join("le.log");
+        let be = dir.path().join("be.log");
+        std::fs::write(&le, content.encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<_>>()).unwrap();
+        std::fs::write(&be, content.encode_utf16().flat_map(u16::to_be_bytes).collect::<Vec<_>>()).unwrap();
+
+        let reader = FileReader::new(16 * 1024);
+        let expected = vec!["2024-01-01 INFO 启动\n", "2024-01-01 ERROR failed\n"];
+        assert_eq!(collect(reader.read_lines(&le).await.unwrap()).await, expected);
+        assert_eq!(collect(reader.read_lines(&be).await.unwrap()).await, expected);
+
+        // 普通文本与零星 NUL 不触发
+        assert!(guess_bomless_utf16(b"plain ascii log line\n").is_none());
+        assert!(guess_bomless_utf16(b"abc\0defghijklmnop\n").is_none());
+    }
+
+    #[tokio::test]
+    async fn encoding_overrides_apply_per_path() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("legacy")).unwrap();
+        let gbk = dir.path().join("legacy/app.log");
+        let utf8 = dir.path().join("app.log");
+        let content = "2024-01-01 ERROR 数据库连接失败\n";
+        std::fs::write(&gbk, encoding_rs::GBK.encode(content).0).unwrap();
+        std::fs::write(&utf8, content).unwrap();
+
+        let overrides = EncodingOverrides::new(&[EncodingOverride {
+            glob: "**/legacy/*.log".into(),
+            encoding: "gbk".into(),
+        }])
+        .unwrap();
+        let reader = FileReader::new(1024).with_encoding_overrides(overrides);
+        assert_eq!(collect(reader.read_lines(&gbk).await.unwrap()).await, vec![content]);
+        assert_eq!(collect(reader.read_lines(&utf8).await.unwrap()).await, vec![content]);
+
+        let unknown = EncodingOverride { glob: "*.log".into(), encoding: "klingon".into() };
+        assert!(EncodingOverrides::new(&[unknown]).is_err());
+    }
+
+    #[tokio::test]
+    async fn max_decompressed_bytes_rejects_gzip_bombs() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("bomb.log.gz");
+        {
+            let file = std::fs::File::create(&path).unwrap();
+            let mut enc = GzEncoder::new(file, Compression::best());
+            let line = [b'a'; 1023].iter().chain(b"\n").copied().collect::<Vec<u8>>();
+            for _ in 0..8 * 1024 {
+                enc.write_all(&line).unwrap();
+            }
+            enc.finish().unwrap();
+        }
+        assert!(std::fs::metadata(&path).unwrap().len() < 64 * 1024);
+
+        let reader = FileReader::new(16 * 1024).with_max_decompressed_bytes(Some(1024 * 1024));
+        let mut stream = reader.read_lines(&path).await.unwrap();
+        let mut lines = 0usize;
+        let err = loop {
+            match stream.next().await {
+                Some(Ok(_)) => lines += 1,
+                Some(Err(e)) => break e,
+                None => panic!("limit was not enforced"),
+            }
+        };
+        assert_eq!(lines, 1024);
+        assert!(err.to_string().contains("max_decompressed_bytes"));
+
+        // 按偏移读取时跳过的部分也计入上限，且计入读取字节数
+        let err = reader.read_lines_from(&path, 4 * 1024 * 1024).await.err().unwrap();
+        assert!(err.to_string().contains("max_decompressed_bytes"));
+        let before = reader.bytes_read();
+        let mut stream = reader.read_lines_from(&path, 1023 * 1024).await.unwrap();
+        assert!(stream.next().await.unwrap().is_ok());
+        assert!(matches!(stream.next().await, Some(Err(_))));
+        assert!(reader.bytes_read() - before >= 1023 * 1024);
+
+        // 恰好等于上限时正常读完
+        let reader = reader.with_max_decompressed_bytes(Some(8 * 1024 * 1024));
+        assert_eq!(collect(reader.read_lines(&path).await.unwrap()).await.len(), 8 * 1024);
+    }
+
+    #[tokio::test]
+    async fn read_tail_keeps_last_records() {
+        let dir = tempdir().unwrap();
+        let content: String = (1..=50).map(|i| format!("line {i}\n")).collect();
+        let plain = dir.path().join("app.log");
+        let mut with_bom = vec![0xEF, 0xBB, 0xBF];
+        with_bom.extend_from_slice(content.as_bytes());
+        std::fs::write(&plain, &with_bom).unwrap();
+        let gz = dir.path().join("app.log.gz");
+        {
+            let mut enc = GzEncoder::new(std::fs::File::create(&gz).unwrap(), Compression::default());
+            enc.write_all(content.as_bytes()).unwrap();
+            enc.finish().unwrap();
+        }
+
+        // 小缓冲使向前查找跨越多个块
+        let reader = FileReader::new(7);
+        let expected = vec!["line 48\n", "line 49\n", "line 50\n"];
+        assert_eq!(reader.read_tail(&plain, 3).await.unwrap(), expected);
+        assert_eq!(reader.read_tail(&gz, 3).await.unwrap(), expected);
+        // 行数超过文件时返回全部，且不含 BOM
+        let all = reader.read_tail(&plain, 100).await.unwrap();
+        assert_eq!(all.len(), 50);
+        assert_eq!(all[0], "line 1\n");
+
+        // 最后一条记录没有分隔符
+        std::fs::write(&plain, "a\nb\nc").unwrap();
+        assert_eq!(reader.read_tail(&plain, 2).await.unwrap(), vec!["b\n", "c"]);
+        assert!(reader.read_tail(&plain, 0).await.unwrap().is_empty());
+    }
 }
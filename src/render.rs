@@ -0,0 +1,183 @@
+use crate::model::HitResult;
+
+/// 命中的纯文本渲染（用于 MCP 的 `text` 内容，结构化 JSON 不经过这里）。
+/// 逐条输出：`文件:起始行` 标题行，随后是前置上下文、命中条目与后置上下文，
+/// 相邻命中之间插入一行 `separator`。
+pub fn render_hits(hits: &[&HitResult], separator: &str) -> String {
+    let blocks: Vec<String> = hits.iter().map(|hit| render_hit(hit)).collect();
+    blocks.join(&format!("{separator}\n"))
+}
+
+fn render_hit(hit: &HitResult) -> String {
+    let mut out = format!("{}:{}\n", hit.file_path.display(), hit.start_line);
+    let entries = hit
+        .context_before
+        .iter()
+        .map(|c| c.content.as_str())
+        .chain(std::iter::once(hit.content.as_str()))
+        .chain(hit.context_after.iter().map(|c| c.content.as_str()));
+    for content in entries {
+        out.push_str(content.trim_end_matches(['\r', '\n']));
+        out.push('\n');
+    }
+    out
+}
+
+/// `grep -n --heading` 风格的渲染：命中按文件首次出现的顺序分组，每组以文件路径为标题，
+/// 组内按行号排序（与 `sort_by` 无关），组之间空一行；条目逐物理行输出，命中行为 `行号:内容`，上下文行为 `行号-内容`，
+/// 行号不连续处插入 `--`。
+pub fn render_grep(hits: &[&HitResult]) -> String {
+    let mut files: Vec<(&std::path::Path, Vec<&HitResult>)> = Vec::new();
+    for hit in hits {
+        match files.iter_mut().find(|(path, _)| *path == hit.file_path) {
+            Some((_, group)) => group.push(hit),
+            None => files.push((&hit.file_path, vec![hit])),
+        }
+    }
+
+    let mut blocks = Vec::with_capacity(files.len());
+    for (path, mut group) in files {
+        // 按相关度等排序时同一文件的命中可能乱序，按行号重排才能正确合并重叠的上下文
+        group.sort_by_key(|hit| hit.start_line);
+        let mut out = format!("{}\n", path.display());
+        let mut next_line: Option<usize> = None;
+        for hit in group {
+            let entries = hit
+                .context_before
+                .iter()
+                .map(|c| (c.start_line, c.content.as_str(), '-'))
+                .chain(std::iter::once((
+                    hit.content_start_line.unwrap_or(hit.start_line),
+                    hit.content.as_str(),
+                    ':',
+                )))
+                .chain(hit.context_after.iter().map(|c| (c.start_line, c.content.as_str(), '-')));
+            for (start, content, marker) in entries {
+                for (i, line) in content.trim_end_matches(['\r', '\n']).split('\n').enumerate() {
+                    let number = start + i;
+                    // 相邻命中的上下文可能重叠，已输出过的行不再重复
+                    if next_line.is_some_and(|next| number < next) {
+                        continue;
+                    }
+                    if next_line.is_some_and(|next| number > next) {
+                        out.push_str("--\n");
+                    }
+                    out.push_str(&format!("{number}{marker}{}\n", line.trim_end_matches('\r')));
+                    next_line = Some(number + 1);
+                }
+            }
+        }
+        blocks.push(out);
+    }
+    blocks.join("\n")
+}
+
+/// 命中的 CSV 表示，列为 `file_path,start_line,end_line,content`，首行为表头。
+/// 含逗号、引号或换行的字段用双引号包裹，内部引号写作两个双引号（RFC 4180）。
+pub fn hits_to_csv(hits: &[&HitResult]) -> String {
+    let mut out = String::from("file_path,start_line,end_line,content\r\n");
+    for hit in hits {
+        let fields = [
+            csv_field(&hit.file_path.to_string_lossy()),
+            hit.start_line.to_string(),
+            hit.end_line.to_string(),
+            csv_field(hit.content.trim_end_matches(['\r', '\n'])),
+        ];
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ContextEntry;
+
+    fn hit(line: usize, content: &str) -> HitResult {
+        HitResult {
+            file_path: "app.log".into(),
+            start_line: line,
+            end_line: line,
+            content: content.to_string(),
+            match_positions: Vec::new(),
+            timestamp: None,
+            context_before: Vec::new(),
+            context_after: Vec::new(),
+            matched_terms: Vec::new(),
+            content_start_line: None,
+            score: 0,
+        }
+    }
+
+    #[test]
+    fn separator_goes_between_hits_with_context() {
+        let mut first = hit(2, "ERROR a\n");
+        first.context_before.push(ContextEntry {
+            start_line: 1,
+            end_line: 1,
+            content: "INFO before".into(),
+        });
+        let second = hit(9, "ERROR b");
+        assert_eq!(
+            render_hits(&[&first, &second], "--"),
+            "app.log:2\nINFO before\nERROR a\n--\napp.log:9\nERROR b\n"
+        );
+        assert_eq!(render_hits(&[], "--"), "");
+    }
+
+    #[test]
+    fn grep_render_groups_by_file_with_line_gutters() {
+        let mut first = hit(2, "ERROR a\n  at frame\n");
+        first.end_line = 3;
+        first.context_before.push(ContextEntry {
+            start_line: 1,
+            end_line: 1,
+            content: "INFO before\n".into(),
+        });
+        let second = hit(9, "ERROR b\n");
+        let mut other = hit(4, "ERROR c\n");
+        other.file_path = "db.log".into();
+        assert_eq!(
+            render_grep(&[&first, &other, &second]),
+            "app.log\n1-INFO before\n2:ERROR a\n3:  at frame\n--\n9:ERROR b\n\ndb.log\n4:ERROR c\n"
+        );
+        assert_eq!(render_grep(&[]), "");
+    }
+
+    #[test]
+    fn grep_render_orders_hits_within_a_file() {
+        let late = hit(9, "ERROR late\n");
+        let mut early = hit(2, "ERROR early\n");
+        early.context_after.push(ContextEntry {
+            start_line: 3,
+            end_line: 3,
+            content: "INFO after\n".into(),
+        });
+        assert_eq!(
+            render_grep(&[&late, &early]),
+            "app.log\n2:ERROR early\n3-INFO after\n--\n9:ERROR late\n"
+        );
+    }
+
+    #[test]
+    fn csv_quotes_embedded_separators() {
+        let plain = hit(3, "ok line\n");
+        let multi = hit(5, "ERROR \"db\", retrying\n  at frame\n");
+        let csv = hits_to_csv(&[&plain, &multi]);
+        assert_eq!(
+            csv,
+            "file_path,start_line,end_line,content\r\n\
+             app.log,3,3,ok line\r\n\
+             app.log,5,5,\"ERROR \"\"db\"\", retrying\n  at frame\"\r\n"
+        );
+    }
+}
@@ -1,20 +1,44 @@
 use std::path::{Path, PathBuf};
 
 use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::{Regex, RegexBuilder};
 use walkdir::WalkDir;
 
 use crate::error::{LogSearchError, Result};
-use crate::model::FileScanConfig;
+use crate::model::{ContentTypeFilter, FileExplanation, FileScanConfig, ScanOrder, SearchQuery};
+use crate::reader::looks_like_text;
 
 /// 文件扫描器：根据包含/排除 globs 递归收集日志文件。
-#[derive(Clone, Default)]
-pub struct FileScanner;
+///
+/// 遍历 `root_path` 时根目录本身可以是符号链接；其下的符号链接文件一律跳过，
+/// 符号链接目录只在 `follow_symlinked_dirs` 开启时进入。
+#[derive(Clone, Copy, Default)]
+pub struct FileScanner {
+    follow_symlinked_dirs: bool,
+}
+
+pub(crate) const DEFAULT_INCLUDE_GLOBS: &[&str] = &["**/*.log", "**/*.log.gz", "**/*.gz"];
 
-const DEFAULT_INCLUDE_GLOBS: &[&str] = &["**/*.log", "**/*.log.gz", "**/*.gz"];
+/// 请求未给出排除模式、配置也未覆盖时跳过的目录。
+pub(crate) const DEFAULT_EXCLUDE_GLOBS: &[&str] = &["**/node_modules/**", "**/.git/**"];
+
+/// 扫描结果：匹配的文件及遍历中无法访问的子路径。
+#[derive(Debug, Default)]
+pub struct ScanOutcome {
+    pub files: Vec<PathBuf>,
+    pub errors: Vec<(PathBuf, String)>,
+}
 
 impl FileScanner {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// 遍历 `root_path` 时是否进入指向其他位置的符号链接目录。
+    pub fn follow_symlinked_dirs(self, follow: bool) -> Self {
+        Self {
+            follow_symlinked_dirs: follow,
+        }
     }
 
     pub fn scan(&self, config: &FileScanConfig) -> Result<Vec<PathBuf>> {
@@ -26,7 +50,20 @@ impl FileScanner {
         config: &FileScanConfig,
         explicit_paths: &Option<Vec<String>>,
     ) -> Result<Vec<PathBuf>> {
+        self.scan_detailed(config, explicit_paths).map(|o| o.files)
+    }
+
+    /// 与 `scan_with_paths` 相同，但同时返回遍历中无法读取的子目录/文件。
+    /// 根目录本身不可读时直接返回 `FileAccessError`。
+    pub fn scan_detailed(
+        &self,
+        config: &FileScanConfig,
+        explicit_paths: &Option<Vec<String>>,
+    ) -> Result<ScanOutcome> {
+        let config = &config.expanded();
+        let name_filter = NameFilter::new(config.filename_query.as_ref())?;
         let mut files = Vec::new();
+        let mut errors = Vec::new();
         
         // Debug log
         use std::io::Write;
@@ -58,19 +95,42 @@ impl FileScanner {
                 if exists {
                      // 简单地检查是否存在，不强制检查是否是 file (可能是 symlink)
                      // 但我们还是希望只处理文件。
-                     if is_file {
+                     if is_file && name_filter.accepts(&p) {
                         files.push(p);
                      }
                 }
             }
         }
 
-        if config.root_path.as_os_str().is_empty() {
-            return Ok(files);
+        if !config.root_path.as_os_str().is_empty() {
+            self.walk_root(config, &name_filter, &mut files, &mut errors)?;
+            files.sort();
+            files.dedup();
+        }
+
+        if config.content_type_filter == ContentTypeFilter::TextOnly {
+            files.retain(|path| match looks_like_text(path) {
+                Ok(is_text) => is_text,
+                Err(e) => {
+                    errors.push((path.clone(), e.to_string()));
+                    false
+                }
+            });
         }
+        Ok(ScanOutcome { files, errors })
+    }
 
+    /// 递归遍历 `root_path`，收集匹配包含/排除模式与文件名过滤的文件。
+    fn walk_root(
+        &self,
+        config: &FileScanConfig,
+        name_filter: &NameFilter,
+        files: &mut Vec<PathBuf>,
+        errors: &mut Vec<(PathBuf, String)>,
+    ) -> Result<()> {
         let include_fallback: Vec<String>;
-        let include_slice: &[String] = if config.include_globs.is_empty() {
+        // 按文件名查询时不再套用默认的扩展名集合
+        let include_slice: &[String] = if config.include_globs.is_empty() && config.filename_query.is_none() {
             include_fallback = DEFAULT_INCLUDE_GLOBS
                 .iter()
                 .map(|s| s.to_string())
@@ -83,11 +143,23 @@ impl FileScanner {
         let include = build_globset(include_slice)?;
         let exclude = build_globset(&config.exclude_globs)?;
         
-        for entry in WalkDir::new(&config.root_path)
-            .into_iter()
-            .filter_map(std::result::Result::ok)
-        {
-            if !entry.file_type().is_file() {
+        for entry in WalkDir::new(&config.root_path).follow_links(self.follow_symlinked_dirs) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let path = e.path().unwrap_or(&config.root_path).to_path_buf();
+                    if e.depth() == 0 {
+                        return Err(LogSearchError::FileAccessError {
+                            path,
+                            reason: e.to_string(),
+                        });
+                    }
+                    errors.push((path, e.to_string()));
+                    continue;
+                }
+            };
+            // 跟随链接时 file_type 是目标的类型，需另行排除符号链接文件
+            if !entry.file_type().is_file() || (entry.depth() > 0 && entry.path_is_symlink()) {
                 continue;
             }
 
@@ -95,25 +167,265 @@ impl FileScanner {
             if !exclude.is_empty() && matches(&exclude, path) {
                 continue;
             }
-            if include.is_empty() || matches(&include, path) {
+            if (include.is_empty() || matches(&include, path)) && name_filter.accepts(path) {
                 files.push(path.to_path_buf());
             }
         }
+        Ok(())
+    }
+}
+
+impl FileScanner {
+    /// 逐项执行扫描时的检查，解释某个文件为何会或不会被扫描。
+    pub fn explain(
+        &self,
+        path: &Path,
+        config: &FileScanConfig,
+        explicit_paths: &Option<Vec<String>>,
+    ) -> Result<FileExplanation> {
+        let config = &config.expanded();
+        let name_filter = NameFilter::new(config.filename_query.as_ref())?;
+        let path = &crate::config::expand_path(path);
+        let exists = path.exists();
+        let is_file = path.is_file();
+        let mut reasons = Vec::new();
+
+        let in_log_file_paths = explicit_paths
+            .as_ref()
+            .map(|paths| paths.iter().any(|p| Path::new(p) == path))
+            .unwrap_or(false);
+
+        let has_root = !config.root_path.as_os_str().is_empty();
+        let under_root = has_root && is_under(path, &config.root_path);
+
+        let default_includes: Vec<String> =
+            DEFAULT_INCLUDE_GLOBS.iter().map(|s| s.to_string()).collect();
+        let no_includes = Vec::new();
+        let include_patterns = match (config.include_globs.is_empty(), config.filename_query.is_some()) {
+            (true, false) => &default_includes,
+            (true, true) => &no_includes,
+            (false, _) => &config.include_globs,
+        };
+        let matched_include_globs = matching_globs(include_patterns, path)?;
+        let name_matched = name_filter.accepts(path);
+        let matched_exclude_globs = matching_globs(&config.exclude_globs, path)?;
+        let extension_recognized = !matching_globs(&default_includes, path)?.is_empty();
+
+        if !exists {
+            reasons.push("path does not exist".to_string());
+        } else if !is_file {
+            reasons.push("path is not a regular file".to_string());
+        }
+
+        if !name_matched {
+            reasons.push("file name does not match filename_query".to_string());
+        }
+
+        let via_explicit = in_log_file_paths && exists && is_file && name_matched;
+        if via_explicit {
+            reasons.push("listed in log_sources.log_file_paths".to_string());
+        }
+
+        let mut via_root = false;
+        if !has_root {
+            reasons.push("scan_config.root_path is empty; only log_file_paths are used".to_string());
+        } else if !under_root {
+            reasons.push(format!("not under root_path {:?}", config.root_path));
+        } else if let Some(reason) = self.symlink_reason(path, &config.root_path) {
+            reasons.push(reason);
+        } else if !matched_exclude_globs.is_empty() {
+            reasons.push(format!("excluded by {:?}", matched_exclude_globs));
+        } else if !include_patterns.is_empty() && matched_include_globs.is_empty() {
+            reasons.push(format!("no include glob matched (checked {:?})", include_patterns));
+        } else if exists && is_file && name_matched {
+            via_root = true;
+            if include_patterns.is_empty() {
+                reasons.push("file name matched filename_query under root_path".to_string());
+            } else {
+                reasons.push(format!("matched include globs {:?} under root_path", matched_include_globs));
+            }
+        }
+
+        if !extension_recognized {
+            reasons.push("extension is not one of the default log types (.log, .gz)".to_string());
+        }
+
+        let mut content_rejected = false;
+        if config.content_type_filter == ContentTypeFilter::TextOnly && (via_explicit || via_root) {
+            match looks_like_text(path) {
+                Ok(true) => {}
+                Ok(false) => {
+                    content_rejected = true;
+                    reasons.push("content does not look like text (content_type_filter: text_only)".to_string());
+                }
+                Err(e) => {
+                    content_rejected = true;
+                    reasons.push(format!("content could not be read for content_type_filter: {e}"));
+                }
+            }
+        }
+
+        Ok(FileExplanation {
+            path: path.to_path_buf(),
+            exists,
+            is_file,
+            in_log_file_paths,
+            under_root,
+            matched_include_globs,
+            matched_exclude_globs,
+            extension_recognized,
+            included: (via_explicit || via_root) && !content_rejected,
+            reasons,
+        })
+    }
+}
+
+impl FileScanner {
+    /// 遍历 `root` 时不会经由的符号链接：文件本身是链接，或路径中间经过未允许跟随的链接目录。
+    fn symlink_reason(&self, path: &Path, root: &Path) -> Option<String> {
+        let is_symlink = |p: &Path| p.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink());
+        if path != root && is_symlink(path) {
+            return Some("path is a symbolic link; symlinked files under root_path are skipped".to_string());
+        }
+        if self.follow_symlinked_dirs {
+            return None;
+        }
+        path.ancestors()
+            .skip(1)
+            .take_while(|dir| *dir != root && dir.starts_with(root))
+            .find(|dir| is_symlink(dir))
+            .map(|dir| {
+                format!(
+                    "reached through symlinked directory {:?}; set log_sources.allow_symlinked_dirs to follow it",
+                    dir
+                )
+            })
+    }
+}
+
+/// 轮转片段在组内的先后：日期后缀按日期升序，数字后缀越大越旧，无后缀的当前文件最新。
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum RotationAge {
+    Dated(String),
+    Numbered(std::cmp::Reverse<u64>),
+    Current,
+}
+
+/// 拆出轮转组名与组内先后：去掉 `.gz`，再去掉 `.N` 或 `-YYYYMMDD` 后缀。
+fn rotation_key(path: &Path) -> (PathBuf, RotationAge) {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = name.strip_suffix(".gz").unwrap_or(&name);
+    let parent = path.parent().unwrap_or(Path::new(""));
+    if let Some((base, n)) = name.rsplit_once('.') {
+        if let Ok(n) = n.parse::<u64>() {
+            return (parent.join(base), RotationAge::Numbered(std::cmp::Reverse(n)));
+        }
+    }
+    if let Some((base, date)) = name.rsplit_once('-') {
+        if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) {
+            return (parent.join(base), RotationAge::Dated(date.to_string()));
+        }
+    }
+    (parent.join(name), RotationAge::Current)
+}
+
+/// 按轮转组排序：组按组名排列，组内从最旧片段到当前文件。
+pub(crate) fn order_rotations(files: &mut [PathBuf]) {
+    files.sort_by_cached_key(|p| rotation_key(p));
+}
+
+/// 按 `order` 重排扫描结果（输入已按路径排序）；修改时间相同时保持路径顺序，
+/// 无法读取修改时间的文件排在最后。
+pub(crate) fn order_by_scan_order(files: &mut [PathBuf], order: ScanOrder) {
+    let mtime = |p: &PathBuf| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    match order {
+        ScanOrder::PathAsc => {}
+        ScanOrder::MtimeDesc => files.sort_by_cached_key(|p| std::cmp::Reverse(mtime(p))),
+        ScanOrder::MtimeAsc => files.sort_by_cached_key(|p| {
+            let t = mtime(p);
+            (t.is_none(), t)
+        }),
+    }
+}
+
+/// `filename_query` 编译后的文件名过滤器，沿用 SearchQuery 的匹配语义，只匹配文件名部分。
+enum NameFilter {
+    All,
+    Regex(Regex),
+    Text { needle: String, case_sensitive: bool },
+}
+
+impl NameFilter {
+    fn new(query: Option<&SearchQuery>) -> Result<Self> {
+        let Some((q, pattern)) = query.and_then(|q| Some((q, q.query.as_ref()?))) else {
+            return Ok(Self::All);
+        };
+        if q.regex || q.whole_word || q.whole_line {
+            let source = match (q.regex, q.whole_line) {
+                (true, true) => format!("^(?:{pattern})$"),
+                (true, false) => pattern.clone(),
+                (false, true) => format!("^{}$", regex::escape(pattern)),
+                (false, false) => format!(r"\b{}\b", regex::escape(pattern)),
+            };
+            let re = RegexBuilder::new(&source)
+                .case_insensitive(!q.case_sensitive)
+                .build()
+                .map_err(|e| LogSearchError::InvalidRequest(format!("invalid filename_query: {e}")))?;
+            return Ok(Self::Regex(re));
+        }
+        Ok(Self::Text {
+            needle: if q.case_sensitive {
+                pattern.clone()
+            } else {
+                pattern.to_lowercase()
+            },
+            case_sensitive: q.case_sensitive,
+        })
+    }
+
+    fn accepts(&self, path: &Path) -> bool {
+        let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        match self {
+            Self::All => true,
+            Self::Regex(re) => re.is_match(&name),
+            Self::Text { needle, case_sensitive: true } => name.contains(needle.as_str()),
+            Self::Text { needle, case_sensitive: false } => name.to_lowercase().contains(needle.as_str()),
+        }
+    }
+}
+
+fn is_under(path: &Path, root: &Path) -> bool {
+    if path.starts_with(root) {
+        return true;
+    }
+    match (std::fs::canonicalize(path), std::fs::canonicalize(root)) {
+        (Ok(p), Ok(r)) => p.starts_with(r),
+        _ => false,
+    }
+}
 
-        files.sort();
-        files.dedup();
-        Ok(files)
+fn matching_globs(patterns: &[String], path: &Path) -> Result<Vec<String>> {
+    let mut matched = Vec::new();
+    for pat in patterns {
+        let glob = Glob::new(pat).map_err(|e| LogSearchError::ConfigError(e.to_string()))?;
+        let mut builder = GlobSetBuilder::new();
+        builder.add(glob);
+        let set = builder
+            .build()
+            .map_err(|e| LogSearchError::ConfigError(e.to_string()))?;
+        if matches(&set, path) {
+            matched.push(pat.clone());
+        }
     }
+    Ok(matched)
 }
 
 fn build_globset(patterns: &[String]) -> Result<GlobSet> {
     if patterns.is_empty() {
         let builder = GlobSetBuilder::new();
-        return Ok(
-            builder
-                .build()
-                .map_err(|e| LogSearchError::ConfigError(e.to_string()))?,
-        );
+        return builder
+            .build()
+            .map_err(|e| LogSearchError::ConfigError(e.to_string()));
     }
 
     let mut builder = GlobSetBuilder::new();
@@ -164,6 +476,9 @@ mod tests {
             root_path: root.to_path_buf(),
             include_globs: Vec::new(),
             exclude_globs: vec!["**/skip/**".to_string()],
+            filename_query: None,
+            content_type_filter: Default::default(),
+            line_range: None,
         };
 
         let mut paths = FileScanner::new().scan(&cfg).unwrap();
@@ -175,4 +490,239 @@ mod tests {
         assert!(!paths.contains(&drop_txt));
         assert!(!paths.contains(&skip_log));
     }
+
+    #[test]
+    fn text_only_skips_binary_content() {
+        use std::io::Write;
+
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("app.log"), "2024-01-01 INFO started\n").unwrap();
+        std::fs::write(root.join("empty.log"), "").unwrap();
+        std::fs::write(root.join("core.log"), [0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0xff, 0xfe]).unwrap();
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all("\u{FEFF}ERROR 数据库连接失败\n".as_bytes()).unwrap();
+        std::fs::write(root.join("old.log.gz"), gz.finish().unwrap()).unwrap();
+
+        let mut cfg = FileScanConfig {
+            root_path: root.to_path_buf(),
+            include_globs: Vec::new(),
+            exclude_globs: Vec::new(),
+            filename_query: None,
+            content_type_filter: ContentTypeFilter::Any,
+            line_range: None,
+        };
+        assert_eq!(FileScanner::new().scan(&cfg).unwrap().len(), 4);
+
+        cfg.content_type_filter = ContentTypeFilter::TextOnly;
+        let names: Vec<String> = FileScanner::new()
+            .scan(&cfg)
+            .unwrap()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["app.log", "empty.log", "old.log.gz"]);
+
+        let explanation = FileScanner::new().explain(&root.join("core.log"), &cfg, &None).unwrap();
+        assert!(!explanation.included);
+        assert!(explanation.reasons.iter().any(|r| r.contains("text_only")));
+    }
+
+    #[test]
+    fn filename_query_matches_names_regardless_of_extension() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let nested = root.join("svc");
+        std::fs::create_dir_all(&nested).unwrap();
+        let audit_txt = nested.join("Audit-2024.txt");
+        let audit_log = root.join("audit.log");
+        let app_log = root.join("app.log");
+        touch(&audit_txt);
+        touch(&audit_log);
+        touch(&app_log);
+
+        let mut cfg = FileScanConfig {
+            root_path: root.to_path_buf(),
+            include_globs: Vec::new(),
+            exclude_globs: Vec::new(),
+            filename_query: Some(SearchQuery {
+                query: Some(r"^audit".to_string()),
+                regex: true,
+                case_sensitive: false,
+                whole_word: false,
+                whole_line: false,
+                per_physical_line: false,
+            }),
+            content_type_filter: Default::default(),
+            line_range: None,
+        };
+        let scanner = FileScanner::new();
+        // 只匹配文件名：目录名 svc 不参与，.txt 不受默认扩展名限制
+        assert_eq!(scanner.scan(&cfg).unwrap(), vec![audit_log.clone(), audit_txt.clone()]);
+        assert!(!scanner.explain(&app_log, &cfg, &None).unwrap().included);
+
+        cfg.include_globs = vec!["**/*.log".to_string()];
+        assert_eq!(scanner.scan(&cfg).unwrap(), vec![audit_log]);
+
+        cfg.filename_query.as_mut().unwrap().query = Some("(".to_string());
+        assert!(scanner.scan(&cfg).is_err());
+    }
+
+    #[test]
+    fn rotations_are_ordered_oldest_first_within_group() {
+        let mut files: Vec<PathBuf> = [
+            "/logs/app.log",
+            "/logs/db.log.1",
+            "/logs/app.log.1",
+            "/logs/app.log.10.gz",
+            "/logs/app.log.2.gz",
+            "/logs/db.log",
+            "/logs/web.log-20240102",
+            "/logs/web.log-20240101.gz",
+            "/logs/web.log",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        order_rotations(&mut files);
+        let names: Vec<&str> = files.iter().map(|p| p.to_str().unwrap()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "/logs/app.log.10.gz",
+                "/logs/app.log.2.gz",
+                "/logs/app.log.1",
+                "/logs/app.log",
+                "/logs/db.log.1",
+                "/logs/db.log",
+                "/logs/web.log-20240101.gz",
+                "/logs/web.log-20240102",
+                "/logs/web.log",
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_order_sorts_by_modification_time() {
+        let dir = tempdir().unwrap();
+        let base = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let mut files = Vec::new();
+        for (name, age_secs) in [("a.log", 60), ("b.log", 0), ("c.log", 3600)] {
+            let path = dir.path().join(name);
+            std::fs::File::create(&path)
+                .unwrap()
+                .set_modified(base - std::time::Duration::from_secs(age_secs))
+                .unwrap();
+            files.push(path);
+        }
+        let missing = dir.path().join("d.log");
+        files.push(missing.clone());
+        let names = |files: &[PathBuf]| -> Vec<String> {
+            files.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect()
+        };
+
+        order_by_scan_order(&mut files, ScanOrder::MtimeDesc);
+        assert_eq!(names(&files), ["b.log", "a.log", "c.log", "d.log"]);
+        order_by_scan_order(&mut files, ScanOrder::MtimeAsc);
+        assert_eq!(names(&files), ["c.log", "a.log", "b.log", "d.log"]);
+    }
+
+    #[test]
+    fn explain_reports_exclusion_and_missing_include() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let skip_dir = root.join("skip");
+        std::fs::create_dir_all(&skip_dir).unwrap();
+        let skipped = skip_dir.join("d.log");
+        let text = root.join("c.txt");
+        let kept = root.join("a.log");
+        touch(&skipped);
+        touch(&text);
+        touch(&kept);
+
+        let cfg = FileScanConfig {
+            root_path: root.to_path_buf(),
+            include_globs: Vec::new(),
+            exclude_globs: vec!["**/skip/**".to_string()],
+            filename_query: None,
+            content_type_filter: Default::default(),
+            line_range: None,
+        };
+        let scanner = FileScanner::new();
+
+        let why = scanner.explain(&skipped, &cfg, &None).unwrap();
+        assert!(!why.included);
+        assert_eq!(why.matched_exclude_globs, vec!["**/skip/**".to_string()]);
+
+        let why = scanner.explain(&text, &cfg, &None).unwrap();
+        assert!(!why.included);
+        assert!(why.matched_include_globs.is_empty());
+        assert!(!why.extension_recognized);
+
+        let why = scanner.explain(&kept, &cfg, &None).unwrap();
+        assert!(why.included);
+        assert!(why.under_root);
+
+        let explicit = Some(vec![text.to_string_lossy().to_string()]);
+        let why = scanner.explain(&text, &cfg, &explicit).unwrap();
+        assert!(why.included);
+        assert!(why.in_log_file_paths);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_under_root_follow_configured_policy() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        let outside = dir.path().join("outside");
+        std::fs::create_dir_all(root.join("real")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        touch(&root.join("real/a.log"));
+        touch(&outside.join("b.log"));
+        symlink(&outside, root.join("linked")).unwrap();
+        symlink(outside.join("b.log"), root.join("linked_file.log")).unwrap();
+        let root_link = dir.path().join("root_link");
+        symlink(&root, &root_link).unwrap();
+
+        let cfg = |root_path: &Path| FileScanConfig {
+            root_path: root_path.to_path_buf(),
+            include_globs: Vec::new(),
+            exclude_globs: Vec::new(),
+            filename_query: None,
+            content_type_filter: Default::default(),
+            line_range: None,
+        };
+        let strict = FileScanner::new();
+        let follow = FileScanner::new().follow_symlinked_dirs(true);
+
+        // 默认：只收集真实目录下的真实文件
+        assert_eq!(strict.scan(&cfg(&root)).unwrap(), vec![root.join("real/a.log")]);
+        // 允许后进入链接目录，但链接文件仍跳过
+        assert_eq!(
+            follow.scan(&cfg(&root)).unwrap(),
+            vec![root.join("linked/b.log"), root.join("real/a.log")]
+        );
+        // 根目录本身是符号链接时两种策略都会解析
+        assert_eq!(strict.scan(&cfg(&root_link)).unwrap(), vec![root_link.join("real/a.log")]);
+        assert_eq!(follow.scan(&cfg(&root_link)).unwrap().len(), 2);
+
+        // why_file 与实际扫描结论一致
+        let via_dir = root.join("linked/b.log");
+        let why = strict.explain(&via_dir, &cfg(&root), &None).unwrap();
+        assert!(!why.included);
+        assert!(why.reasons.iter().any(|r| r.contains("symlinked directory")));
+        assert!(follow.explain(&via_dir, &cfg(&root), &None).unwrap().included);
+        for scanner in [strict, follow] {
+            let why = scanner.explain(&root.join("linked_file.log"), &cfg(&root), &None).unwrap();
+            assert!(!why.included);
+            assert!(scanner.explain(&root_link.join("real/a.log"), &cfg(&root_link), &None).unwrap().included);
+        }
+
+        // log_file_paths 中显式列出的链接文件照常使用
+        let explicit = Some(vec![root.join("linked_file.log").to_string_lossy().to_string()]);
+        let files = strict.scan_with_paths(&cfg(Path::new("")), &explicit).unwrap();
+        assert_eq!(files, vec![root.join("linked_file.log")]);
+    }
 }
@@ -1,41 +1,94 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::future::Future;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::Instant;
 
-use futures::{stream, Stream, StreamExt};
-use tokio::time::{timeout, Duration};
+use chrono::{DateTime, Timelike, Utc};
+use chrono_tz::Tz;
+use futures::stream::BoxStream;
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Serialize;
+use tokio::time::Duration;
 use tracing::{error, warn};
 
-use crate::error::Result;
-use crate::model::{HitResult, MatchPosition, SearchRequest, SearchResponse, TimeFilter};
+use crate::error::{LogSearchError, Result};
+use crate::model::{
+    group_hits_by_file, ContextEntry, DistinctValues, ErrorMode, ErrorSummary, FileScanConfig, HourCount, PreparedSearchInfo, RegexErrorPosition, RegexSampleMatch, RegexTestResult, HitResult, LogEntry, LogicalQuery, MatchEstimate, MatchPosition, RefineMode, SearchDiff, SearchRequest, SearchResponse, SortBy, TailPosition,
+    TimeFilter, TimingBreakdown, ValueCount, MAX_TEMPLATE_GROUPS, SEARCH_RESPONSE_FIELDS,
+};
 use crate::parser::LogParser;
-use crate::query::{QueryProcessor, ParsedTimeFilter};
-use crate::reader::FileReader;
-use crate::scanner::FileScanner;
+use crate::query::{
+    format_output_time, in_time_range, nfc, parse_output_tz, LevelFilter, NormalizedText, NumericFilter, strip_ansi,
+    MatchContext, ParsedTimeFilter, QueryProcessor, DEFAULT_LEVEL_ORDER, DEFAULT_TIMESTAMP_REGEX,
+};
+use crate::diff::{diff_groups, ContentNormalizer};
+use crate::reader::{EncodingOverrides, FileReader, SharedReadBudget};
+use crate::scanner::{FileScanner, ScanOutcome};
+use crate::session::{CursorState, HitRef, PreparedSearch, ResultSet, SessionManager, DEFAULT_SESSION};
 
 use std::sync::{Arc, RwLock};
 use crate::config::Config;
 
-fn parse_time_filter(tf: &crate::model::TimeFilter) -> ParsedTimeFilter {
-    let parse_dt = |s: &str| -> Option<chrono::DateTime<chrono::Utc>> {
-        // 优先尝试 RFC3339 格式
-        if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(s) {
-            return Some(dt.with_timezone(&chrono::Utc));
-        }
-        // 尝试用空格代替 T
-        let normalized = s.replace('T', " ");
-        if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(&normalized, "%Y-%m-%d %H:%M:%S") {
-             return Some(chrono::DateTime::from_utc(dt, chrono::Utc));
-        }
-        if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(&normalized, "%Y-%m-%d %H:%M:%S%.3f") {
-             return Some(chrono::DateTime::from_utc(dt, chrono::Utc));
-        }
-        None
-    };
+/// 解析请求中的时间边界：RFC 3339，或视为 UTC 的 `YYYY-MM-DD HH:MM:SS[.fff]`（可用 T 分隔）。
+fn parse_dt(s: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    // 优先尝试 RFC3339 格式
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Some(dt.with_timezone(&chrono::Utc));
+    }
+    // 尝试用空格代替 T
+    let normalized = s.replace('T', " ");
+    if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(&normalized, "%Y-%m-%d %H:%M:%S") {
+         return Some(chrono::DateTime::from_naive_utc_and_offset(dt, chrono::Utc));
+    }
+    if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(&normalized, "%Y-%m-%d %H:%M:%S%.3f") {
+         return Some(chrono::DateTime::from_naive_utc_and_offset(dt, chrono::Utc));
+    }
+    None
+}
 
+/// 解析 `time_windows`；边界无法解析或起点晚于终点时报错。
+fn parse_time_windows(
+    windows: &[(String, String)],
+) -> Result<Vec<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)>> {
+    windows
+        .iter()
+        .map(|(start, end)| {
+            let invalid = |reason: &str| {
+                crate::error::LogSearchError::InvalidRequest(format!(
+                    "invalid time window [{start:?}, {end:?}]: {reason}"
+                ))
+            };
+            let (Some(s), Some(e)) = (parse_dt(start), parse_dt(end)) else {
+                return Err(invalid("unrecognized time format"));
+            };
+            if s > e {
+                return Err(invalid("start is after end"));
+            }
+            Ok((s, e))
+        })
+        .collect()
+}
+
+fn parse_time_filter(
+    tf: &crate::model::TimeFilter,
+    windows: Vec<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)>,
+) -> ParsedTimeFilter {
+    let start = tf.time_start.as_deref().and_then(parse_dt);
+    let end = tf.time_end.as_deref().and_then(parse_dt);
+    // 给了时间范围却没有时间戳正则时，用内置正则识别 ISO 8601 与 syslog 时间戳，否则过滤不生效
+    let has_range = start.is_some() || end.is_some() || !windows.is_empty();
+    let pattern = tf
+        .timestamp_regex
+        .as_deref()
+        .or(has_range.then_some(DEFAULT_TIMESTAMP_REGEX));
     ParsedTimeFilter {
-        start: tf.time_start.as_deref().and_then(parse_dt),
-        end: tf.time_end.as_deref().and_then(parse_dt),
-        regex: tf.timestamp_regex.as_deref().and_then(|r| regex::Regex::new(r).ok()),
+        start,
+        end,
+        regex: pattern.and_then(|r| regex::Regex::new(r).ok()),
+        windows,
     }
 }
 
@@ -46,12 +99,61 @@ pub struct SearchEngine {
     reader: FileReader,
     parser: LogParser,
     query: QueryProcessor,
+    sessions: SessionManager,
+    counters: EngineCounters,
+    /// 所有搜索共享的文件打开许可，数量为 `search.max_open_files`。
+    open_files: Arc<tokio::sync::Semaphore>,
+}
+
+/// 引擎运行以来的累计统计（`search` 调用），通过 [`SearchEngine::stats`] 取快照。
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EngineStats {
+    /// 完成的 `search` 调用数（含游标翻页，不含失败的请求）。
+    pub searches: u64,
+    /// 这些搜索实际读完的文件数。
+    pub files_read: u64,
+    /// 读取器累计读取的字节数（gzip 按解压后计），包含导出、预览、`/watch` 等所有读取。
+    pub bytes_read: u64,
+    /// 响应中返回的命中条数（分页后）。
+    pub hits_returned: u64,
+    pub avg_latency_ms: f64,
+}
+
+#[derive(Default)]
+struct EngineCounters {
+    searches: AtomicU64,
+    files_read: AtomicU64,
+    hits_returned: AtomicU64,
+    total_latency_ms: AtomicU64,
+}
+
+impl EngineCounters {
+    fn record(&self, response: &SearchResponse, elapsed: Duration) {
+        let page_hits = response.hits.len()
+            + response
+                .grouped_hits
+                .iter()
+                .flatten()
+                .map(|g| g.hits.len())
+                .sum::<usize>();
+        self.searches.fetch_add(1, Ordering::Relaxed);
+        self.hits_returned.fetch_add(page_hits as u64, Ordering::Relaxed);
+        self.total_latency_ms
+            .fetch_add(elapsed.as_millis() as u64, Ordering::Relaxed);
+    }
 }
 
 impl SearchEngine {
     pub fn new(config: Arc<RwLock<Config>>) -> Self {
-        let buffer_size = config.read().unwrap().search.buffer_size;
-        let mut reader = FileReader::new(buffer_size);
+        let (read_buffer_size, decompress_buffer_size, max_open_files) = {
+            let cfg = config.read().unwrap();
+            (
+                cfg.search.read_buffer_size(),
+                cfg.search.decompress_buffer_size(),
+                cfg.search.max_open_files(),
+            )
+        };
+        let reader = FileReader::new(read_buffer_size).with_decompress_buffer_size(decompress_buffer_size);
         // 如果 is_gzip 为 true，FileReader 会自动处理 gzip。
         // 它通过扩展名检测。日志文件是 .log，但可能是纯文本。
         
@@ -61,478 +163,3939 @@ impl SearchEngine {
             scanner: FileScanner::new(),
             parser: LogParser::new(),
             query: QueryProcessor::new(),
+            sessions: SessionManager::new(),
+            counters: EngineCounters::default(),
+            open_files: Arc::new(tokio::sync::Semaphore::new(max_open_files)),
         }
     }
 
+    pub fn sessions(&self) -> &SessionManager {
+        &self.sessions
+    }
+
     pub fn list_files(&self, config: &crate::model::FileScanConfig) -> Result<Vec<PathBuf>> {
         // 如果需要，合并全局路径，尽管 list_files 通常是显式的。
         // 但如果 config.root_path 为空，我们可能会依赖全局路径。
         // 目前，我们直接传递，但如果我们也想在这里支持全局路径：
         let global_cfg = self.config.read().unwrap();
         let global_paths = global_cfg.log_sources.log_file_paths.clone();
+        let config = &global_cfg.log_sources.with_defaults(config);
         
         if let Some(paths) = global_paths {
              // 如果扫描器支持显式路径，请使用它们。
              // 目前扫描器仅支持 root_path + globs。
              // 我们需要修改扫描器。
-             self.scanner.scan_with_paths(config, &Some(paths))
+             self.scanner
+                 .follow_symlinked_dirs(global_cfg.log_sources.allow_symlinked_dirs)
+                 .scan_with_paths(config, &Some(paths))
         } else {
              // 如果没有全局配置，且 root_path 为空，我们返回空列表？
              // 或者尝试扫描 root_path
-             self.scanner.scan(config)
+             self.scanner
+                 .follow_symlinked_dirs(global_cfg.log_sources.allow_symlinked_dirs)
+                 .scan(config)
         }
     }
 
-    pub async fn search(&self, request: SearchRequest) -> Result<SearchResponse> {
-        self.validate_request(&request)?;
-        let started = Instant::now();
-        
-        let (search_config, log_parser_config, log_sources) = {
-            let cfg = self.config.read().unwrap();
-            (cfg.search.clone(), cfg.log_parser.clone(), cfg.log_sources.clone())
-        };
+    /// 读取文件开头的至多 `lines` 行（去掉行尾分隔符），用于在搜索前查看日志格式。
+    /// 读够即停止，不会读取整个文件。只允许读取 [`Self::readable_path`] 认可的文件。
+    pub async fn head_file(&self, path: &std::path::Path, lines: usize) -> Result<Vec<String>> {
+        let path = self.readable_path(path)?;
+        self.read_line_range(&path, 0, 1, lines.min(MAX_HEAD_LINES)).await
+    }
 
-        // 扫描文件
-        // 关键调试点：确认是否真的扫描到了文件
-        let files = if let Some(paths) = &log_sources.log_file_paths {
-             // 如果配置了全局路径，直接使用
-             self.scanner.scan_with_paths(&request.scan_config, &Some(paths.clone()))?
-        } else {
-             self.scanner.scan(&request.scan_config)?
+    /// 读取文件末尾的至多 `lines` 行（去掉行尾分隔符）。gzip 文件需完整解压，但只保留最后 `lines` 行。
+    pub async fn tail_file(&self, path: &std::path::Path, lines: usize) -> Result<Vec<String>> {
+        let path = self.readable_path(path)?;
+        let reader = self.configured_reader()?;
+        let trim: &[char] = &[reader.line_delimiter as char, '\r', '\n'];
+        let tail = reader.read_tail(&path, lines.min(MAX_HEAD_LINES)).await?;
+        Ok(tail.into_iter().map(|l| l.trim_end_matches(trim).to_string()).collect())
+    }
+
+    /// 读取从第 `start_line` 行（1 起）开始的至多 `count` 行，去掉行尾分隔符。
+    ///
+    /// `byte_offset` 非 0 时从该（解压后）字节偏移之后的第一条完整记录起算行号，
+    /// 带 `.gzi` 索引的 gzip 文件可直接定位，无需从头解压。
+    pub async fn read_line_range(
+        &self,
+        path: &std::path::Path,
+        byte_offset: u64,
+        start_line: usize,
+        count: usize,
+    ) -> Result<Vec<String>> {
+        let reader = self.configured_reader()?;
+        let trim: &[char] = &[reader.line_delimiter as char, '\r', '\n'];
+        let stream = reader
+            .read_lines_from(&crate::config::expand_path(path), byte_offset)
+            .await?;
+        stream
+            .skip(start_line.saturating_sub(1))
+            .take(count)
+            .map(|line| line.map(|l| l.trim_end_matches(trim).to_string()))
+            .try_collect()
+            .await
+    }
+
+    /// 检查直接读取的文件是否在允许范围内：`log_sources.log_file_paths` 中的文件，
+    /// 或按默认包含/排除规则会被扫描的 `log_sources.default_root` 下的文件（与 `why_file` 判断相同）。
+    /// 返回规范化后的路径，`..` 与符号链接不能绕过检查。
+    fn readable_path(&self, path: &std::path::Path) -> Result<PathBuf> {
+        let denied = |reason: &str| LogSearchError::FileAccessError {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
         };
-        
-        // eprintln!("DEBUG: scanned files count: {}", files.len());
-        // for f in &files {
-        //    eprintln!("DEBUG: file: {:?}", f);
-        // }
+        let canonical = crate::config::expand_path(path)
+            .canonicalize()
+            .map_err(|e| denied(&e.to_string()))?;
+        if self
+            .resource_files()
+            .iter()
+            .any(|allowed| allowed.canonicalize().is_ok_and(|a| a == canonical))
+        {
+            return Ok(canonical);
+        }
+        let default_root = self.config.read().unwrap().log_sources.default_root.clone();
+        if let Some(root_path) = default_root {
+            let scan = crate::model::FileScanConfig {
+                root_path,
+                include_globs: Vec::new(),
+                exclude_globs: Vec::new(),
+                filename_query: None,
+                content_type_filter: Default::default(),
+                line_range: None,
+            };
+            if self.why_file(&canonical, &scan)?.included {
+                return Ok(canonical);
+            }
+        }
+        Err(denied("not listed in log_sources.log_file_paths nor included under log_sources.default_root"))
+    }
 
-        let mut hits: Vec<HitResult> = Vec::new();
-        let mut failed_files = Vec::new();
-        let mut timed_out = false;
-        let mut files_scanned = 0usize;
+    /// 按当前配置（分隔符、编码覆盖、解压上限、严格解码）设置的读取器，用于搜索之外的直接读取。
+    fn configured_reader(&self) -> Result<FileReader> {
+        let cfg = self.config.read().unwrap();
+        Ok(self
+            .reader
+            .clone()
+            .with_line_delimiter(cfg.search.line_delimiter.unwrap_or(b'\n'))
+            .with_encoding_overrides(EncodingOverrides::new(&cfg.search.encoding_overrides)?)
+            .with_max_decompressed_bytes(cfg.search.max_decompressed_bytes)
+            .with_strict_utf8(cfg.search.strict_utf8))
+    }
+
+    /// 作为 MCP 资源公开的文件：`log_sources.log_file_paths` 中当前存在的文件。
+    pub fn resource_files(&self) -> Vec<PathBuf> {
+        let paths = self.config.read().unwrap().log_sources.log_file_paths.clone();
+        paths
+            .unwrap_or_default()
+            .into_iter()
+            .map(PathBuf::from)
+            .filter(|p| p.is_file())
+            .collect()
+    }
 
-        let log_start_pattern = request
-            .log_start_pattern
-            .as_ref()
-            .or(log_parser_config.default_log_start_pattern.as_ref())
-            .cloned();
+    /// 读取文件开头的样本行，推断多行日志的起始行正则；样本中没有可识别的行首时返回 `None`。
+    pub async fn infer_log_start_pattern(
+        &self,
+        path: &std::path::Path,
+        sample_lines: usize,
+    ) -> Result<Option<crate::model::PatternProposal>> {
+        let lines = self.head_file(path, sample_lines).await?;
+        Ok(crate::infer::infer_log_start_pattern(&lines))
+    }
 
-        let mut time_filter = request.time_filter.clone();
-        if let Some(ref mut tf) = time_filter {
-            if tf.timestamp_regex.is_none() {
-                tf.timestamp_regex = log_parser_config.default_timestamp_regex.clone();
+    /// 按搜索时的规则（长度上限、大小写）编译 `pattern`，并列出它在 `sample_text` 上的匹配与捕获组。
+    /// 编译失败不算错误，而是在结果中给出错误信息与位置。
+    pub fn test_regex(&self, pattern: &str, sample_text: &str, case_sensitive: bool) -> RegexTestResult {
+        let max_regex_len = self.config.read().unwrap().search.max_regex_len();
+        let re = match self
+            .query
+            .clone()
+            .with_max_regex_len(Some(max_regex_len))
+            .compile_regex(pattern, case_sensitive)
+        {
+            Ok(re) => re,
+            Err(e) => {
+                return RegexTestResult {
+                    error: Some(e.to_string()),
+                    error_position: regex_error_position(pattern),
+                    ..Default::default()
+                }
             }
-        } else if let Some(ts) = &log_parser_config.default_timestamp_regex {
-             time_filter = Some(TimeFilter {
-                 time_start: None,
-                 time_end: None,
-                 timestamp_regex: Some(ts.clone()),
-             });
+        };
+
+        let mut result = RegexTestResult {
+            valid: true,
+            ..Default::default()
+        };
+        for caps in re.captures_iter(sample_text) {
+            if result.matches.len() >= MAX_REGEX_SAMPLE_MATCHES {
+                result.truncated = true;
+                break;
+            }
+            let m = caps.get(0).expect("group 0 always participates");
+            let named = re
+                .capture_names()
+                .flatten()
+                .filter_map(|name| caps.name(name).map(|v| (name.to_string(), v.as_str().to_string())))
+                .collect();
+            result.matches.push(RegexSampleMatch {
+                line: sample_text[..m.start()].matches('\n').count() + 1,
+                offset: m.start(),
+                text: m.as_str().to_string(),
+                captures: caps.iter().skip(1).map(|c| c.map(|c| c.as_str().to_string())).collect(),
+                named,
+            });
         }
-        let parsed_time_filter = time_filter.as_ref().map(parse_time_filter);
+        result
+    }
 
-        let log_start_re = if let Some(pat) = &log_start_pattern {
-            Some(self.query.compile_regex(pat, true)?)
-        } else {
-            None
+    /// 解释某个文件在给定扫描配置下为何会或不会被搜索。
+    pub fn why_file(
+        &self,
+        path: &std::path::Path,
+        config: &crate::model::FileScanConfig,
+    ) -> Result<crate::model::FileExplanation> {
+        let log_sources = self.config.read().unwrap().log_sources.clone();
+        let config = log_sources.with_defaults(config);
+        self.scanner
+            .follow_symlinked_dirs(log_sources.allow_symlinked_dirs)
+            .explain(path, &config, &log_sources.log_file_paths)
+    }
+
+    /// 当前累计统计的快照。
+    pub fn stats(&self) -> EngineStats {
+        let c = &self.counters;
+        let searches = c.searches.load(Ordering::Relaxed);
+        let total_latency_ms = c.total_latency_ms.load(Ordering::Relaxed);
+        EngineStats {
+            searches,
+            files_read: c.files_read.load(Ordering::Relaxed),
+            bytes_read: self.reader.bytes_read(),
+            hits_returned: c.hits_returned.load(Ordering::Relaxed),
+            avg_latency_ms: if searches == 0 {
+                0.0
+            } else {
+                total_latency_ms as f64 / searches as f64
+            },
+        }
+    }
+
+    pub async fn search(&self, request: SearchRequest) -> Result<SearchResponse> {
+        let started = Instant::now();
+        let response = match &request.cursor {
+            Some(cursor) => self.resume_cursor(cursor, &request)?,
+            None => self.run_search(request).await?,
         };
+        self.counters.record(&response, started.elapsed());
+        Ok(response)
+    }
 
-        let max_concurrent = search_config.max_concurrent_files.max(1);
+    async fn run_search(&self, request: SearchRequest) -> Result<SearchResponse> {
+        self.validate_request(&request)?;
+        let started = Instant::now();
+        let resolved = {
+            let (search_config, log_sources) = {
+                let cfg = self.config.read().unwrap();
+                (cfg.search.clone(), cfg.log_sources.clone())
+            };
+            self.resolve_files(&request, &search_config, &log_sources)?
+        };
+        let (response, _) = self.execute(request, resolved, started, false).await?;
+        Ok(response)
+    }
 
-        let mut tasks = stream::iter(files.into_iter()).map(|path| {
-            let reader = self.reader.clone();
-            let parser = self.parser.clone();
-            let query = self.query.clone();
-            let request = request.clone();
-            let log_start_re = log_start_re.clone();
-            let default_timeout = search_config.default_timeout_ms;
-            let time_filter = parsed_time_filter.clone();
-
-            async move {
-                if let Ok(meta) = std::fs::metadata(&path) {
-                    const TEN_GB: u64 = 10 * 1024 * 1024 * 1024;
-                    if meta.len() > TEN_GB {
-                        warn!("file larger than 10GB: {}", path.display());
-                    }
-                }
+    /// 在已解析的文件列表上执行搜索。`keep_all` 时不呈现本页、不保存游标，
+    /// 而是随响应（`hits` 为空）一并返回按顺序排列的全部命中；否则第二项为空。
+    async fn execute(
+        &self,
+        request: SearchRequest,
+        resolved: ResolvedFiles,
+        started: Instant,
+        keep_all: bool,
+    ) -> Result<(SearchResponse, Vec<HitResult>)> {
+        let (search_config, log_parser_config) = {
+            let cfg = self.config.read().unwrap();
+            (cfg.search.clone(), cfg.log_parser.clone())
+        };
 
-                let single_file = async {
-                    // eprintln!("DEBUG: reading file {}", path.display());
-                    let lines = reader.read_lines(&path).await?;
-                    // eprintln!("DEBUG: read lines ok, parsing...");
-                    let entries = parser.parse(path.clone(), lines, log_start_re).await?;
-                    // eprintln!("DEBUG: parsing ok, scanning entries...");
-                    scan_entries_static(&query, entries, &request, time_filter).await
-                };
+        let ResolvedFiles {
+            files,
+            scan_errors,
+            refine_set,
+            sample_seed,
+        } = resolved;
+        let scan_elapsed = started.elapsed();
+        let session_id = request.session_id.as_deref().unwrap_or(DEFAULT_SESSION);
+
+        // 按文件分组收集，便于按时间戳做多路归并。
+        let mut per_file: Vec<Vec<MatchedHit>> = Vec::new();
+        let mut collected = 0usize;
+        let max_failed = search_config.max_failed_files_reported();
+        let mut failed_files_truncated_count = 0usize;
+        let mut failed_files = match request.on_file_error {
+            ErrorMode::Collect => scan_errors,
+            ErrorMode::Fail => match scan_errors.into_iter().next() {
+                Some((path, reason)) => return Err(LogSearchError::FileAccessError { path, reason }),
+                None => Vec::new(),
+            },
+            ErrorMode::Ignore => Vec::new(),
+        };
+        if failed_files.len() > max_failed {
+            failed_files_truncated_count = failed_files.len() - max_failed;
+            failed_files.truncate(max_failed);
+        }
+        let mut timed_out = false;
+        let mut read_budget_exceeded = false;
+        let mut files_scanned = 0usize;
+        let mut searched_files = Vec::new();
 
-                let effective_timeout = request
-                    .hard_timeout_ms
-                    .or(Some(default_timeout))
-                    .filter(|ms| *ms > 0);
+        let mut plan = self.plan(&request, &log_parser_config, &search_config)?;
+        plan.refine = refine_set.map(|set| (set, request.refine_mode));
+        let mut snapshot_id = None;
+        if request.snapshot_sizes {
+            let (id, sizes) = self.size_snapshot(session_id, &files, &request)?;
+            plan.size_snapshot = Some(sizes);
+            snapshot_id = Some(id);
+        }
 
-                let result = if let Some(ms) = effective_timeout {
-                    match timeout(Duration::from_millis(ms), single_file).await {
-                        Ok(res) => res.map(|v| (v, false)),
-                        Err(_) => Ok((Vec::new(), true)),
-                    }
-                } else {
-                    single_file.await.map(|v| (v, false))
-                };
+        let files_total = files.len();
 
-                match result {
-                    Ok((hits, timed_out)) => TaskResult {
-                        hits,
-                        failed: None,
-                        timed_out,
-                    },
-                    Err(e) => TaskResult {
-                        hits: Vec::new(),
-                        failed: Some((path, e.to_string())),
-                        timed_out: false,
-                    },
-                }
-            }
-        })
-        .buffer_unordered(max_concurrent);
+        // 整体时间预算从请求开始计时：进行中的文件到时停止读取并交回已得到的命中，
+        // 尚未开始的文件直接跳过；读取卡住的任务在宽限期后丢弃
+        plan.deadline = request
+            .total_timeout_ms
+            .filter(|ms| *ms > 0)
+            .map(|ms| tokio::time::Instant::from_std(started) + Duration::from_millis(ms));
+        let deadline = plan.deadline;
 
-        while let Some(task) = tasks.next().await {
+        let mut tasks = self.file_tasks(files, &request, &plan, &search_config);
+        loop {
+            let next = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline + DEADLINE_GRACE, tasks.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        timed_out = true;
+                        break;
+                    }
+                },
+                None => tasks.next().await,
+            };
+            let Some(task) = next else {
+                break;
+            };
             files_scanned += 1;
-            if let Some(f) = task.failed {
-                error!("failed to search {}: {}", f.0.display(), f.1);
-                failed_files.push(f);
+            if let Some(reason) = task.failed {
+                let path = task.path;
+                error!("failed to search {}: {}", path.display(), reason);
+                match request.on_file_error {
+                    ErrorMode::Collect if failed_files.len() < max_failed => failed_files.push((path, reason)),
+                    ErrorMode::Collect => failed_files_truncated_count += 1,
+                    ErrorMode::Fail => return Err(LogSearchError::FileAccessError { path, reason }),
+                    ErrorMode::Ignore => {}
+                }
             } else {
-                hits.extend(task.hits);
+                let file_hits = task.hits;
+                collected += file_hits.len();
+                per_file.push(file_hits);
+                if !task.timed_out && !plan.read_budget.exceeded() {
+                    searched_files.push(task.path);
+                }
             }
             if task.timed_out {
                 timed_out = true;
                 break;
             }
+            if plan.read_budget.exceeded() {
+                read_budget_exceeded = true;
+                break;
+            }
             if let Some(limit) = request.max_hits {
-                if hits.len() >= limit {
+                if collected >= limit {
                     break;
                 }
             }
         }
 
-        let page_size = if request.page_size == 0 {
-            search_config.default_page_size
-        } else {
-            request
-                .page_size
-                .min(search_config.max_page_size)
-                .max(1)
-        };
+        let page_size = effective_page_size(&request, &search_config);
+
+        self.counters
+            .files_read
+            .fetch_add(searched_files.len() as u64, Ordering::Relaxed);
+        // 只有显式给出 session_id 时才记录，供之后的 exclude_session_files 使用
+        if request.session_id.is_some() {
+            self.sessions.record_searched_files(session_id, searched_files);
+        }
+        if let Some(name) = &request.save_as {
+            self.sessions.save_result_set(
+                session_id,
+                name,
+                per_file.iter().flatten().map(|m| HitRef::from(&m.hit)).collect(),
+            );
+        }
+
+        let mut per_query_counts: HashMap<String, usize> = HashMap::new();
+        for m in per_file.iter().flatten() {
+            for term in &m.hit.matched_terms {
+                *per_query_counts.entry(term.clone()).or_default() += 1;
+            }
+        }
+
+        let templates = request.template.then(|| {
+            let hits = per_file
+                .iter()
+                .flatten()
+                .map(|m| m.clone().into_hit(plan.output_tz.as_ref()));
+            let mut groups = ContentNormalizer::default().group(hits);
+            groups.sort_by_key(|g| Reverse(g.count));
+            groups.truncate(MAX_TEMPLATE_GROUPS);
+            groups
+        });
 
-        let total_hits = hits.len();
-        let total_pages = if page_size == 0 {
-            0
+        let total_hits = collected;
+        let total_pages = total_hits.div_ceil(page_size);
+        let lines_scanned = plan.lines_scanned.load(Ordering::Relaxed);
+        let match_ratio = if lines_scanned == 0 {
+            0.0
         } else {
-            (total_hits + page_size - 1) / page_size
+            total_hits as f64 / lines_scanned as f64
         };
 
         let page = request.page.max(1);
         let start = page_size.saturating_mul(page.saturating_sub(1));
         let end = (start + page_size).min(total_hits);
-        let hits = if start < end {
-            hits[start..end].to_vec()
-        } else {
-            Vec::new()
+        // 显式给出 session_id 且当前页之后还有结果时一并排好序，保存到会话中供游标翻页；
+        // 其余请求只排到当前页为止，不在共享的 default 会话里留下整份结果
+        let save_cursor = end < total_hits && request.session_id.is_some();
+        let limit = if save_cursor || keep_all { total_hits } else { end };
+        let ordered: Vec<MatchedHit> = match request.sort_by {
+            SortBy::Unordered => per_file.into_iter().flatten().take(limit).collect(),
+            // 只归并到需要的位置即可
+            SortBy::TimestampAsc => merge_by_timestamp(per_file, limit),
+            SortBy::Relevance => sort_by_relevance(per_file, limit),
         };
+        let mut all: Vec<HitResult> = ordered
+            .into_iter()
+            .skip(if keep_all { 0 } else { start })
+            .map(|m| m.into_hit(plan.output_tz.as_ref()))
+            .collect();
 
-        let response = SearchResponse {
+        let mut response = SearchResponse {
             total_hits,
             page,
             page_size,
             total_pages,
-            hits,
+            hits: Vec::new(),
             execution_time_ms: started.elapsed().as_millis() as u64,
             files_scanned,
             timed_out,
             failed_files,
+            failed_files_truncated_count,
+            files_sampled: sample_seed.map(|_| files_total),
+            sample_seed,
+            per_query_counts,
+            grouped_hits: None,
+            templates,
+            next_cursor: None,
+            timing: plan.timings.as_ref().map(|t| t.breakdown(scan_elapsed)),
+            match_ratio,
+            too_broad: match_ratio > search_config.too_broad_ratio(),
+            read_budget_exceeded,
+            effective_timeout_ms: search_config.effective_timeout_ms(request.hard_timeout_ms),
+            bytes_scanned: plan.read_budget.used(),
+            lines_scanned: lines_scanned as usize,
+            snapshot_id,
+        };
+        if keep_all {
+            return Ok((response, all));
+        }
+
+        let rest = all.split_off(end.saturating_sub(start).min(all.len()));
+        let summary = save_cursor.then(|| response.clone());
+        (response.hits, response.grouped_hits) = present_page(all, &request);
+
+        if let Some(summary) = summary {
+            let cursor_id = format!("{:016x}", rand::random::<u64>());
+            self.sessions.save_cursor(
+                session_id,
+                &cursor_id,
+                CursorState {
+                    hits: Arc::new(rest),
+                    base: end,
+                    summary,
+                },
+            );
+            response.next_cursor = Some(format!("{cursor_id}:0"));
+        }
+
+        Ok((response, Vec::new()))
+    }
+
+    /// 给出 `snapshot_id` 时取会话中对应的快照；否则（只允许第 1 页）记录各文件当前大小并以新的 id 保存。
+    fn size_snapshot(
+        &self,
+        session_id: &str,
+        files: &[PathBuf],
+        request: &SearchRequest,
+    ) -> Result<(String, Arc<HashMap<PathBuf, u64>>)> {
+        if let Some(id) = &request.snapshot_id {
+            let sizes = self.sessions.size_snapshot(session_id, id).ok_or_else(|| {
+                LogSearchError::InvalidRequest(format!(
+                    "snapshot '{id}' not found in session '{session_id}'; it may have expired, request page 1 again without snapshot_id"
+                ))
+            })?;
+            return Ok((id.clone(), sizes));
+        }
+        if request.page > 1 {
+            return Err(LogSearchError::InvalidRequest(
+                "snapshot_sizes with page > 1 requires the snapshot_id returned by page 1".into(),
+            ));
+        }
+        let sizes: Arc<HashMap<PathBuf, u64>> = Arc::new(
+            files
+                .iter()
+                .filter_map(|path| std::fs::metadata(path).ok().map(|meta| (path.clone(), meta.len())))
+                .collect(),
+        );
+        let id = format!("{:016x}", rand::random::<u64>());
+        self.sessions.save_size_snapshot(session_id, &id, sizes.clone());
+        Ok((id, sizes))
+    }
+
+    /// 按游标从会话保存的结果中取下一页。
+    fn resume_cursor(&self, cursor: &str, request: &SearchRequest) -> Result<SearchResponse> {
+        let started = Instant::now();
+        let session_id = request.session_id.as_deref().unwrap_or(DEFAULT_SESSION);
+        let invalid = || {
+            LogSearchError::InvalidRequest(format!(
+                "cursor '{cursor}' not found in session '{session_id}'; it may have expired, run the search again"
+            ))
         };
+        let (cursor_id, offset) = cursor.split_once(':').ok_or_else(invalid)?;
+        let offset: usize = offset.parse().map_err(|_| invalid())?;
+        let state = self.sessions.cursor(session_id, cursor_id).ok_or_else(invalid)?;
 
-        Ok(response)
+        let search_config = self.config.read().unwrap().search.clone();
+        let page_size = effective_page_size(request, &search_config);
+        let start = offset.min(state.hits.len());
+        let end = (start + page_size).min(state.hits.len());
+        let (hits, grouped_hits) = present_page(state.hits[start..end].to_vec(), request);
+
+        Ok(SearchResponse {
+            page: (state.base + start) / page_size + 1,
+            page_size,
+            total_pages: state.summary.total_hits.div_ceil(page_size),
+            hits,
+            grouped_hits,
+            execution_time_ms: started.elapsed().as_millis() as u64,
+            next_cursor: (end < state.hits.len()).then(|| format!("{cursor_id}:{end}")),
+            timing: None,
+            ..state.summary
+        })
     }
 
-    /// 单文件搜索，主要用于测试组合
-    pub async fn search_file(&self, path: PathBuf, request: &SearchRequest) -> Result<Vec<HitResult>> {
-        let (log_parser_config, _) = {
+    /// 校验请求、预编译正则并扫描文件列表，保存为会话中的预备搜索，返回其句柄。
+    /// 文件列表此时即固定，之后的 [`Self::fetch_page`] 不再重新扫描目录。
+    pub fn prepare_search(&self, request: SearchRequest) -> Result<PreparedSearchInfo> {
+        self.validate_request(&request)?;
+        let (search_config, log_parser_config, log_sources) = {
             let cfg = self.config.read().unwrap();
-            (cfg.log_parser.clone(), cfg.search.clone())
+            (cfg.search.clone(), cfg.log_parser.clone(), cfg.log_sources.clone())
         };
+        let resolved = self.resolve_files(&request, &search_config, &log_sources)?;
+        self.plan(&request, &log_parser_config, &search_config)?;
 
-        let lines = self.reader.read_lines(&path).await?;
-        let log_start_pattern = request
-            .log_start_pattern
-            .as_ref()
-            .or(log_parser_config.default_log_start_pattern.as_ref())
-            .cloned();
-        let log_start_re = if let Some(pat) = &log_start_pattern {
-            Some(self.query.compile_regex(pat, true)?)
-        } else {
-            None
+        let session_id = request.session_id.clone().unwrap_or_else(|| DEFAULT_SESSION.to_string());
+        let handle = format!("{:016x}", rand::random::<u64>());
+        let info = PreparedSearchInfo {
+            handle: handle.clone(),
+            session_id: session_id.clone(),
+            files: resolved.files.len(),
+            scan_errors: resolved.scan_errors.len(),
         };
+        self.sessions.save_prepared(
+            &session_id,
+            &handle,
+            PreparedSearch {
+                request,
+                files: Arc::new(resolved.files),
+                scan_errors: resolved.scan_errors,
+                refine_set: resolved.refine_set,
+                sample_seed: resolved.sample_seed,
+                results: None,
+            },
+        );
+        Ok(info)
+    }
 
-        let mut time_filter = request.time_filter.clone();
-        if time_filter.is_none() {
-            if let Some(ts) = &log_parser_config.default_timestamp_regex {
-                time_filter = Some(TimeFilter {
-                    time_start: None,
-                    time_end: None,
-                    timestamp_regex: Some(ts.clone()),
-                });
+    /// 取预备搜索的第 `page` 页。首次调用在保存的文件列表上执行一次完整搜索并缓存全部有序命中，
+    /// 之后各页直接从缓存中切取，不再读取文件。`page_size` 省略时沿用预备请求中的设置。
+    pub async fn fetch_page(
+        &self,
+        session_id: Option<&str>,
+        handle: &str,
+        page: usize,
+        page_size: Option<usize>,
+    ) -> Result<SearchResponse> {
+        let started = Instant::now();
+        let session_id = session_id.unwrap_or(DEFAULT_SESSION);
+        let prepared = self.sessions.prepared(session_id, handle).ok_or_else(|| {
+            LogSearchError::InvalidRequest(format!(
+                "prepared search '{handle}' not found in session '{session_id}'; it may have expired, call prepare_search again"
+            ))
+        })?;
+        let mut request = prepared.request;
+        let state = match prepared.results {
+            Some(state) => state,
+            None => {
+                let resolved = ResolvedFiles {
+                    files: prepared.files.as_ref().clone(),
+                    scan_errors: prepared.scan_errors,
+                    refine_set: prepared.refine_set,
+                    sample_seed: prepared.sample_seed,
+                };
+                let mut first = request.clone();
+                first.page = 1;
+                let (summary, hits) = self.execute(first, resolved, started, true).await?;
+                let state = CursorState {
+                    hits: Arc::new(hits),
+                    base: 0,
+                    summary,
+                };
+                self.sessions.save_prepared_results(session_id, handle, state.clone());
+                state
             }
+        };
+
+        if let Some(page_size) = page_size {
+            request.page_size = page_size;
         }
-        let parsed_time_filter = time_filter.as_ref().map(parse_time_filter);
+        let search_config = self.config.read().unwrap().search.clone();
+        let page_size = effective_page_size(&request, &search_config);
+        let page = page.max(1);
+        let start = page_size.saturating_mul(page - 1).min(state.hits.len());
+        let end = (start + page_size).min(state.hits.len());
+        let (hits, grouped_hits) = present_page(state.hits[start..end].to_vec(), &request);
 
-        let entries = self
-            .parser
-            .parse(path.clone(), lines, log_start_re)
-            .await?;
-        self.scan_entries(entries, request, parsed_time_filter).await
+        let response = SearchResponse {
+            page,
+            page_size,
+            total_pages: state.summary.total_hits.div_ceil(page_size),
+            hits,
+            grouped_hits,
+            execution_time_ms: started.elapsed().as_millis() as u64,
+            next_cursor: None,
+            ..state.summary
+        };
+        self.counters.record(&response, started.elapsed());
+        Ok(response)
     }
 
-    // 如果 scan_entries_static 不是静态方法但我需要访问 self.query，则使用此辅助函数替代
-    async fn scan_entries(&self, entries: impl Stream<Item = Result<crate::model::LogEntry>> + Unpin, request: &SearchRequest, time_filter: Option<ParsedTimeFilter>) -> Result<Vec<HitResult>> {
-         scan_entries_static(&self.query, entries, request, time_filter).await
-    }
+    /// 导出全部命中：不分页、不在内存中汇总，按文件完成顺序逐条产出。
+    ///
+    /// 请求校验、文件扫描与正则编译在返回前完成，错误直接返回；
+    /// 之后单个文件失败或超时只记录日志并跳过。`sort_by`、`group_by_file`、
+    /// `save_as` 等依赖完整结果集的选项在导出时被忽略，`max_hits` 与 `total_timeout_ms` 仍然生效。
+    pub async fn search_export(
+        self: Arc<Self>,
+        request: SearchRequest,
+    ) -> Result<impl Stream<Item = HitResult> + Send + 'static> {
+        let started = tokio::time::Instant::now();
+        self.validate_request(&request)?;
+        let (search_config, log_parser_config, log_sources) = {
+            let cfg = self.config.read().unwrap();
+            (cfg.search.clone(), cfg.log_parser.clone(), cfg.log_sources.clone())
+        };
+        let ResolvedFiles {
+            files,
+            scan_errors,
+            refine_set,
+            ..
+        } = self.resolve_files(&request, &search_config, &log_sources)?;
+        for (path, e) in &scan_errors {
+            error!("failed to scan {}: {}", path.display(), e);
+        }
+        let mut plan = self.plan(&request, &log_parser_config, &search_config)?;
+        plan.refine = refine_set.map(|set| (set, request.refine_mode));
+        // 与 execute 相同：整体时间预算从请求开始计时，到时结束导出流
+        plan.deadline = request
+            .total_timeout_ms
+            .filter(|ms| *ms > 0)
+            .map(|ms| started + Duration::from_millis(ms));
 
-    pub fn validate_request(&self, request: &SearchRequest) -> Result<()> {
-        let global_cfg = self.config.read().unwrap();
-        let has_global = global_cfg.log_sources.log_file_paths.as_ref().map(|v| !v.is_empty()).unwrap_or(false);
-        
-        if request.scan_config.root_path.as_os_str().is_empty() {
-             if !has_global {
-                 return Err(crate::error::LogSearchError::InvalidRequest("root_path is empty and no global log_file_paths configured".to_string()));
-             }
-             // if has global, we skip directory check for root_path
-        } else {
-            // 如果提供了 root_path，我们需要验证它是否存在
-            // 但如果用户传入 "." 这种相对路径，fs::metadata 可能会基于当前工作目录去找
-            // MCP server 的工作目录通常是启动它的目录。
-            // 
-            // 关键修复：如果 request.scan_config.root_path 指向一个不存在的目录，但我们有全局日志配置，
-            // 我们应该宽容处理吗？或者，如果它是 ".", 且它存在，就没问题。
-            
-            let meta_res = std::fs::metadata(&request.scan_config.root_path);
-            match meta_res {
-                Ok(meta) => {
-                     if !meta.is_dir() {
-                        return Err(crate::error::LogSearchError::InvalidRequest(format!(
-                            "{:?} is not a directory",
-                            request.scan_config.root_path
-                        )));
-                    }
-                },
-                Err(e) => {
-                     // 如果校验失败，但我们有全局配置，且 root_path 看起来像是一个为了绕过必填检查的占位符（比如 "." 但如果当前目录不可读？）
-                     // 不过通常 "." 是存在的。
-                     // 如果用户传了一个无效路径，报错是合理的。
-                     return Err(crate::error::LogSearchError::FileAccessError {
-                        path: request.scan_config.root_path.clone(),
-                        reason: e.to_string(),
-                    });
+        Ok(async_stream::stream! {
+            let mut remaining = request.max_hits.unwrap_or(usize::MAX);
+            let mut tasks = self.file_tasks(files, &request, &plan, &search_config);
+            loop {
+                let next = match plan.deadline {
+                    Some(deadline) => match tokio::time::timeout_at(deadline + DEADLINE_GRACE, tasks.next()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            warn!("export stopped early: total_timeout_ms reached");
+                            break;
+                        }
+                    },
+                    None => tasks.next().await,
+                };
+                let Some(task) = next else { break };
+                if let Some(reason) = task.failed {
+                    error!("failed to search {}: {}", task.path.display(), reason);
+                    continue;
+                }
+                for m in task.hits.into_iter().take(remaining) {
+                    remaining -= 1;
+                    yield m.into_hit(plan.output_tz.as_ref());
+                }
+                if remaining == 0 {
+                    break;
+                }
+                if task.timed_out {
+                    warn!("export stopped early: a file or the total search timeout was exceeded");
+                    break;
+                }
+                if plan.read_budget.exceeded() {
+                    warn!("export stopped early: search.max_total_read_bytes reached");
+                    break;
                 }
             }
-        }
+        })
+    }
 
-        if request.page == 0 {
-            return Err(crate::error::LogSearchError::InvalidRequest(
-                "page must be >= 1".into(),
-            ));
-        }
-        Ok(())
+    /// 分别执行两个搜索，按归一化内容（数字、ID 等易变部分替换为 `#`）比较命中，
+    /// 返回只出现在一侧的内容，每侧最多 `limit` 组。
+    pub async fn diff_searches(
+        &self,
+        baseline: &SearchRequest,
+        current: &SearchRequest,
+        limit: usize,
+    ) -> Result<SearchDiff> {
+        let (baseline_hits, baseline_truncated) = self.collect_hits(baseline).await?;
+        let (current_hits, current_truncated) = self.collect_hits(current).await?;
+        let normalizer = ContentNormalizer::default();
+        let baseline_total_hits = baseline_hits.len();
+        let current_total_hits = current_hits.len();
+        let (mut only_in_current, mut only_in_baseline, common) =
+            diff_groups(normalizer.group(baseline_hits), normalizer.group(current_hits));
+        only_in_current.truncate(limit);
+        only_in_baseline.truncate(limit);
+        Ok(SearchDiff {
+            baseline_total_hits,
+            current_total_hits,
+            only_in_current,
+            only_in_baseline,
+            common,
+            truncated: baseline_truncated || current_truncated,
+        })
     }
-}
 
-#[derive(Debug)]
-struct TaskResult {
-    hits: Vec<HitResult>,
-    failed: Option<(PathBuf, String)>,
-    timed_out: bool,
-}
+    /// 统计时间窗口内的错误条目：总数、按归一化内容分组的高频消息（最多 `limit` 组）、
+    /// 首末次出现时间及按小时分布。
+    ///
+    /// 错误条目由 `level_regex` 识别；未给出时使用 [`DEFAULT_ERROR_REGEX`]，并按 `min_level: error`
+    /// 排除首个级别低于 ERROR 的条目（如消息中恰好含有 "error" 的 INFO 日志）。
+    pub async fn error_summary(
+        &self,
+        scan_config: &FileScanConfig,
+        time_window: Option<&TimeFilter>,
+        level_regex: Option<&str>,
+        limit: usize,
+    ) -> Result<ErrorSummary> {
+        let request: SearchRequest = serde_json::from_value(serde_json::json!({
+            "scan_config": scan_config,
+            "logical_query": {
+                "must": [{
+                    "query": level_regex.unwrap_or(DEFAULT_ERROR_REGEX),
+                    "regex": true,
+                    "case_sensitive": true,
+                }],
+            },
+            "time_filter": time_window,
+            "min_level": level_regex.is_none().then_some("error"),
+        }))
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid error_summary request: {e}")))?;
+        let (hits, truncated) = self.collect_hits(&request).await?;
 
-async fn scan_entries_static(
-    query: &QueryProcessor,
-    mut entries: impl Stream<Item = Result<crate::model::LogEntry>> + Unpin,
-    request: &SearchRequest,
-    time_filter: Option<ParsedTimeFilter>,
-) -> Result<Vec<HitResult>> {
-    let mut hits = Vec::new();
-    while let Some(entry) = entries.next().await {
-        let entry = entry?;
+        let mut hourly: std::collections::BTreeMap<DateTime<chrono::FixedOffset>, usize> =
+            std::collections::BTreeMap::new();
+        let mut first_seen: Option<DateTime<chrono::FixedOffset>> = None;
+        let mut last_seen: Option<DateTime<chrono::FixedOffset>> = None;
+        let mut without_timestamp = 0;
+        for hit in &hits {
+            let Some(ts) = hit
+                .timestamp
+                .as_deref()
+                .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+            else {
+                without_timestamp += 1;
+                continue;
+            };
+            first_seen = Some(first_seen.map_or(ts, |f| f.min(ts)));
+            last_seen = Some(last_seen.map_or(ts, |l| l.max(ts)));
+            let hour = ts
+                .with_minute(0)
+                .and_then(|t| t.with_second(0))
+                .and_then(|t| t.with_nanosecond(0))
+                .unwrap_or(ts);
+            *hourly.entry(hour).or_default() += 1;
+        }
 
-        // 输出调试信息到 stderr（不会影响 stdout json-rpc）
-        // eprintln!("DEBUG: checking entry: {}", entry.content.lines().next().unwrap_or(""));
+        let total_errors = hits.len();
+        let mut top_messages = ContentNormalizer::default().group(hits);
+        let distinct_messages = top_messages.len();
+        top_messages.sort_by_key(|g| Reverse(g.count));
+        top_messages.truncate(limit);
+        let format = |ts: DateTime<chrono::FixedOffset>| ts.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        Ok(ErrorSummary {
+            total_errors,
+            top_messages,
+            distinct_messages,
+            first_seen: first_seen.map(format),
+            last_seen: last_seen.map(format),
+            hourly: hourly
+                .into_iter()
+                .map(|(hour, count)| HourCount {
+                    hour: hour.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                    count,
+                })
+                .collect(),
+            without_timestamp,
+            truncated,
+        })
+    }
 
-        if !query.apply_time_filter(&entry.content, &time_filter) {
-            // eprintln!("DEBUG: time filter rejected");
-            continue;
+    /// 随机抽取最多 `sample_files` 个文件（每个文件至多读开头 `sample_bytes` 字节）搜索，
+    /// 外推出扫描范围内的命中总数。
+    ///
+    /// 只读了开头的普通文件按 文件大小 / 已读字节 放大；各文件的估计值再按 总文件数 / 样本数 外推，
+    /// 置信区间取文件间方差的正态近似。只读样本文件，因此不受 `search.max_files` 限制。
+    pub async fn estimate_matches(
+        &self,
+        scan_config: &FileScanConfig,
+        logical_query: &LogicalQuery,
+        sample_files: usize,
+        sample_bytes: Option<u64>,
+        sample_seed: Option<u64>,
+    ) -> Result<MatchEstimate> {
+        if sample_files == 0 {
+            return Err(LogSearchError::InvalidRequest("sample_files must be at least 1".to_string()));
         }
-        if !query.matches(&entry.content, &request.logical_query) {
-            // eprintln!("DEBUG: content match rejected");
-            continue;
+        if sample_bytes == Some(0) {
+            return Err(LogSearchError::InvalidRequest("sample_bytes must be at least 1".to_string()));
+        }
+        let request: SearchRequest = serde_json::from_value(serde_json::json!({
+            "scan_config": scan_config,
+            "logical_query": logical_query,
+            "include_content": false,
+            "scan_first_bytes": sample_bytes,
+        }))
+        .map_err(|e| LogSearchError::InvalidRequest(format!("invalid estimate_matches request: {e}")))?;
+        self.validate_request(&request)?;
+        let (search_config, log_parser_config, log_sources) = {
+            let cfg = self.config.read().unwrap();
+            (cfg.search.clone(), cfg.log_parser.clone(), cfg.log_sources.clone())
+        };
+        let ScanOutcome { files, errors } = self.scan_request_files(&request, &log_sources)?;
+        for (path, e) in &errors {
+            error!("failed to scan {}: {}", path.display(), e);
         }
+        let files_total = files.len();
+        let seed = sample_seed.unwrap_or_else(rand::random);
+        let files = sample_paths(files, sample_files, seed);
+        let plan = self.plan(&request, &log_parser_config, &search_config)?;
 
-        let positions = collect_positions_static(query, &entry.content, &request.logical_query);
-        hits.push(HitResult {
-            file_path: entry.file_path.clone(),
-            start_line: entry.start_line,
-            end_line: entry.end_line,
-            content: if request.include_content {
-                entry.content.clone()
-            } else {
-                String::new()
+        let mut estimate = MatchEstimate {
+            files_total,
+            sample_seed: seed,
+            ..Default::default()
+        };
+        let mut per_file: Vec<f64> = Vec::new();
+        let mut scaled = false;
+        let mut tasks = self.file_tasks(files, &request, &plan, &search_config);
+        while let Some(task) = tasks.next().await {
+            if let Some(reason) = task.failed {
+                error!("failed to search {}: {}", task.path.display(), reason);
+                estimate.failed_files.push((task.path, reason));
+                continue;
+            }
+            if task.timed_out {
+                estimate.failed_files.push((task.path, "timed out".to_string()));
+                continue;
+            }
+            let hits = task.hits.len();
+            estimate.sampled_hits += hits;
+            let size = std::fs::metadata(&task.path).map(|m| m.len()).unwrap_or(0);
+            let factor = match sample_bytes {
+                Some(limit) if size > limit => {
+                    scaled = true;
+                    if crate::reader::is_gz(&task.path) {
+                        estimate.gzip_unscaled += 1;
+                        1.0
+                    } else {
+                        size as f64 / limit as f64
+                    }
+                }
+                _ => 1.0,
+            };
+            per_file.push(hits as f64 * factor);
+        }
+
+        let n = per_file.len();
+        estimate.files_sampled = n;
+        estimate.exact = n == files_total && !scaled;
+        if n == 0 {
+            return Ok(estimate);
+        }
+        let mean = per_file.iter().sum::<f64>() / n as f64;
+        let total = mean * files_total as f64;
+        let half_width = if n > 1 && n < files_total {
+            let variance = per_file.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+            let fpc = (files_total - n) as f64 / (files_total - 1) as f64;
+            1.96 * files_total as f64 * (variance / n as f64 * fpc).sqrt()
+        } else {
+            0.0
+        };
+        let floor = estimate.sampled_hits as f64;
+        estimate.estimated_total = total.max(floor).round() as u64;
+        estimate.low = (total - half_width).max(floor).round() as u64;
+        estimate.high = (total + half_width).max(floor).round() as u64;
+        Ok(estimate)
+    }
+
+    /// 不分页地收集一个请求的全部命中（仍受 `max_hits` 限制），失败的文件只记录日志。
+    /// 第二项表示结果是否因超时或 `max_hits` 而不完整。
+    async fn collect_hits(&self, request: &SearchRequest) -> Result<(Vec<HitResult>, bool)> {
+        self.validate_request(request)?;
+        let (search_config, log_parser_config, log_sources) = {
+            let cfg = self.config.read().unwrap();
+            (cfg.search.clone(), cfg.log_parser.clone(), cfg.log_sources.clone())
+        };
+        let ResolvedFiles {
+            files,
+            scan_errors,
+            refine_set,
+            ..
+        } = self.resolve_files(request, &search_config, &log_sources)?;
+        for (path, e) in &scan_errors {
+            error!("failed to scan {}: {}", path.display(), e);
+        }
+        let mut plan = self.plan(request, &log_parser_config, &search_config)?;
+        plan.refine = refine_set.map(|set| (set, request.refine_mode));
+
+        let mut hits = Vec::new();
+        let mut truncated = false;
+        let limit = request.max_hits.unwrap_or(usize::MAX);
+        let mut tasks = self.file_tasks(files, request, &plan, &search_config);
+        while let Some(task) = tasks.next().await {
+            if let Some(reason) = task.failed {
+                error!("failed to search {}: {}", task.path.display(), reason);
+                continue;
+            }
+            hits.extend(task.hits.into_iter().map(|m| m.into_hit(plan.output_tz.as_ref())));
+            if task.timed_out || hits.len() >= limit || plan.read_budget.exceeded() {
+                truncated = true;
+                break;
+            }
+        }
+        hits.truncate(limit);
+        Ok((hits, truncated))
+    }
+
+    /// 统计扫描范围内各行 `pattern` 第一个捕获组的取值及出现次数，按次数从多到少排列。
+    ///
+    /// 逐行读取并只保留计数，不构造命中；不同取值超过 [`MAX_DISTINCT_VALUES`] 后新值不再计入，
+    /// 超出 `max_total_read_bytes` 时停止读取，两者都会置 `truncated`。
+    pub async fn distinct_values(
+        &self,
+        scan_config: &crate::model::FileScanConfig,
+        pattern: &str,
+        limit: usize,
+    ) -> Result<DistinctValues> {
+        let re = self.query.compile_regex(pattern, true)?;
+        if re.captures_len() < 2 {
+            return Err(crate::error::LogSearchError::InvalidRequest(
+                "pattern must contain a capturing group".to_string(),
+            ));
+        }
+        let files = self.list_files(scan_config)?;
+        let budget = SharedReadBudget::new(self.config.read().unwrap().search.max_total_read_bytes);
+        let reader = self.configured_reader()?.with_shared_budget(Some(budget.clone()));
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let mut result = DistinctValues::default();
+        for path in files {
+            let outcome = async {
+                let mut lines = reader.read_lines(&path).await?;
+                while let Some(line) = lines.next().await {
+                    let line = line?;
+                    let Some(value) = re.captures(&line).and_then(|c| c.get(1)) else {
+                        continue;
+                    };
+                    result.matched_lines += 1;
+                    if let Some(count) = counts.get_mut(value.as_str()) {
+                        *count += 1;
+                    } else if counts.len() < MAX_DISTINCT_VALUES {
+                        counts.insert(value.as_str().to_string(), 1);
+                    } else {
+                        result.truncated = true;
+                    }
+                }
+                Ok::<_, crate::error::LogSearchError>(())
+            }
+            .await;
+            if budget.exceeded() {
+                result.truncated = true;
+                break;
+            }
+            match outcome {
+                Ok(()) => result.files_scanned += 1,
+                Err(e) => {
+                    error!("failed to read {}: {}", path.display(), e);
+                    result.failed_files.push((path, e.to_string()));
+                }
+            }
+        }
+
+        result.distinct = counts.len();
+        let mut values: Vec<ValueCount> = counts
+            .into_iter()
+            .map(|(value, count)| ValueCount { value, count })
+            .collect();
+        values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        values.truncate(limit);
+        result.values = values;
+        Ok(result)
+    }
+
+    /// 扫描请求涉及的文件，并解析 refine 结果集、执行抽样与文件数上限检查。
+    fn resolve_files(
+        &self,
+        request: &SearchRequest,
+        search_config: &crate::config::SearchConfig,
+        log_sources: &crate::config::LogSourceConfig,
+    ) -> Result<ResolvedFiles> {
+        // 关键调试点：确认是否真的扫描到了文件
+        let ScanOutcome { mut files, errors: scan_errors } =
+            self.scan_request_files(request, log_sources)?;
+
+        let session_id = request.session_id.as_deref().unwrap_or(DEFAULT_SESSION);
+        let refine_set = match &request.refine_from {
+            Some(name) => Some(self.sessions.result_set(session_id, name).ok_or_else(|| {
+                crate::error::LogSearchError::InvalidRequest(format!(
+                    "result set '{name}' not found in session '{session_id}'"
+                ))
+            })?),
+            None => None,
+        };
+
+        if let Some(excluded) = &request.exclude_session_files {
+            self.sessions.retain_unsearched(excluded, &mut files);
+        }
+
+        let mut sample_seed = None;
+        if let Some(n) = request.sample_files {
+            let seed = request.sample_seed.unwrap_or_else(rand::random);
+            files = sample_paths(files, n, seed);
+            sample_seed = Some(seed);
+        }
+        crate::scanner::order_by_scan_order(&mut files, request.scan_order);
+        if request.group_rotations {
+            crate::scanner::order_rotations(&mut files);
+        }
+
+        if let Some(max_files) = search_config.max_files {
+            if files.len() > max_files {
+                return Err(crate::error::LogSearchError::InvalidRequest(format!(
+                    "scan matched {} files, exceeding search.max_files ({max_files}); narrow root_path or include_globs/exclude_globs",
+                    files.len()
+                )));
+            }
+        }
+
+        Ok(ResolvedFiles {
+            files,
+            scan_errors,
+            refine_set,
+            sample_seed,
+        })
+    }
+
+    /// 并发搜索各文件的任务流。`group_rotations` 时按文件列表顺序产出结果（仍并发读取），
+    /// 否则按完成顺序产出。
+    fn file_tasks<'a>(
+        &'a self,
+        files: Vec<PathBuf>,
+        request: &'a SearchRequest,
+        plan: &'a SearchPlan,
+        search_config: &'a crate::config::SearchConfig,
+    ) -> BoxStream<'a, TaskResult> {
+        let max_concurrent = search_config.max_concurrent_files.max(1);
+        let tasks = stream::iter(files).map(move |path| self.search_one_file(path, request, plan, search_config));
+        if request.group_rotations {
+            tasks.buffered(max_concurrent).boxed()
+        } else {
+            tasks.buffer_unordered(max_concurrent).boxed()
+        }
+    }
+
+    /// 搜索单个文件：带读取重试与超时，错误收进 [`TaskResult`] 而不向上传播。
+    fn search_one_file<'a>(
+        &'a self,
+        path: PathBuf,
+        request: &'a SearchRequest,
+        plan: &'a SearchPlan,
+        search_config: &crate::config::SearchConfig,
+    ) -> impl Future<Output = TaskResult> + Send + 'a {
+        let reader = self
+            .reader
+            .clone()
+            .with_line_delimiter(plan.line_delimiter)
+            .with_max_bytes(request.scan_first_bytes)
+            .with_encoding_overrides(plan.encoding_overrides.clone())
+            .with_max_decompressed_bytes(search_config.max_decompressed_bytes)
+            .with_strict_utf8(search_config.strict_utf8)
+            .with_shared_budget(Some(plan.read_budget.clone()))
+            .with_size_limit(
+                plan.size_snapshot
+                    .as_ref()
+                    .map(|sizes| sizes.get(&path).copied().unwrap_or(0)),
+            );
+        let parser = self.parser.clone();
+        let query = self.query.clone();
+        let open_files = self.open_files.clone();
+        let effective_timeout = search_config.effective_timeout_ms(request.hard_timeout_ms);
+        let retries = search_config.read_retries.unwrap_or(DEFAULT_READ_RETRIES);
+        let retry_delay = Duration::from_millis(
+            search_config
+                .read_retry_delay_ms
+                .unwrap_or(DEFAULT_READ_RETRY_DELAY_MS),
+        );
+
+        async move {
+            if let Ok(meta) = std::fs::metadata(&path) {
+                const TEN_GB: u64 = 10 * 1024 * 1024 * 1024;
+                if meta.len() > TEN_GB {
+                    warn!("file larger than 10GB: {}", path.display());
+                }
+            }
+
+            // 等待打开许可的时间不计入单文件超时，但受整体截止时间约束
+            let acquire = open_files.acquire();
+            let permit = match plan.deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, acquire).await {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        return TaskResult {
+                            path,
+                            hits: Vec::new(),
+                            failed: None,
+                            timed_out: true,
+                        }
+                    }
+                },
+                None => acquire.await,
+            }
+            .expect("open file semaphore is never closed");
+
+            // 到达截止时间后条目流结束，已得到的命中照常返回；读取卡住时再由硬超时兜底
+            let file_deadline = effective_timeout
+                .map(|ms| tokio::time::Instant::now() + Duration::from_millis(ms))
+                .into_iter()
+                .chain(plan.deadline)
+                .min();
+            let expired = Arc::new(AtomicBool::new(false));
+            let single_file = with_read_retry(retries, retry_delay, || async {
+                // 失败的尝试可能被重试：撤销它读取的字节，避免重复计入或误触发总读取预算
+                let attempt_bytes = Arc::new(AtomicU64::new(0));
+                let reader = reader.clone().with_attempt_counter(attempt_bytes.clone());
+                let result = async {
+                    let mut lines = reader.read_lines(&path).await?;
+                    if request.strip_ansi {
+                        lines = strip_ansi_lines(lines);
+                    }
+                    if let Some(timings) = &plan.timings {
+                        lines = timed(lines, timings.read.clone());
+                    }
+                    // eprintln!("DEBUG: read lines ok, parsing...");
+                    let mut entries = parser
+                        .parse_with_format(path.clone(), lines, plan.log_start_re.clone(), request.log_format)
+                        .await?;
+                    if let Some(timings) = &plan.timings {
+                        entries = timed(entries, timings.entries.clone());
+                    }
+                    if let Some(deadline) = file_deadline {
+                        entries = until_deadline(entries, deadline, expired.clone());
+                    }
+                    // eprintln!("DEBUG: parsing ok, scanning entries...");
+                    scan_entries_static(&query, entries, request, plan).await
+                }
+                .await;
+                if result.is_err() {
+                    reader.release(attempt_bytes.load(Ordering::Relaxed));
+                }
+                result
+            });
+
+            let result = match file_deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline + DEADLINE_GRACE, single_file).await {
+                    Ok(res) => res.map(|v| (v, expired.load(Ordering::Relaxed))),
+                    Err(_) => Ok((Vec::new(), true)),
+                },
+                None => single_file.await.map(|v| (v, false)),
+            };
+            drop(permit);
+
+            match result {
+                Ok((hits, timed_out)) => TaskResult {
+                    path,
+                    hits,
+                    failed: None,
+                    timed_out,
+                },
+                Err(e) => TaskResult {
+                    path,
+                    hits: Vec::new(),
+                    failed: Some(describe_file_error(&e)),
+                    timed_out: false,
+                },
+            }
+        }
+    }
+
+    /// 单文件搜索，主要用于测试组合
+    pub async fn search_file(&self, path: PathBuf, request: &SearchRequest) -> Result<Vec<HitResult>> {
+        let (log_parser_config, search_config) = {
+            let cfg = self.config.read().unwrap();
+            (cfg.log_parser.clone(), cfg.search.clone())
+        };
+        let plan = self.plan(request, &log_parser_config, &search_config)?;
+
+        let mut lines = self
+            .reader
+            .clone()
+            .with_line_delimiter(plan.line_delimiter)
+            .with_max_bytes(request.scan_first_bytes)
+            .with_encoding_overrides(plan.encoding_overrides.clone())
+            .with_max_decompressed_bytes(search_config.max_decompressed_bytes)
+            .with_strict_utf8(search_config.strict_utf8)
+            .read_lines(&path)
+            .await?;
+        if request.strip_ansi {
+            lines = strip_ansi_lines(lines);
+        }
+        let entries = self
+            .parser
+            .parse_with_format(path.clone(), lines, plan.log_start_re.clone(), request.log_format)
+            .await?;
+        let matched = self.scan_entries(entries, request, &plan).await?;
+        Ok(matched
+            .into_iter()
+            .map(|m| m.into_hit(plan.output_tz.as_ref()))
+            .collect())
+    }
+
+    /// 增量搜索：只读取各文件自上次位置之后新追加的完整记录。
+    ///
+    /// `positions` 为 `None` 时使用会话中保存的位置并在结束后写回，
+    /// 便于跨多次工具调用持续跟踪；会话中尚无位置时仅建立基线，不返回命中。
+    /// 不在表中的文件视为新文件从头读取，文件变短（截断/轮转）时同样从头读取。
+    /// 跨两次调用被截断的多行条目会被拆成两条。
+    pub async fn search_incremental(
+        &self,
+        request: &SearchRequest,
+        positions: Option<HashMap<PathBuf, TailPosition>>,
+    ) -> Result<IncrementalResult> {
+        if positions.is_some() {
+            return self.tail_files(request, positions).await;
+        }
+        let session_id = request.session_id.as_deref().unwrap_or(DEFAULT_SESSION);
+        let stored = self.sessions.tail_positions(session_id);
+        let result = self.tail_files(request, stored).await?;
+        self.sessions
+            .save_tail_positions(session_id, result.positions.clone());
+        Ok(result)
+    }
+
+    /// 记录各文件当前末尾作为增量搜索的基线，不读取已有内容，也不写入会话。
+    pub async fn tail_baseline(&self, request: &SearchRequest) -> Result<IncrementalResult> {
+        self.tail_files(request, None).await
+    }
+
+    async fn tail_files(
+        &self,
+        request: &SearchRequest,
+        positions: Option<HashMap<PathBuf, TailPosition>>,
+    ) -> Result<IncrementalResult> {
+        self.validate_request(request)?;
+        let (search_config, log_parser_config, log_sources) = {
+            let cfg = self.config.read().unwrap();
+            (cfg.search.clone(), cfg.log_parser.clone(), cfg.log_sources.clone())
+        };
+        let ScanOutcome { files, errors } = self.scan_request_files(request, &log_sources)?;
+        let plan = self.plan(request, &log_parser_config, &search_config)?;
+        let reader = self
+            .reader
+            .clone()
+            .with_line_delimiter(plan.line_delimiter)
+            .with_encoding_overrides(plan.encoding_overrides.clone())
+            .with_max_decompressed_bytes(search_config.max_decompressed_bytes)
+            .with_strict_utf8(search_config.strict_utf8);
+
+        let mut result = IncrementalResult {
+            failed_files: errors,
+            ..Default::default()
+        };
+        for path in files {
+            let outcome = async {
+                let Some(previous) = positions.as_ref() else {
+                    return reader.tail_position(&path).await.map(|pos| (Vec::new(), pos));
+                };
+                let mut start = previous.get(&path).copied().unwrap_or_default();
+                let len = tokio::fs::metadata(&path).await?.len();
+                if len < start.offset {
+                    start = TailPosition::default();
+                }
+                let chunk = reader.read_appended(&path, start).await?;
+                let mut lines: futures::stream::BoxStream<'static, Result<String>> =
+                    Box::pin(stream::iter(chunk.lines.into_iter().map(Ok)));
+                if request.strip_ansi {
+                    lines = strip_ansi_lines(lines);
+                }
+                let entries = self
+                    .parser
+                    .parse(path.clone(), lines, plan.log_start_re.clone())
+                    .await?;
+                let matched =
+                    scan_entries_static(&self.query, entries, request, &plan).await?;
+                let hits = matched
+                    .into_iter()
+                    .map(|m| {
+                        let mut hit = m.into_hit(plan.output_tz.as_ref());
+                        hit.start_line += start.line;
+                        hit.end_line += start.line;
+                        for ctx in hit.context_before.iter_mut().chain(hit.context_after.iter_mut()) {
+                            ctx.start_line += start.line;
+                            ctx.end_line += start.line;
+                        }
+                        hit
+                    })
+                    .collect();
+                Ok::<_, crate::error::LogSearchError>((hits, chunk.next))
+            }
+            .await;
+
+            match outcome {
+                Ok((hits, next)) => {
+                    result.hits.extend(hits);
+                    result.positions.insert(path, next);
+                }
+                Err(e) => {
+                    error!("failed to tail {}: {}", path.display(), e);
+                    result.failed_files.push((path, e.to_string()));
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn config_snapshot(&self) -> Config {
+        self.config.read().unwrap().clone()
+    }
+
+    fn scan_request_files(
+        &self,
+        request: &SearchRequest,
+        log_sources: &crate::config::LogSourceConfig,
+    ) -> Result<ScanOutcome> {
+        // 如果配置了全局路径，一并纳入
+        let scan_config = log_sources.with_defaults(&request.scan_config);
+        self.scanner
+            .follow_symlinked_dirs(log_sources.allow_symlinked_dirs)
+            .scan_detailed(&scan_config, &log_sources.log_file_paths)
+    }
+
+    /// 计算与具体文件无关的搜索参数：多行起始正则、时间过滤与分隔符。
+    fn plan(
+        &self,
+        request: &SearchRequest,
+        log_parser_config: &crate::config::LogParserConfig,
+        search_config: &crate::config::SearchConfig,
+    ) -> Result<SearchPlan> {
+        // 请求中的正则在这里统一按长度上限预编译校验，匹配阶段编译失败只会静默不命中。
+        let limited = self
+            .query
+            .clone()
+            .with_max_regex_len(Some(search_config.max_regex_len()));
+        let logical = &request.logical_query;
+        for q in logical
+            .must
+            .iter()
+            .chain(&logical.any)
+            .chain(&logical.none)
+            .chain(request.field_query.values())
+            .filter(|q| q.regex)
+        {
+            if let Some(pattern) = &q.query {
+                limited.compile_regex(pattern, q.case_sensitive)?;
+            }
+        }
+        if let Some(pattern) = request.time_filter.as_ref().and_then(|tf| tf.timestamp_regex.as_ref()) {
+            limited.compile_regex(log_parser_config.resolve_timestamp_regex(pattern)?, true)?;
+        }
+
+        let log_start_re = match &request.log_start_pattern {
+            Some(pat) => Some(limited.compile_regex(pat, true)?),
+            None => match &log_parser_config.default_log_start_pattern {
+                Some(pat) => Some(self.query.compile_regex(pat, true)?),
+                None => None,
             },
-            match_positions: positions,
-        });
+        };
+
+        let mut time_filter = request.time_filter.clone();
+        if let Some(ref mut tf) = time_filter {
+            if tf.timestamp_regex.is_none() {
+                tf.timestamp_regex = log_parser_config.default_timestamp_regex.clone();
+            }
+        } else if log_parser_config.default_timestamp_regex.is_some() || !request.time_windows.is_empty() {
+             time_filter = Some(TimeFilter {
+                 time_start: None,
+                 time_end: None,
+                 timestamp_regex: log_parser_config.default_timestamp_regex.clone(),
+             });
+        }
+        if let Some(tf) = &mut time_filter {
+            if let Some(pattern) = &tf.timestamp_regex {
+                tf.timestamp_regex = Some(log_parser_config.resolve_timestamp_regex(pattern)?.to_string());
+            }
+        }
+
+        let windows = parse_time_windows(&request.time_windows)?;
+        let time_filter = time_filter.as_ref().map(|tf| parse_time_filter(tf, windows));
+
+        let mut logical_query = request.logical_query.clone();
+        if request.unicode_normalize {
+            for q in logical_query
+                .must
+                .iter_mut()
+                .chain(&mut logical_query.any)
+                .chain(&mut logical_query.none)
+            {
+                if let Some(text) = &mut q.query {
+                    *text = nfc(text).into_owned();
+                }
+            }
+        }
+        // 全局噪声模式相当于隐式 none 条件，整次搜索只编译一次
+        let global_excludes = if request.ignore_global_excludes || search_config.global_exclude_patterns.is_empty() {
+            None
+        } else {
+            Some(regex::RegexSet::new(&search_config.global_exclude_patterns).map_err(|e| {
+                LogSearchError::ConfigError(format!("invalid search.global_exclude_patterns: {e}"))
+            })?)
+        };
+        if request.sort_by == SortBy::TimestampAsc
+            && time_filter.as_ref().and_then(|tf| tf.regex.as_ref()).is_none()
+        {
+            return Err(crate::error::LogSearchError::InvalidRequest(
+                "sort_by timestamp_asc requires time_filter.timestamp_regex or log_parser.default_timestamp_regex".into(),
+            ));
+        }
+
+        let level_filter = match &request.min_level {
+            Some(min_level) => {
+                let order: Vec<String> = log_parser_config.level_order.clone().unwrap_or_else(|| {
+                    DEFAULT_LEVEL_ORDER.iter().map(|s| s.to_string()).collect()
+                });
+                Some(LevelFilter::new(
+                    &order,
+                    log_parser_config.level_regex.as_deref(),
+                    min_level,
+                )?)
+            }
+            None => None,
+        };
+
+        let numeric_filters = request
+            .numeric_query
+            .iter()
+            .map(|q| NumericFilter::new(&limited, q))
+            .collect::<Result<Vec<_>>>()?;
+
+        let line_delimiter = request
+            .line_delimiter
+            .or(search_config.line_delimiter)
+            .unwrap_or(b'\n');
+        let match_ctx = MatchContext::new(
+            logical_query
+                .must
+                .iter()
+                .chain(&logical_query.any)
+                .chain(&logical_query.none)
+                .chain(request.field_query.values()),
+        )
+        .with_line_delimiter(line_delimiter);
+        Ok(SearchPlan {
+            log_start_re,
+            time_filter,
+            level_filter,
+            numeric_filters,
+            output_tz: request
+                .display_tz
+                .as_deref()
+                .or(search_config.output_tz.as_deref())
+                .map(parse_output_tz)
+                .transpose()?,
+            line_delimiter,
+            timings: request.profile.then(PhaseTimes::default),
+            logical_query,
+            match_ctx,
+            global_excludes,
+            lines_scanned: AtomicU64::new(0),
+            encoding_overrides: EncodingOverrides::new(&search_config.encoding_overrides)?,
+            read_budget: SharedReadBudget::new(search_config.max_total_read_bytes),
+            size_snapshot: None,
+            deadline: None,
+            refine: None,
+        })
+    }
+
+    // 如果 scan_entries_static 不是静态方法但我需要访问 self.query，则使用此辅助函数替代
+    async fn scan_entries(&self, entries: impl Stream<Item = Result<crate::model::LogEntry>> + Unpin, request: &SearchRequest, plan: &SearchPlan) -> Result<Vec<MatchedHit>> {
+         scan_entries_static(&self.query, entries, request, plan).await
+    }
+
+    pub fn validate_request(&self, request: &SearchRequest) -> Result<()> {
+        let global_cfg = self.config.read().unwrap();
+        let has_global = global_cfg.log_sources.log_file_paths.as_ref().map(|v| !v.is_empty()).unwrap_or(false);
+        let scan_config = global_cfg.log_sources.with_defaults(&request.scan_config);
+
+        if scan_config.root_path.as_os_str().is_empty() {
+             if !has_global {
+                 return Err(crate::error::LogSearchError::InvalidRequest("root_path is empty and no global log_file_paths or log_sources.default_root configured".to_string()));
+             }
+             // if has global, we skip directory check for root_path
+        } else {
+            // 如果提供了 root_path，我们需要验证它是否存在
+            // 但如果用户传入 "." 这种相对路径，fs::metadata 可能会基于当前工作目录去找
+            // MCP server 的工作目录通常是启动它的目录。
+            // 
+            // 关键修复：如果 request.scan_config.root_path 指向一个不存在的目录，但我们有全局日志配置，
+            // 我们应该宽容处理吗？或者，如果它是 ".", 且它存在，就没问题。
+            
+            let root_path = crate::config::expand_path(&scan_config.root_path);
+            let meta_res = std::fs::metadata(&root_path);
+            match meta_res {
+                Ok(meta) => {
+                     if !meta.is_dir() {
+                        return Err(crate::error::LogSearchError::InvalidRequest(format!(
+                            "{:?} is not a directory",
+                            root_path
+                        )));
+                    }
+                },
+                Err(e) => {
+                     // 如果校验失败，但我们有全局配置，且 root_path 看起来像是一个为了绕过必填检查的占位符（比如 "." 但如果当前目录不可读？）
+                     // 不过通常 "." 是存在的。
+                     // 如果用户传了一个无效路径，报错是合理的。
+                     return Err(crate::error::LogSearchError::FileAccessError {
+                        path: root_path,
+                        reason: e.to_string(),
+                    });
+                }
+            }
+        }
+
+        if request.page == 0 {
+            return Err(crate::error::LogSearchError::InvalidRequest(
+                "page must be >= 1".into(),
+            ));
+        }
+        let max_page = global_cfg.search.max_page();
+        if request.page > max_page {
+            return Err(crate::error::LogSearchError::InvalidRequest(format!(
+                "page {} exceeds search.max_page ({max_page}); set session_id and use next_cursor to page through deep results",
+                request.page
+            )));
+        }
+        if let Some((first, last)) = request.scan_config.line_range {
+            if first == 0 || first > last {
+                return Err(crate::error::LogSearchError::InvalidRequest(format!(
+                    "line_range [{first}, {last}] is invalid: lines start at 1 and the start must not exceed the end"
+                )));
+            }
+        }
+        let any = &request.logical_query;
+        if any.any_min_matches > any.any.len() && !any.any.is_empty() {
+            return Err(crate::error::LogSearchError::InvalidRequest(format!(
+                "any_min_matches ({}) exceeds the number of any terms ({})",
+                any.any_min_matches,
+                any.any.len()
+            )));
+        }
+        if let Some(unknown) = request
+            .fields
+            .iter()
+            .flatten()
+            .find(|f| !SEARCH_RESPONSE_FIELDS.contains(&f.as_str()))
+        {
+            return Err(crate::error::LogSearchError::InvalidRequest(format!(
+                "unknown response field '{unknown}'; expected one of {}",
+                SEARCH_RESPONSE_FIELDS.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// head_file 单次最多返回的行数。
+pub const MAX_HEAD_LINES: usize = 1000;
+
+/// `test_regex` 最多列出的匹配数。
+const MAX_REGEX_SAMPLE_MATCHES: usize = 100;
+
+/// 用 regex-syntax 重新解析模式以取得语法错误的位置；长度超限等非语法错误返回 `None`。
+fn regex_error_position(pattern: &str) -> Option<RegexErrorPosition> {
+    let span = match regex_syntax::Parser::new().parse(pattern).err()? {
+        regex_syntax::Error::Parse(e) => *e.span(),
+        regex_syntax::Error::Translate(e) => *e.span(),
+        _ => return None,
+    };
+    Some(RegexErrorPosition {
+        offset: span.start.offset,
+        line: span.start.line,
+        column: span.start.column,
+    })
+}
+
+/// `error_summary` 未给出 `level_regex` 时识别错误条目的正则。
+pub const DEFAULT_ERROR_REGEX: &str = r"(?i)\b(?:ERROR|ERR|FATAL|CRITICAL)\b";
+
+/// `distinct_values` 最多跟踪的不同取值数，防止高基数字段占满内存。
+pub const MAX_DISTINCT_VALUES: usize = 100_000;
+
+/// 截止时间到达后，仍在等待读取的文件任务最多再等待的时间，超过则丢弃其命中。
+const DEADLINE_GRACE: Duration = Duration::from_millis(100);
+
+const DEFAULT_READ_RETRIES: u32 = 2;
+const DEFAULT_READ_RETRY_DELAY_MS: u64 = 100;
+
+/// 网络文件系统上常见的瞬时错误可以重试；NotFound、PermissionDenied 等立即失败。
+fn is_retryable(err: &LogSearchError) -> bool {
+    const EIO: i32 = 5;
+    match err {
+        LogSearchError::Io(e) => {
+            matches!(
+                e.kind(),
+                std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::WouldBlock
+                    | std::io::ErrorKind::TimedOut
+            ) || e.raw_os_error() == Some(EIO)
+        }
+        _ => false,
+    }
+}
+
+/// 文件失败原因；进程打开的文件数达到上限（EMFILE/ENFILE）时给出可操作的提示。
+fn describe_file_error(err: &LogSearchError) -> String {
+    const ENFILE: i32 = 23;
+    const EMFILE: i32 = 24;
+    match err {
+        LogSearchError::Io(e) if cfg!(unix) && matches!(e.raw_os_error(), Some(EMFILE | ENFILE)) => format!(
+            "too many open files ({e}); lower search.max_open_files or raise the file descriptor limit (ulimit -n)"
+        ),
+        _ => err.to_string(),
+    }
+}
+
+/// 对单文件读取做有限次重试，每次等待时间翻倍。整个文件从头重新处理，不会产生重复命中。
+async fn with_read_retry<T, F, Fut>(retries: u32, delay: Duration, mut op: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Err(e) if attempt < retries && is_retryable(&e) => {
+                warn!("transient read error, retrying ({}/{}): {}", attempt + 1, retries, e);
+                tokio::time::sleep(delay * 2u32.saturating_pow(attempt)).await;
+                attempt += 1;
+            }
+            other => return other,
+        }
+    }
+}
+
+/// 按种子从文件列表中无放回抽取最多 `n` 个，保持原有路径顺序。
+fn sample_paths(files: Vec<PathBuf>, n: usize, seed: u64) -> Vec<PathBuf> {
+    if n >= files.len() {
+        return files;
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut picked = rand::seq::index::sample(&mut rng, files.len(), n).into_vec();
+    picked.sort_unstable();
+    picked.into_iter().map(|i| files[i].clone()).collect()
+}
+
+/// 一次搜索中与具体文件无关的准备结果。
+struct SearchPlan {
+    log_start_re: Option<regex::Regex>,
+    time_filter: Option<ParsedTimeFilter>,
+    line_delimiter: u8,
+    output_tz: Option<Tz>,
+    level_filter: Option<LevelFilter>,
+    numeric_filters: Vec<NumericFilter>,
+    /// 仅在 `profile` 时统计。
+    timings: Option<PhaseTimes>,
+    /// 请求的 `logical_query`（`unicode_normalize` 时已做 NFC）。
+    logical_query: LogicalQuery,
+    /// 查询词（含 `field_query`）中预编译的整词正则。
+    match_ctx: MatchContext,
+    /// `search.global_exclude_patterns`；任一命中的条目被丢弃，`ignore_global_excludes` 时为 `None`。
+    global_excludes: Option<regex::RegexSet>,
+    /// 所有文件任务累计扫描的物理行数。
+    lines_scanned: AtomicU64,
+    encoding_overrides: EncodingOverrides,
+    /// 本次搜索所有文件任务共享的读取预算（`search.max_total_read_bytes`）。
+    read_budget: SharedReadBudget,
+    /// `snapshot_sizes` 时各文件只读到的大小；不在其中的文件不读取。
+    size_snapshot: Option<Arc<HashMap<PathBuf, u64>>>,
+    /// `refine_from` 的结果集与模式；在匹配阶段过滤，使单文件与 `max_hits` 上限只计入保留的命中。
+    refine: Option<(ResultSet, RefineMode)>,
+    /// `total_timeout_ms` 对应的整体截止时间。
+    deadline: Option<tokio::time::Instant>,
+}
+
+/// 各阶段累计耗时（纳秒），由同一次搜索的所有文件任务共享。
+#[derive(Default)]
+struct PhaseTimes {
+    read: Arc<AtomicU64>,
+    /// 等待解析器产出条目的时间，包含其中的读取时间。
+    entries: Arc<AtomicU64>,
+    matching: AtomicU64,
+}
+
+impl PhaseTimes {
+    fn breakdown(&self, scan: Duration) -> TimingBreakdown {
+        let ms = |nanos: u64| nanos / 1_000_000;
+        let read = self.read.load(Ordering::Relaxed);
+        let entries = self.entries.load(Ordering::Relaxed);
+        TimingBreakdown {
+            scan_ms: scan.as_millis() as u64,
+            read_ms: ms(read),
+            parse_ms: ms(entries.saturating_sub(read)),
+            match_ms: ms(self.matching.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// 到达 `deadline` 后结束条目流并置 `expired`，使截止前得到的命中仍能返回。
+fn until_deadline<T: Send + 'static>(
+    inner: BoxStream<'static, T>,
+    deadline: tokio::time::Instant,
+    expired: Arc<AtomicBool>,
+) -> BoxStream<'static, T> {
+    inner
+        .take_while(move |_| {
+            let live = tokio::time::Instant::now() < deadline;
+            if !live {
+                expired.store(true, Ordering::Relaxed);
+            }
+            futures::future::ready(live)
+        })
+        .boxed()
+}
+
+/// 把每个元素从开始等待到产出的墙钟时间累加到 `counter`，含等待 IO 的时间。
+fn timed<T: Send + 'static>(
+    mut inner: BoxStream<'static, T>,
+    counter: Arc<AtomicU64>,
+) -> BoxStream<'static, T> {
+    let mut waiting_since: Option<Instant> = None;
+    Box::pin(stream::poll_fn(move |cx| {
+        let since = *waiting_since.get_or_insert_with(Instant::now);
+        let poll = inner.poll_next_unpin(cx);
+        if poll.is_ready() {
+            counter.fetch_add(since.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            waiting_since = None;
+        }
+        poll
+    }))
+}
+
+/// 增量搜索结果：新命中与各文件推进后的读取位置。
+#[derive(Debug, Default, Serialize)]
+pub struct IncrementalResult {
+    pub hits: Vec<HitResult>,
+    pub positions: HashMap<PathBuf, TailPosition>,
+    pub failed_files: Vec<(PathBuf, String)>,
+}
+
+/// 单条命中及其匹配细节，供汇总阶段统计使用，不直接对外输出。
+#[derive(Debug, Clone)]
+struct MatchedHit {
+    hit: HitResult,
+    /// 条目时间戳；自身没有时沿用同文件前一条目的时间，保证文件内有序。
+    timestamp: Option<DateTime<Utc>>,
+}
+
+impl MatchedHit {
+    fn into_hit(self, tz: Option<&Tz>) -> HitResult {
+        let mut hit = self.hit;
+        hit.timestamp = self.timestamp.map(|ts| format_output_time(ts, tz));
+        hit
+    }
+}
+
+/// 按 `score` 降序排列全部命中并取前 `limit` 条；分数相同时按文件路径和行号排序，保证结果稳定。
+fn sort_by_relevance(per_file: Vec<Vec<MatchedHit>>, limit: usize) -> Vec<MatchedHit> {
+    let mut hits: Vec<MatchedHit> = per_file.into_iter().flatten().collect();
+    hits.sort_by(|a, b| {
+        b.hit
+            .score
+            .cmp(&a.hit.score)
+            .then_with(|| a.hit.file_path.cmp(&b.hit.file_path))
+            .then_with(|| a.hit.start_line.cmp(&b.hit.start_line))
+    });
+    hits.truncate(limit);
+    hits
+}
+
+/// 多路归并各文件的命中（文件内已按行序），按时间戳升序输出前 `limit` 条。
+/// 没有时间戳的条目排在最前；时间相同时按文件路径和行号排序，保证结果稳定。
+fn merge_by_timestamp(per_file: Vec<Vec<MatchedHit>>, limit: usize) -> Vec<MatchedHit> {
+    type Key = (Option<DateTime<Utc>>, PathBuf, usize, usize);
+    let key = |m: &MatchedHit, idx: usize| -> Key {
+        (m.timestamp, m.hit.file_path.clone(), m.hit.start_line, idx)
+    };
+
+    let mut sources: Vec<std::vec::IntoIter<MatchedHit>> =
+        per_file.into_iter().map(Vec::into_iter).collect();
+    let mut heads: Vec<Option<MatchedHit>> = sources.iter_mut().map(Iterator::next).collect();
+    let mut heap: BinaryHeap<Reverse<Key>> = heads
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| h.as_ref().map(|m| Reverse(key(m, i))))
+        .collect();
+
+    let mut merged = Vec::with_capacity(limit);
+    while merged.len() < limit {
+        let Some(Reverse((.., idx))) = heap.pop() else {
+            break;
+        };
+        let Some(hit) = heads[idx].take() else {
+            break;
+        };
+        heads[idx] = sources[idx].next();
+        if let Some(next) = &heads[idx] {
+            heap.push(Reverse(key(next, idx)));
+        }
+        merged.push(hit);
+    }
+    merged
+}
+
+/// 请求的每页条数：0 取默认值，其余限制在 [1, max_page_size]。
+fn effective_page_size(request: &SearchRequest, search_config: &crate::config::SearchConfig) -> usize {
+    if request.page_size == 0 {
+        search_config.default_page_size.max(1)
+    } else {
+        request
+            .page_size
+            .min(search_config.max_page_size)
+            .max(1)
+    }
+}
+
+/// 对一页命中应用 `max_total_positions` 上限，并按需按文件分组。
+fn present_page(mut hits: Vec<HitResult>, request: &SearchRequest) -> (Vec<HitResult>, Option<Vec<crate::model::FileHits>>) {
+    if let Some(cap) = request.max_total_positions {
+        let mut remaining = cap;
+        for hit in &mut hits {
+            hit.match_positions.truncate(remaining);
+            remaining -= hit.match_positions.len();
+        }
+    }
+    if let Some(base) = &request.relative_to {
+        let base = crate::config::expand_path(base);
+        for hit in &mut hits {
+            if let Ok(relative) = hit.file_path.strip_prefix(&base) {
+                hit.file_path = relative.to_path_buf();
+            }
+        }
+    }
+    if request.group_by_file {
+        (Vec::new(), Some(group_hits_by_file(hits)))
+    } else {
+        (hits, None)
+    }
+}
+
+/// 扫描并筛选后待搜索的文件。
+struct ResolvedFiles {
+    files: Vec<PathBuf>,
+    scan_errors: Vec<(PathBuf, String)>,
+    refine_set: Option<ResultSet>,
+    sample_seed: Option<u64>,
+}
+
+/// 逐行去掉 ANSI 控制序列，位于解析与匹配之前，后续的内容与匹配位置都基于去除后的文本。
+fn strip_ansi_lines(lines: BoxStream<'static, Result<String>>) -> BoxStream<'static, Result<String>> {
+    lines
+        .map(|line| {
+            line.map(|l| match strip_ansi(&l) {
+                std::borrow::Cow::Borrowed(_) => l,
+                std::borrow::Cow::Owned(stripped) => stripped,
+            })
+        })
+        .boxed()
+}
+
+#[derive(Debug)]
+struct TaskResult {
+    path: PathBuf,
+    hits: Vec<MatchedHit>,
+    failed: Option<String>,
+    timed_out: bool,
+}
+
+async fn scan_entries_static(
+    query: &QueryProcessor,
+    mut entries: impl Stream<Item = Result<crate::model::LogEntry>> + Unpin,
+    request: &SearchRequest,
+    plan: &SearchPlan,
+) -> Result<Vec<MatchedHit>> {
+    let mut hits: Vec<MatchedHit> = Vec::new();
+    let mut last_timestamp = None;
+    // 最近的若干条目，用作下一条命中的前文
+    let mut recent: VecDeque<ContextEntry> = VecDeque::new();
+    // (命中下标, 还需补充的后文条目数)
+    let mut pending_after: Vec<(usize, usize)> = Vec::new();
+    let mut limit_reached = false;
+    // 单个文件的命中上限：max_hits 与 max_hits_per_file 取较小者
+    let file_limit = match (request.max_hits, request.max_hits_per_file) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
+    // 本次扫描计入的行数；读取出错时撤销，使重试的文件不被重复计入
+    let mut counted = 0u64;
+    while let Some(entry) = entries.next().await {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                plan.lines_scanned.fetch_sub(counted, Ordering::Relaxed);
+                return Err(e);
+            }
+        };
+        let lines = (entry.end_line + 1).saturating_sub(entry.start_line) as u64;
+        plan.lines_scanned.fetch_add(lines, Ordering::Relaxed);
+        counted += lines;
+
+        for (idx, remaining) in pending_after.iter_mut() {
+            hits[*idx].hit.context_after.push(ContextEntry::from(&entry));
+            *remaining -= 1;
+        }
+        pending_after.retain(|(_, remaining)| *remaining > 0);
+        let (in_range, past_range) = match request.scan_config.line_range {
+            Some((first, last)) => (
+                (first..=last).contains(&entry.start_line),
+                entry.start_line > last,
+            ),
+            None => (true, false),
+        };
+        if past_range {
+            limit_reached = true;
+        }
+        if limit_reached {
+            // 命中数已达上限，只继续读取以补齐后文
+            if pending_after.is_empty() {
+                break;
+            }
+            continue;
+        }
+
+        let match_started = plan.timings.as_ref().map(|_| Instant::now());
+        let matched = if in_range {
+            match_entry(query, &entry, request, plan, &mut last_timestamp)
+        } else {
+            None
+        };
+        if let (Some(timings), Some(started)) = (&plan.timings, match_started) {
+            timings
+                .matching
+                .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+        if let Some(mut matched) = matched {
+            matched.hit.context_before = recent.iter().cloned().collect();
+            hits.push(matched);
+            if request.context_entries_after > 0 {
+                pending_after.push((hits.len() - 1, request.context_entries_after));
+            }
+            if let Some(limit) = file_limit {
+                if hits.len() >= limit {
+                    limit_reached = true;
+                    if pending_after.is_empty() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if request.context_entries_before > 0 {
+            if recent.len() == request.context_entries_before {
+                recent.pop_front();
+            }
+            recent.push_back(ContextEntry::from(&entry));
+        }
+    }
+    if request.merge_context && request.include_content {
+        for m in &mut hits {
+            merge_context(&mut m.hit);
+        }
+    }
+    Ok(hits)
+}
+
+/// 把前后文并入 `content`：按行序拼接（缺少结尾换行的片段补一个 `\n`），
+/// 匹配位置平移前文的长度，并记录拼接文本的首行行号。
+fn merge_context(hit: &mut HitResult) {
+    if hit.context_before.is_empty() && hit.context_after.is_empty() {
+        return;
+    }
+    let before = std::mem::take(&mut hit.context_before);
+    let after = std::mem::take(&mut hit.context_after);
+    hit.content_start_line = Some(before.first().map_or(hit.start_line, |c| c.start_line));
+
+    fn push_line(merged: &mut String, piece: &str) {
+        if !merged.is_empty() && !merged.ends_with('\n') {
+            merged.push('\n');
+        }
+        merged.push_str(piece);
+    }
+    let mut merged = String::new();
+    for c in &before {
+        push_line(&mut merged, &c.content);
+    }
+    push_line(&mut merged, "");
+    let shift = merged.len();
+    push_line(&mut merged, &hit.content);
+    for c in &after {
+        push_line(&mut merged, &c.content);
+    }
+    for p in &mut hit.match_positions {
+        p.offset += shift;
+    }
+    hit.content = merged;
+}
+
+/// 对单个条目应用时间、级别、内容和字段过滤，匹配时构造命中。
+fn match_entry(
+    query: &QueryProcessor,
+    entry: &LogEntry,
+    request: &SearchRequest,
+    plan: &SearchPlan,
+    last_timestamp: &mut Option<DateTime<Utc>>,
+) -> Option<MatchedHit> {
+    let time_filter = &plan.time_filter;
+
+    // 输出调试信息到 stderr（不会影响 stdout json-rpc）
+    // eprintln!("DEBUG: checking entry: {}", entry.content.lines().next().unwrap_or(""));
+
+    let timestamp = query.extract_timestamp(&entry.content, time_filter);
+    if !in_time_range(timestamp, time_filter) {
+        // eprintln!("DEBUG: time filter rejected");
+        return None;
+    }
+    if timestamp.is_some() {
+        *last_timestamp = timestamp;
+    }
+    if let Some((set, mode)) = &plan.refine {
+        if set.contains(&entry.file_path, entry.start_line) != (*mode == RefineMode::Intersect) {
+            return None;
+        }
+    }
+    if let Some(level_filter) = &plan.level_filter {
+        if !level_filter.accepts(&entry.content) {
+            return None;
+        }
+    }
+    let content = if request.unicode_normalize {
+        nfc(&entry.content)
+    } else {
+        std::borrow::Cow::Borrowed(entry.content.as_str())
+    };
+    let normalized = request
+        .normalize_whitespace
+        .then(|| NormalizedText::new(&content));
+    let match_text = normalized.as_ref().map_or(content.as_ref(), |n| n.text.as_str());
+    if plan.global_excludes.as_ref().is_some_and(|set| set.is_match(match_text)) {
+        return None;
+    }
+    if !query.matches(match_text, &plan.logical_query, &plan.match_ctx) {
+        // eprintln!("DEBUG: content match rejected");
+        return None;
+    }
+    if !query.matches_fields(&entry.content, request.log_format, &request.field_query, &plan.match_ctx) {
+        return None;
+    }
+    if !plan.numeric_filters.iter().all(|f| f.accepts(&entry.content)) {
+        return None;
+    }
+
+    let (mut positions, terms, score) = collect_positions_static(query, match_text, &plan.logical_query, &plan.match_ctx);
+    if let Some(normalized) = &normalized {
+        positions = positions.iter().map(|p| normalized.map_position(p)).collect();
+    }
+    Some(MatchedHit {
+        hit: HitResult {
+            file_path: entry.file_path.clone(),
+            start_line: entry.start_line,
+            end_line: entry.end_line,
+            content: if request.include_content {
+                content.into_owned()
+            } else {
+                String::new()
+            },
+            match_positions: positions,
+            timestamp: None,
+            context_before: Vec::new(),
+            context_after: Vec::new(),
+            matched_terms: terms,
+            content_start_line: None,
+            score,
+        },
+        timestamp: *last_timestamp,
+    })
+}
+
+/// 收集所有查询词的匹配位置，记录哪些 must/any 查询词在条目中出现，并计算相关度：
+/// `10 × 出现的不同查询词数 + must/any 匹配位置数`。
+fn collect_positions_static(
+    query: &QueryProcessor,
+    text: &str,
+    logical: &LogicalQuery,
+    ctx: &MatchContext,
+) -> (Vec<MatchPosition>, Vec<String>, usize) {
+    let mut positions = Vec::new();
+    let mut terms = Vec::new();
+    for q in logical.must.iter().chain(logical.any.iter()) {
+        let found = query.find_positions(text, q, ctx);
+        if !found.is_empty() {
+            if let Some(term) = &q.query {
+                if !terms.contains(term) {
+                    terms.push(term.clone());
+                }
+            }
+        }
+        positions.extend(found);
+    }
+    let score = 10 * terms.len() + positions.len();
+    for q in &logical.none {
+        positions.extend(query.find_positions(text, q, ctx));
+    }
+    (positions, terms, score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{FileScanConfig, LogFormat, LogicalQuery, ScanOrder, SearchQuery};
+    use crate::config::{Config, LogParserConfig, LogSourceConfig, SearchConfig, ServerConfig, ServerMode};
+    use tempfile::tempdir;
+
+    fn test_config() -> Config {
+         Config {
+              server: ServerConfig { mode: ServerMode::Stdio, http_addr: None, http_port: None, max_watchers: None, watch_poll_ms: None, watch_token: None, max_sse_sessions: None, sse_keepalive_secs: None, http_idle_timeout_secs: None },
+              log_parser: LogParserConfig { default_log_start_pattern: None, default_timestamp_regex: None, level_order: None, level_regex: None, timestamp_presets: Default::default() },
+              search: SearchConfig::default(),
+              log_sources: LogSourceConfig::default(),
+         }
+    }
+
+    fn create_test_engine(buffer_size: usize) -> SearchEngine {
+         let mut cfg = test_config();
+         cfg.search.buffer_size = buffer_size;
+         SearchEngine::new(Arc::new(RwLock::new(cfg)))
+    }
+
+    fn sq(text: &str) -> SearchQuery {
+        SearchQuery {
+            query: Some(text.to_string()),
+            regex: false,
+            case_sensitive: false,
+            whole_word: false,
+            whole_line: false,
+            per_physical_line: false,
+        }
+    }
+
+    fn base_request(root: PathBuf, logical_query: LogicalQuery) -> SearchRequest {
+        SearchRequest {
+            scan_config: FileScanConfig {
+                root_path: root,
+                include_globs: vec!["**/*.log".to_string()],
+                exclude_globs: vec![],
+                filename_query: None,
+                content_type_filter: Default::default(),
+                line_range: None,
+            },
+            logical_query,
+            time_filter: None,
+            time_windows: vec![],
+            log_start_pattern: None,
+            page_size: 10,
+            page: 1,
+            max_hits: None,
+            hard_timeout_ms: None,
+            include_content: true,
+            sample_files: None,
+            sample_seed: None,
+            session_id: None,
+            save_as: None,
+            refine_from: None,
+            refine_mode: RefineMode::Intersect,
+            line_delimiter: None,
+            max_total_positions: None,
+            log_format: LogFormat::Text,
+            field_query: HashMap::new(),
+            numeric_query: vec![],
+            sort_by: SortBy::Unordered,
+            group_by_file: false,
+            template: false,
+            min_level: None,
+            context_entries_before: 0,
+            context_entries_after: 0,
+            merge_context: false,
+            fields: None,
+            cursor: None,
+            total_timeout_ms: None,
+            on_file_error: ErrorMode::Collect,
+            normalize_whitespace: false,
+            unicode_normalize: false,
+            strip_ansi: false,
+            relative_to: None,
+            snapshot_sizes: false,
+            snapshot_id: None,
+            exclude_session_files: None,
+            scan_first_bytes: None,
+            max_hits_per_file: None,
+            profile: false,
+            ignore_global_excludes: false,
+            group_rotations: false,
+            context_separator: None,
+            render: None,
+            display_tz: None,
+            scan_order: ScanOrder::PathAsc,
+        }
+    }
+
+    #[tokio::test]
+    async fn single_line_search_respects_must_and_none() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("demo.log");
+        std::fs::write(
+            &path,
+            "ok line\nerror traffic\nfatal error\n",
+        )
+        .unwrap();
+
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![sq("fatal")],
+            any_min_matches: 1,
+        };
+        let req = base_request(dir.path().to_path_buf(), logical);
+        let engine = create_test_engine(32 * 1024);
+
+        let hits = engine.search_file(path, &req).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].content.contains("error traffic"));
+    }
+
+    #[tokio::test]
+    async fn strip_ansi_cleans_content_before_matching() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("color.log");
+        std::fs::write(&path, "\x1b[32mINFO\x1b[0m ok\n\x1b[31mERROR\x1b[0m db down\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("ERROR db")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        let engine = create_test_engine(32 * 1024);
+        assert!(engine.search_file(path.clone(), &req).await.unwrap().is_empty());
+
+        req.strip_ansi = true;
+        let hits = engine.search_file(path, &req).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].content.trim_end(), "ERROR db down");
+        assert_eq!(hits[0].match_positions[0].offset, 0);
+        assert_eq!(hits[0].match_positions[0].length, 8);
+    }
+
+    #[tokio::test]
+    async fn unicode_normalize_matches_decomposed_text() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("i18n.log");
+        std::fs::write(&path, "user Jose\u{301} logged in\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("José")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        let engine = create_test_engine(32 * 1024);
+        assert!(engine.search_file(path.clone(), &req).await.unwrap().is_empty());
+
+        req.unicode_normalize = true;
+        let hits = engine.search_file(path, &req).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].content.trim_end(), "user José logged in");
+        assert_eq!(hits[0].match_positions[0].offset, 5);
+        assert_eq!(hits[0].match_positions[0].length, "José".len());
+    }
+
+    #[tokio::test]
+    async fn multiline_search_aggregates_entries() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("multi.log");
+        std::fs::write(
+            &path,
+            "2025-01-01 INFO start\nline-a\n2025-01-01 ERROR boom\nline-b\n",
+        )
+        .unwrap();
+
+        let logical = LogicalQuery {
+            must: vec![sq("ERROR")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.log_start_pattern = Some(r"^\d{4}-\d{2}-\d{2}".to_string());
+
+        let engine = create_test_engine(32 * 1024);
+        let hits = engine.search_file(path, &req).await.unwrap();
+
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].content.starts_with("2025-01-01 ERROR"));
+        assert!(hits[0].content.contains("line-b"));
+        assert!(hits[0].start_line <= hits[0].end_line);
+    }
+
+    #[test]
+    fn test_regex_reports_matches_and_error_position() {
+        let engine = create_test_engine(32 * 1024);
+        let result = engine.test_regex(
+            r"latency=(?P<ms>\d+)(s)?",
+            "GET /a latency=12\nGET /b latency=900 done",
+            true,
+        );
+        assert!(result.valid);
+        assert_eq!(result.matches.len(), 2);
+        let second = &result.matches[1];
+        assert_eq!((second.line, second.text.as_str()), (2, "latency=900"));
+        assert_eq!(second.captures, vec![Some("900".to_string()), None]);
+        assert_eq!(second.named["ms"], "900");
+
+        let result = engine.test_regex("error (timeout", "", true);
+        assert!(!result.valid);
+        assert!(result.error.is_some());
+        let pos = result.error_position.unwrap();
+        assert_eq!((pos.offset, pos.line, pos.column), (6, 1, 7));
+    }
+
+    #[tokio::test]
+    async fn distinct_values_counts_first_capture() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("a.log"),
+            "error_code=E1 x\nerror_code=E2\nok\nerror_code=E1 error_code=E9\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("b.log"), "error_code=E2\nerror_code=E1\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let scan = base_request(dir.path().to_path_buf(), logical).scan_config;
+        let engine = create_test_engine(32 * 1024);
+
+        let result = engine.distinct_values(&scan, r"error_code=(\w+)", 10).await.unwrap();
+        let values: Vec<(&str, usize)> =
+            result.values.iter().map(|v| (v.value.as_str(), v.count)).collect();
+        assert_eq!(values, vec![("E1", 3), ("E2", 2)]);
+        assert_eq!((result.distinct, result.matched_lines, result.files_scanned), (2, 5, 2));
+
+        let top = engine.distinct_values(&scan, r"error_code=(\w+)", 1).await.unwrap();
+        assert_eq!(top.values.len(), 1);
+        assert_eq!(top.distinct, 2);
+        assert!(engine.distinct_values(&scan, r"error_code=\w+", 10).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn search_invalid_root_returns_error() {
+        let root = std::path::PathBuf::from("D:/path/does/not/exist");
+        let logical = LogicalQuery {
+            must: vec![sq("anything")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let req = base_request(root, logical);
+        let engine = create_test_engine(32 * 1024);
+        let err = engine.search(req).await.unwrap_err().to_string();
+        assert!(err.contains("文件访问错误") || err.contains("not a directory"));
+    }
+
+    #[tokio::test]
+    async fn search_rejects_scan_exceeding_max_files() {
+        let dir = tempdir().unwrap();
+        for name in ["a.log", "b.log", "c.log"] {
+            std::fs::write(dir.path().join(name), "error here\n").unwrap();
+        }
+
+        let mut cfg = test_config();
+        cfg.search.max_files = Some(2);
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+
+        let err = engine
+            .search(base_request(dir.path().to_path_buf(), logical))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("max_files"));
+    }
+
+    #[tokio::test]
+    async fn sample_files_is_reproducible_with_seed() {
+        let dir = tempdir().unwrap();
+        for i in 0..10 {
+            std::fs::write(dir.path().join(format!("f{i}.log")), "error here\n").unwrap();
+        }
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.sample_files = Some(3);
+        req.sample_seed = Some(42);
+
+        let engine = create_test_engine(32 * 1024);
+        let first = engine.search(req.clone()).await.unwrap();
+        let second = engine.search(req).await.unwrap();
+
+        assert_eq!(first.files_sampled, Some(3));
+        assert_eq!(first.sample_seed, Some(42));
+        assert_eq!(first.files_scanned, 3);
+        let mut a: Vec<_> = first.hits.iter().map(|h| h.file_path.clone()).collect();
+        let mut b: Vec<_> = second.hits.iter().map(|h| h.file_path.clone()).collect();
+        a.sort();
+        b.sort();
+        assert_eq!(a, b);
+    }
+
+    #[tokio::test]
+    async fn refine_from_saved_result_set() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("app.log"),
+            "error db timeout\nerror disk full\ninfo timeout retry\n",
+        )
+        .unwrap();
+        let engine = create_test_engine(32 * 1024);
+        let query = |term: &str| LogicalQuery {
+            must: vec![sq(term)],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+
+        let mut first = base_request(dir.path().to_path_buf(), query("error"));
+        first.session_id = Some("s1".into());
+        first.save_as = Some("errors".into());
+        assert_eq!(engine.search(first).await.unwrap().total_hits, 2);
+
+        let mut refine = base_request(dir.path().to_path_buf(), query("timeout"));
+        refine.session_id = Some("s1".into());
+        refine.refine_from = Some("errors".into());
+        let resp = engine.search(refine.clone()).await.unwrap();
+        assert_eq!(resp.total_hits, 1);
+        assert!(resp.hits[0].content.contains("error db timeout"));
+
+        refine.refine_mode = RefineMode::Subtract;
+        let resp = engine.search(refine.clone()).await.unwrap();
+        assert_eq!(resp.total_hits, 1);
+        assert!(resp.hits[0].content.contains("info timeout retry"));
+
+        refine.session_id = Some("other".into());
+        assert!(engine.search(refine).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refine_from_is_applied_before_hit_caps() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("app.log"),
+            "info timeout 1\ninfo timeout 2\ninfo timeout 3\nerror timeout a\nerror timeout b\n",
+        )
+        .unwrap();
+        let engine = create_test_engine(32 * 1024);
+        let query = |term: &str| LogicalQuery {
+            must: vec![sq(term)],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+
+        let mut first = base_request(dir.path().to_path_buf(), query("error"));
+        first.session_id = Some("s1".into());
+        first.save_as = Some("errors".into());
+        assert_eq!(engine.search(first).await.unwrap().total_hits, 2);
+
+        // 前三条原始命中不在结果集中，不应占用 max_hits / max_hits_per_file 的名额
+        let mut refine = base_request(dir.path().to_path_buf(), query("timeout"));
+        refine.session_id = Some("s1".into());
+        refine.refine_from = Some("errors".into());
+        refine.max_hits = Some(2);
+        let resp = engine.search(refine.clone()).await.unwrap();
+        assert_eq!(resp.total_hits, 2);
+        assert!(resp.hits.iter().all(|h| h.content.starts_with("error")));
+
+        refine.max_hits = None;
+        refine.max_hits_per_file = Some(1);
+        let resp = engine.search(refine).await.unwrap();
+        assert_eq!(resp.total_hits, 1);
+        assert!(resp.hits[0].content.contains("error timeout a"));
+    }
+
+    #[tokio::test]
+    async fn per_query_counts_track_each_term() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("app.log"),
+            "db timeout\ndb refused\ncache timeout\nnothing\n",
+        )
+        .unwrap();
+        let logical = LogicalQuery {
+            must: vec![],
+            any: vec![sq("timeout"), sq("refused"), sq("db")],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let engine = create_test_engine(32 * 1024);
+        let resp = engine
+            .search(base_request(dir.path().to_path_buf(), logical))
+            .await
+            .unwrap();
+
+        assert_eq!(resp.total_hits, 3);
+        assert_eq!(resp.per_query_counts.get("timeout"), Some(&2));
+        assert_eq!(resp.per_query_counts.get("refused"), Some(&1));
+        assert_eq!(resp.per_query_counts.get("db"), Some(&2));
+
+        // 每条命中列出触发它的查询词，按查询中的顺序
+        let terms: Vec<Vec<String>> = resp.hits.iter().map(|h| h.matched_terms.clone()).collect();
+        assert_eq!(terms, vec![vec!["timeout", "db"], vec!["refused", "db"], vec!["timeout"]]);
+    }
+
+    #[tokio::test]
+    async fn max_total_positions_caps_positions_across_hits() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "err err\nerr\nerr err err\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("err")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.max_total_positions = Some(3);
+        let engine = create_test_engine(32 * 1024);
+        let mut resp = engine.search(req).await.unwrap();
+        resp.hits.sort_by_key(|h| h.start_line);
+
+        assert_eq!(resp.total_hits, 3);
+        let counts: Vec<usize> = resp.hits.iter().map(|h| h.match_positions.len()).collect();
+        assert_eq!(counts.iter().sum::<usize>(), 3);
+        assert!(resp.hits.iter().all(|h| !h.content.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn read_retry_only_retries_transient_errors() {
+        use std::io::{Error, ErrorKind};
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let calls = AtomicU32::new(0);
+        let result = with_read_retry(2, Duration::from_millis(1), || async {
+            if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err(LogSearchError::Io(Error::from_raw_os_error(5)))
+            } else {
+                Ok(7)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let calls = AtomicU32::new(0);
+        let result: Result<()> = with_read_retry(2, Duration::from_millis(1), || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(LogSearchError::Io(Error::from(ErrorKind::NotFound)))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_read_attempts_are_not_counted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "error 1\nerror 2\n").unwrap();
+        let cfg = test_config();
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg.clone())));
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let req = base_request(dir.path().to_path_buf(), logical);
+        let plan = engine.plan(&req, &cfg.log_parser, &cfg.search).unwrap();
+
+        // 中途出错的扫描撤销已计入的行数
+        let entry = LogEntry {
+            file_path: path.clone(),
+            start_line: 1,
+            end_line: 2,
+            content: "error 1\nerror 2\n".into(),
+        };
+        let failing = stream::iter(vec![
+            Ok(entry),
+            Err(LogSearchError::Io(std::io::Error::from_raw_os_error(5))),
+        ]);
+        assert!(scan_entries_static(&engine.query, failing, &req, &plan).await.is_err());
+        assert_eq!(plan.lines_scanned.load(Ordering::Relaxed), 0);
+
+        // 撤销失败尝试读取的字节
+        let attempt = Arc::new(AtomicU64::new(0));
+        let reader = FileReader::new(1024)
+            .with_shared_budget(Some(plan.read_budget.clone()))
+            .with_attempt_counter(attempt.clone());
+        let lines: Vec<_> = reader.read_lines(&path).await.unwrap().collect().await;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(plan.read_budget.used(), 16);
+        reader.release(attempt.load(Ordering::Relaxed));
+        assert_eq!((plan.read_budget.used(), reader.bytes_read()), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn field_query_filters_logfmt_entries() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("app.log"),
+            "level=error msg=\"db down\"\nlevel=info msg=\"error budget ok\"\n",
+        )
+        .unwrap();
+        let logical = LogicalQuery {
+            must: vec![],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.log_format = LogFormat::Logfmt;
+        req.field_query.insert("level".to_string(), sq("error"));
+        let engine = create_test_engine(32 * 1024);
+        let resp = engine.search(req).await.unwrap();
+
+        assert_eq!(resp.total_hits, 1);
+        assert_eq!(resp.hits[0].start_line, 1);
+    }
+
+    #[tokio::test]
+    async fn json_array_export_is_searched_per_element() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("export.json"),
+            "[\n  {\"level\": \"error\", \"msg\": \"db down, retry [1]\"},\n  {\n    \"level\": \"info\",\n    \"ctx\": {\"user\": \"alice\"}\n  }\n]\n",
+        )
+        .unwrap();
+        let logical = LogicalQuery {
+            must: vec![],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.scan_config.include_globs = vec!["**/*.json".to_string()];
+        req.log_format = LogFormat::Json;
+        req.field_query.insert("ctx.user".to_string(), sq("alice"));
+        let engine = create_test_engine(32 * 1024);
+        let resp = engine.search(req.clone()).await.unwrap();
+
+        assert_eq!(resp.total_hits, 1);
+        assert_eq!((resp.hits[0].start_line, resp.hits[0].end_line), (3, 6));
+
+        req.field_query.clear();
+        req.field_query.insert("level".to_string(), sq("error"));
+        let resp = engine.search(req).await.unwrap();
+        assert_eq!(resp.total_hits, 1);
+        assert!(resp.hits[0].content.contains("retry [1]"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unreadable_directories_are_reported() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        std::fs::create_dir(&locked).unwrap();
+        std::fs::write(locked.join("hidden.log"), "error\n").unwrap();
+        std::fs::write(dir.path().join("app.log"), "error\n").unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+        if std::fs::read_dir(&locked).is_ok() {
+            // 以 root 运行时权限位不生效，无法构造该场景。
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+            return;
+        }
+
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let engine = create_test_engine(32 * 1024);
+        let resp = engine
+            .search(base_request(dir.path().to_path_buf(), logical.clone()))
+            .await;
+        let root_err = engine.search(base_request(locked.clone(), logical)).await;
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let resp = resp.unwrap();
+        assert_eq!(resp.total_hits, 1);
+        assert_eq!(resp.failed_files.len(), 1);
+        assert_eq!(resp.failed_files[0].0, locked);
+        assert!(matches!(root_err, Err(LogSearchError::FileAccessError { .. })));
+    }
+
+    #[tokio::test]
+    async fn on_file_error_collects_fails_or_ignores() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "error\n").unwrap();
+        // strict_utf8 时非 UTF-8 的 gzip 内容在读取时报编码错误
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut enc, b"error \xff\xfe\n").unwrap();
+        std::fs::write(dir.path().join("bad.log.gz"), enc.finish().unwrap()).unwrap();
+
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.scan_config.include_globs = vec!["**/*.log".to_string(), "**/*.gz".to_string()];
+        let mut cfg = test_config();
+        cfg.search.strict_utf8 = true;
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+
+        let resp = engine.search(req.clone()).await.unwrap();
+        assert_eq!((resp.total_hits, resp.failed_files.len()), (1, 1));
+
+        req.on_file_error = ErrorMode::Ignore;
+        let resp = engine.search(req.clone()).await.unwrap();
+        assert_eq!((resp.total_hits, resp.failed_files.len()), (1, 0));
+
+        req.on_file_error = ErrorMode::Fail;
+        assert!(engine.search(req).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn failed_files_list_is_capped() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "error\n").unwrap();
+        for name in ["a.log.gz", "b.log.gz", "c.log.gz"] {
+            let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            std::io::Write::write_all(&mut enc, b"error \xff\xfe\n").unwrap();
+            std::fs::write(dir.path().join(name), enc.finish().unwrap()).unwrap();
+        }
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.scan_config.include_globs = vec!["**/*.log".to_string(), "**/*.gz".to_string()];
+        let mut cfg = test_config();
+        cfg.search.max_failed_files_reported = Some(1);
+        cfg.search.strict_utf8 = true;
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+
+        let resp = engine.search(req).await.unwrap();
+        assert_eq!(resp.total_hits, 1);
+        assert_eq!(resp.failed_files.len(), 1);
+        assert_eq!(resp.failed_files_truncated_count, 2);
+    }
+
+    #[tokio::test]
+    async fn root_path_env_vars_are_expanded() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "error\n").unwrap();
+        std::env::set_var("LOG_SEARCH_MCP_TEST_ROOT", dir.path());
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let req = base_request(PathBuf::from("${LOG_SEARCH_MCP_TEST_ROOT}"), logical);
+        let resp = create_test_engine(32 * 1024).search(req).await.unwrap();
+        assert_eq!(resp.total_hits, 1);
+    }
+
+    #[tokio::test]
+    async fn default_root_used_when_root_path_empty() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "error\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let req = base_request(PathBuf::new(), logical);
+        assert!(create_test_engine(32 * 1024).search(req.clone()).await.is_err());
+
+        let mut cfg = test_config();
+        cfg.log_sources.default_root = Some(dir.path().to_path_buf());
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+        assert_eq!(engine.list_files(&req.scan_config).unwrap().len(), 1);
+        assert_eq!(engine.search(req).await.unwrap().total_hits, 1);
+    }
+
+    #[tokio::test]
+    async fn total_timeout_bounds_whole_search() {
+        let dir = tempdir().unwrap();
+        for i in 0..500 {
+            std::fs::write(dir.path().join(format!("f{i:03}.log")), "error\n".repeat(200)).unwrap();
+        }
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut cfg = test_config();
+        cfg.search.max_concurrent_files = 1;
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.total_timeout_ms = Some(1);
+        let resp = engine.search(req.clone()).await.unwrap();
+        assert!(resp.timed_out);
+        assert!(resp.files_scanned < 500);
+
+        req.total_timeout_ms = None;
+        let resp = engine.search(req).await.unwrap();
+        assert!(!resp.timed_out);
+        assert_eq!(resp.files_scanned, 500);
+    }
+
+    #[tokio::test]
+    async fn total_timeout_keeps_hits_found_before_deadline() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.log"), "error fast\n").unwrap();
+        std::fs::write(dir.path().join("b.log"), "error slow\n".repeat(1_000_000)).unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut cfg = test_config();
+        cfg.search.max_concurrent_files = 1;
+        cfg.search.default_timeout_ms = 0;
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.page_size = 1;
+        req.total_timeout_ms = Some(100);
+        let resp = engine.search(req).await.unwrap();
+        assert!(resp.timed_out);
+        assert_eq!(resp.hits[0].file_path, dir.path().join("a.log"));
+        // 进行中的大文件交回了截止前得到的部分命中
+        assert!(resp.total_hits > 1);
+        assert!(resp.total_hits < 1_000_001);
+    }
+
+    #[tokio::test]
+    async fn export_stops_at_total_timeout() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.log"), "error fast\n").unwrap();
+        std::fs::write(dir.path().join("b.log"), "error slow\n".repeat(1_000_000)).unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut cfg = test_config();
+        cfg.search.max_concurrent_files = 1;
+        cfg.search.default_timeout_ms = 0;
+        let engine = Arc::new(SearchEngine::new(Arc::new(RwLock::new(cfg))));
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.total_timeout_ms = Some(100);
+        let hits: Vec<HitResult> = engine.search_export(req).await.unwrap().collect().await;
+        assert_eq!(hits[0].file_path, dir.path().join("a.log"));
+        assert!(hits.len() > 1);
+        assert!(hits.len() < 1_000_001);
+    }
+
+    #[tokio::test]
+    async fn cursor_pages_through_saved_results() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "error 1\nerror 2\nerror 3\nerror 4\nerror 5\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let engine = create_test_engine(32 * 1024);
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.page_size = 2;
+        // 未给出 session_id 时不保存游标
+        assert!(engine.search(req.clone()).await.unwrap().next_cursor.is_none());
+
+        req.session_id = Some("s1".to_string());
+        let first = engine.search(req.clone()).await.unwrap();
+        assert_eq!(first.hits.len(), 2);
+
+        // 文件改变不影响已保存的游标结果
+        std::fs::write(&path, "error x\n").unwrap();
+        req.cursor = first.next_cursor.clone();
+        let second = engine.search(req.clone()).await.unwrap();
+        assert_eq!((second.page, second.total_hits), (2, 5));
+        assert_eq!(second.hits[0].start_line, 3);
+        req.cursor = second.next_cursor.clone();
+        let third = engine.search(req.clone()).await.unwrap();
+        assert_eq!(third.hits.len(), 1);
+        assert!(third.next_cursor.is_none());
+
+        req.cursor = Some("missing:0".to_string());
+        assert!(engine.search(req).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn exclude_session_files_skips_already_searched_files() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.log"), "error a\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let engine = create_test_engine(32 * 1024);
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.session_id = Some("s1".to_string());
+        assert_eq!(engine.search(req.clone()).await.unwrap().total_hits, 1);
+
+        std::fs::write(dir.path().join("b.log"), "error b\n").unwrap();
+        req.exclude_session_files = Some("s1".to_string());
+        let resp = engine.search(req.clone()).await.unwrap();
+        assert_eq!((resp.total_hits, resp.files_scanned), (1, 1));
+        assert!(resp.hits[0].file_path.ends_with("b.log"));
+
+        // 第二次搜索也记入了 s1，此时已没有新文件
+        let resp = engine.search(req.clone()).await.unwrap();
+        assert_eq!((resp.total_hits, resp.files_scanned), (0, 0));
+
+        req.exclude_session_files = Some("other".to_string());
+        assert_eq!(engine.search(req.clone()).await.unwrap().total_hits, 2);
+
+        // 未给出 session_id 的搜索不记录到 default 会话
+        req.session_id = None;
+        req.exclude_session_files = None;
+        engine.search(req.clone()).await.unwrap();
+        req.exclude_session_files = Some(DEFAULT_SESSION.to_string());
+        assert_eq!(engine.search(req).await.unwrap().total_hits, 2);
+    }
+
+    #[tokio::test]
+    async fn max_hits_per_file_balances_noisy_files() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("noisy.log"), "error 1\nerror 2\nerror 3\nerror 4\n").unwrap();
+        std::fs::write(dir.path().join("quiet.log"), "error q\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let engine = create_test_engine(32 * 1024);
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.max_hits_per_file = Some(2);
+        let resp = engine.search(req).await.unwrap();
+        assert_eq!(resp.total_hits, 3);
+        let noisy = resp.hits.iter().filter(|h| h.file_path.ends_with("noisy.log")).count();
+        assert_eq!(noisy, 2);
+    }
+
+    #[tokio::test]
+    async fn max_hits_per_file_stops_reading_the_file() {
+        let dir = tempdir().unwrap();
+        let content: String = (0..10_000).map(|i| format!("error {i}\n")).collect();
+        std::fs::write(dir.path().join("noisy.log"), &content).unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let engine = create_test_engine(32 * 1024);
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.max_hits_per_file = Some(3);
+        let resp = engine.search(req).await.unwrap();
+        assert_eq!(resp.total_hits, 3);
+        assert!(resp.failed_files.is_empty() && !resp.timed_out);
+        assert_eq!(resp.lines_scanned, 3);
+        assert!(resp.bytes_scanned < content.len() as u64 / 100, "read {} bytes", resp.bytes_scanned);
+    }
+
+    #[tokio::test]
+    async fn profile_reports_timing_breakdown() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "error 1\ninfo 2\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let engine = create_test_engine(32 * 1024);
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        assert!(engine.search(req.clone()).await.unwrap().timing.is_none());
+
+        req.profile = true;
+        let resp = engine.search(req).await.unwrap();
+        let timing = resp.timing.clone().expect("timing");
+        assert!(timing.scan_ms <= resp.execution_time_ms);
+        assert!(resp.to_json(None).get("timing").is_some());
+    }
+
+    #[tokio::test]
+    async fn stats_accumulate_across_searches() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.log"), "error 1\nerror 2\nerror 3\n").unwrap();
+        std::fs::write(dir.path().join("b.log"), "info\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let engine = create_test_engine(32 * 1024);
+        assert_eq!(engine.stats().searches, 0);
+
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.page_size = 2;
+        req.session_id = Some("s1".to_string());
+        let first = engine.search(req.clone()).await.unwrap();
+        req.cursor = first.next_cursor;
+        engine.search(req).await.unwrap();
+
+        let stats = engine.stats();
+        assert_eq!(stats.searches, 2);
+        assert_eq!(stats.files_read, 2);
+        assert_eq!(stats.bytes_read, 29);
+        assert_eq!(stats.hits_returned, 3);
+    }
+
+    #[tokio::test]
+    async fn global_exclude_patterns_act_as_implicit_none() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("access.log"),
+            "GET /health 200\nGET /api/users 500\nGET /healthz 200\n",
+        )
+        .unwrap();
+        let mut cfg = test_config();
+        cfg.search.global_exclude_patterns = vec![r"GET /health\b".to_string()];
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+        let logical = LogicalQuery {
+            must: vec![sq("GET")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        let resp = engine.search(req.clone()).await.unwrap();
+        let lines: Vec<usize> = resp.hits.iter().map(|h| h.start_line).collect();
+        assert_eq!(lines, vec![2, 3]);
+
+        req.ignore_global_excludes = true;
+        assert_eq!(engine.search(req).await.unwrap().total_hits, 3);
+
+        let mut bad = test_config();
+        bad.search.global_exclude_patterns = vec!["(".to_string()];
+        assert!(bad.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn max_total_read_bytes_stops_the_search() {
+        let dir = tempdir().unwrap();
+        for name in ["a.log", "b.log", "c.log"] {
+            let content: String = (0..10).map(|i| format!("ERROR {i}\n")).collect();
+            std::fs::write(dir.path().join(name), content).unwrap();
+        }
+        let logical = LogicalQuery {
+            must: vec![sq("ERROR")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let req = base_request(dir.path().to_path_buf(), logical);
+
+        let mut cfg = test_config();
+        cfg.search.max_concurrent_files = 1;
+        cfg.search.max_total_read_bytes = Some(64);
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+        let resp = engine.search(req.clone()).await.unwrap();
+        assert!(resp.read_budget_exceeded);
+        // 每行 8 字节：读到第 64 字节后停止
+        assert_eq!(resp.total_hits, 8);
+
+        let resp = create_test_engine(32 * 1024).search(req).await.unwrap();
+        assert!(!resp.read_budget_exceeded);
+        assert_eq!(resp.total_hits, 30);
+    }
+
+    #[tokio::test]
+    async fn group_rotations_searches_segments_oldest_first() {
+        let dir = tempdir().unwrap();
+        for (name, body) in [("app.log", "error c\n"), ("app.log.1", "error b\n"), ("app.log.2", "error a\n")] {
+            std::fs::write(dir.path().join(name), body).unwrap();
+        }
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let engine = create_test_engine(32 * 1024);
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.scan_config.include_globs = vec!["**/app.log*".to_string()];
+        req.group_rotations = true;
+        let resp = engine.search(req).await.unwrap();
+        let order: Vec<&str> = resp.hits.iter().map(|h| h.content.trim()).collect();
+        assert_eq!(order, vec!["error a", "error b", "error c"]);
+    }
+
+    #[tokio::test]
+    async fn match_ratio_flags_too_broad_queries() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "error a\nerror b\nerror c\ninfo d\n").unwrap();
+        let engine = create_test_engine(32 * 1024);
+        let logical = |term: &str| LogicalQuery {
+            must: vec![sq(term)],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let resp = engine
+            .search(base_request(dir.path().to_path_buf(), logical("error")))
+            .await
+            .unwrap();
+        assert_eq!((resp.match_ratio, resp.too_broad), (0.75, true));
+        assert_eq!((resp.bytes_scanned, resp.lines_scanned), (31, 4));
+
+        let resp = engine
+            .search(base_request(dir.path().to_path_buf(), logical("info")))
+            .await
+            .unwrap();
+        assert_eq!((resp.match_ratio, resp.too_broad), (0.25, false));
+    }
+
+    #[test]
+    fn default_excludes_apply_only_without_request_excludes() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        for sub in ["node_modules/pkg", ".git"] {
+            std::fs::create_dir_all(root.join(sub)).unwrap();
+        }
+        for file in ["app.log", "node_modules/pkg/install.log", ".git/gc.log"] {
+            std::fs::write(root.join(file), "x\n").unwrap();
+        }
+        let mut scan = FileScanConfig {
+            root_path: root.to_path_buf(),
+            include_globs: Vec::new(),
+            exclude_globs: Vec::new(),
+            filename_query: None,
+            content_type_filter: Default::default(),
+            line_range: None,
+        };
+        let engine = create_test_engine(32 * 1024);
+        assert_eq!(engine.list_files(&scan).unwrap(), vec![root.join("app.log")]);
+
+        // 请求给出排除模式时完全替换默认值
+        scan.exclude_globs = vec!["**/other/**".to_string()];
+        assert_eq!(engine.list_files(&scan).unwrap().len(), 3);
+
+        scan.exclude_globs.clear();
+        let mut cfg = test_config();
+        cfg.log_sources.default_exclude_globs = Some(vec!["**/.git/**".to_string()]);
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+        assert_eq!(engine.list_files(&scan).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn snapshot_sizes_pins_later_pages_to_first_page_sizes() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("app.log");
+        std::fs::write(&log, "error 1\nerror 2\nerror 3\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.session_id = Some("live".into());
+        req.snapshot_sizes = true;
+        req.page_size = 2;
+        let engine = create_test_engine(1024);
+        let page1 = engine.search(req.clone()).await.unwrap();
+        assert_eq!(page1.total_hits, 3);
+
+        // 文件在翻页期间继续增长，新文件也出现
+        std::fs::write(&log, "error 0\nerror 1\nerror 2\nerror 3\nerror 4\n").unwrap();
+        std::fs::write(dir.path().join("new.log"), "error new\n").unwrap();
+        req.page = 2;
+        assert!(engine.search(req.clone()).await.is_err());
+        req.snapshot_id = page1.snapshot_id.clone();
+        let page2 = engine.search(req.clone()).await.unwrap();
+        assert_eq!(page2.total_hits, 3);
+        assert_eq!(page2.hits.len(), 1);
+        assert_eq!(page2.snapshot_id, page1.snapshot_id);
+
+        req.snapshot_id = Some("unknown".into());
+        assert!(engine.search(req.clone()).await.is_err());
+
+        req.snapshot_sizes = false;
+        assert_eq!(engine.search(req).await.unwrap().total_hits, 6);
+    }
+
+    #[tokio::test]
+    async fn snapshot_sizes_are_kept_per_search_in_one_session() {
+        let first = tempdir().unwrap();
+        let second = tempdir().unwrap();
+        std::fs::write(first.path().join("a.log"), "error a1\nerror a2\nerror a3\n").unwrap();
+        std::fs::write(second.path().join("b.log"), "error b1\nerror b2\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let request = |root: &std::path::Path| {
+            let mut req = base_request(root.to_path_buf(), logical.clone());
+            req.snapshot_sizes = true;
+            req.page_size = 1;
+            req
+        };
+        let engine = create_test_engine(1024);
+        let mut a = request(first.path());
+        let mut b = request(second.path());
+        let a1 = engine.search(a.clone()).await.unwrap();
+        let b1 = engine.search(b.clone()).await.unwrap();
+        assert_ne!(a1.snapshot_id, b1.snapshot_id);
+
+        // 两个搜索在同一（默认）会话中交替翻页，各自沿用自己的快照
+        a.page = 2;
+        a.snapshot_id = a1.snapshot_id;
+        b.page = 2;
+        b.snapshot_id = b1.snapshot_id;
+        let a2 = engine.search(a).await.unwrap();
+        let b2 = engine.search(b).await.unwrap();
+        assert_eq!((a2.total_hits, a2.hits[0].content.trim()), (3, "error a2"));
+        assert_eq!((b2.total_hits, b2.hits[0].content.trim()), (2, "error b2"));
+    }
+
+    #[tokio::test]
+    async fn prepared_search_pages_from_cached_results() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("app.log");
+        std::fs::write(&log, "error 1\nerror 2\nerror 3\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.page_size = 2;
+        let engine = create_test_engine(1024);
+        let info = engine.prepare_search(req).unwrap();
+        assert_eq!(info.files, 1);
+
+        // 文件列表在 prepare 时固定，之后新建的文件不被搜索
+        std::fs::write(dir.path().join("new.log"), "error new\n").unwrap();
+        let first = engine.fetch_page(None, &info.handle, 1, None).await.unwrap();
+        assert_eq!((first.total_hits, first.total_pages, first.hits.len()), (3, 2, 2));
+
+        std::fs::write(&log, "").unwrap();
+        let second = engine.fetch_page(None, &info.handle, 2, None).await.unwrap();
+        assert_eq!(second.page, 2);
+        assert_eq!(second.hits.len(), 1);
+        assert!(second.hits[0].content.contains("error 3"));
+
+        assert!(engine.fetch_page(Some("other"), &info.handle, 1, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn page_beyond_max_page_is_rejected() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "error\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        let mut cfg = test_config();
+        cfg.search.max_page = Some(5);
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+
+        req.page = 5;
+        assert!(engine.search(req.clone()).await.unwrap().hits.is_empty());
+        req.page = 6;
+        let err = engine.search(req).await.unwrap_err().to_string();
+        assert!(err.contains("search.max_page (5)"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn relative_to_strips_base_from_hit_paths() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("svc")).unwrap();
+        std::fs::write(dir.path().join("svc/app.log"), "error\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.relative_to = Some(dir.path().to_path_buf());
+        let engine = create_test_engine(32 * 1024);
+        let resp = engine.search(req.clone()).await.unwrap();
+        assert_eq!(resp.hits[0].file_path, PathBuf::from("svc/app.log"));
 
-        if let Some(limit) = request.max_hits {
-            if hits.len() >= limit {
-                break;
-            }
-        }
+        // 不在基准目录下的文件保留绝对路径
+        req.relative_to = Some(PathBuf::from("/nonexistent/base"));
+        let resp = engine.search(req).await.unwrap();
+        assert!(resp.hits[0].file_path.is_absolute());
     }
-    Ok(hits)
-}
 
-fn collect_positions_static(
-    query: &QueryProcessor,
-    text: &str,
-    logical: &crate::model::LogicalQuery,
-) -> Vec<MatchPosition> {
-    let mut positions = Vec::new();
-    for q in logical
-        .must
-        .iter()
-        .chain(logical.any.iter())
-        .chain(logical.none.iter())
-    {
-        positions.extend(query.find_positions(text, q));
+    #[tokio::test]
+    async fn time_windows_keep_entries_in_any_window() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("a.log"),
+            "2024-01-01 09:30:00 error d1-early
+\
+             2024-01-01 10:15:00 error d1
+\
+             2024-01-02 10:45:00 error d2
+\
+             2024-01-02 12:00:00 error d2-late
+\
+             2024-01-03 10:05:00 error d3
+",
+        )
+        .unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.time_windows = vec![
+            ("2024-01-01 10:00:00".into(), "2024-01-01 11:00:00".into()),
+            ("2024-01-02T10:00:00Z".into(), "2024-01-02T11:00:00Z".into()),
+        ];
+        let engine = create_test_engine(32 * 1024);
+        let resp = engine.search(req.clone()).await.unwrap();
+        let tags: Vec<&str> = resp.hits.iter().map(|h| h.content.trim().rsplit(' ').next().unwrap()).collect();
+        assert_eq!(tags, vec!["d1", "d2"]);
+
+        req.time_windows.push(("2024-01-03 11:00:00".into(), "2024-01-03 10:00:00".into()));
+        assert!(engine.search(req).await.is_err());
     }
-    positions
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::model::{FileScanConfig, LogicalQuery, SearchQuery};
-    use crate::config::{Config, LogParserConfig, LogSourceConfig, SearchConfig, ServerConfig, ServerMode};
-    use tempfile::tempdir;
+    #[tokio::test]
+    async fn relevance_sort_puts_most_matching_entries_first() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.log"), "timeout\ndb timeout timeout\n").unwrap();
+        std::fs::write(dir.path().join("b.log"), "db timeout\ndb\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![],
+            any: vec![sq("db"), sq("timeout")],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.sort_by = SortBy::Relevance;
+        let engine = create_test_engine(1024);
+        let res = engine.search(req).await.unwrap();
+        let order: Vec<(String, usize)> = res
+            .hits
+            .iter()
+            .map(|h| (h.content.trim_end().to_string(), h.score))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("db timeout timeout".to_string(), 23),
+                ("db timeout".to_string(), 22),
+                // 同分时按文件路径：a.log 在前
+                ("timeout".to_string(), 11),
+                ("db".to_string(), 11),
+            ]
+        );
+    }
 
-    fn create_test_engine(buffer_size: usize) -> SearchEngine {
-         let mut cfg = Config {
-              server: ServerConfig { mode: ServerMode::Stdio, http_addr: None, http_port: None },
-              log_parser: LogParserConfig { default_log_start_pattern: None, default_timestamp_regex: None },
-              search: SearchConfig::default(),
-              log_sources: LogSourceConfig::default(),
-         };
-         cfg.search.buffer_size = buffer_size;
-         SearchEngine::new(Arc::new(RwLock::new(cfg)))
+    #[tokio::test]
+    async fn timestamp_asc_merges_files_in_time_order() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("a.log"),
+            "2024-01-01 10:00:00 error a1\n2024-01-01 10:00:03 error a2\n  trace line error\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("b.log"),
+            "2024-01-01 10:00:01 error b1\n2024-01-01 10:00:04 error b2\n",
+        )
+        .unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.sort_by = SortBy::TimestampAsc;
+        req.page_size = 3;
+        let engine = create_test_engine(32 * 1024);
+        assert!(engine.search(req.clone()).await.is_err());
+
+        req.time_filter = Some(TimeFilter {
+            time_start: None,
+            time_end: None,
+            timestamp_regex: Some(r"\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}".into()),
+        });
+        let first = engine.search(req.clone()).await.unwrap();
+        req.page = 2;
+        let second = engine.search(req.clone()).await.unwrap();
+
+        let order: Vec<String> = first
+            .hits
+            .iter()
+            .chain(second.hits.iter())
+            .map(|h| h.content.trim().rsplit(' ').next().unwrap().to_string())
+            .collect();
+        assert_eq!(first.total_hits, 5);
+        assert_eq!(order, vec!["a1", "b1", "a2", "error", "b2"]);
+        // 续行沿用同文件上一条目的时间
+        assert_eq!(first.hits[2].timestamp.as_deref(), Some("2024-01-01T10:00:03.000Z"));
+        assert_eq!(second.hits[0].timestamp.as_deref(), Some("2024-01-01T10:00:03.000Z"));
+
+        // display_tz 只改变本次请求的时间戳输出
+        req.display_tz = Some("Asia/Shanghai".into());
+        let shifted = engine.search(req.clone()).await.unwrap();
+        assert_eq!(shifted.hits[0].timestamp.as_deref(), Some("2024-01-01T18:00:03.000+08:00"));
+        req.display_tz = Some("Mars/Base".into());
+        assert!(engine.search(req).await.is_err());
     }
 
-    fn sq(text: &str) -> SearchQuery {
-        SearchQuery {
-            query: Some(text.to_string()),
-            regex: false,
-            case_sensitive: false,
-            whole_word: false,
-        }
+    #[tokio::test]
+    async fn timestamp_presets_resolve_by_name() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("app.log"),
+            "[2024-01-01 09:00:00] error early\n[2024-01-01 11:00:00] error late\n",
+        )
+        .unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.time_filter = Some(TimeFilter {
+            time_start: Some("2024-01-01 10:00:00".into()),
+            time_end: None,
+            timestamp_regex: Some("@bracketed".into()),
+        });
+        let mut cfg = test_config();
+        cfg.log_parser
+            .timestamp_presets
+            .insert("bracketed".into(), r"\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}".into());
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+
+        let resp = engine.search(req.clone()).await.unwrap();
+        assert_eq!(resp.total_hits, 1);
+        assert!(resp.hits[0].content.contains("late"));
+
+        req.time_filter.as_mut().unwrap().timestamp_regex = Some("@missing".into());
+        let err = engine.search(req).await.unwrap_err();
+        assert!(err.to_string().contains("@bracketed"), "{err}");
     }
 
-    fn base_request(root: PathBuf, logical_query: LogicalQuery) -> SearchRequest {
-        SearchRequest {
-            scan_config: FileScanConfig {
-                root_path: root,
-                include_globs: vec!["**/*.log".to_string()],
-                exclude_globs: vec![],
-            },
-            logical_query,
-            time_filter: None,
-            log_start_pattern: None,
-            page_size: 10,
-            page: 1,
-            max_hits: None,
-            hard_timeout_ms: None,
-            include_content: true,
-        }
+    #[tokio::test]
+    async fn incremental_search_resumes_from_session_positions() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "old error\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.session_id = Some("tail".into());
+        let engine = create_test_engine(32 * 1024);
+
+        let baseline = engine.search_incremental(&req, None).await.unwrap();
+        assert!(baseline.hits.is_empty());
+
+        let mut f = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut f, b"new error\n").unwrap();
+        let next = engine.search_incremental(&req, None).await.unwrap();
+        assert_eq!(next.hits.len(), 1);
+        assert_eq!(next.hits[0].start_line, 2);
+
+        let again = engine.search_incremental(&req, None).await.unwrap();
+        assert!(again.hits.is_empty());
+        // 其他会话互不影响
+        req.session_id = Some("other".into());
+        assert!(engine.search_incremental(&req, None).await.unwrap().hits.is_empty());
     }
 
     #[tokio::test]
-    async fn single_line_search_respects_must_and_none() {
+    async fn group_by_file_partitions_page_hits() {
         let dir = tempdir().unwrap();
-        let path = dir.path().join("demo.log");
         std::fs::write(
-            &path,
-            "ok line\nerror traffic\nfatal error\n",
+            dir.path().join("a.log"),
+            "2024-01-01 10:00:00 error a1\n2024-01-01 10:00:02 error a2\n",
         )
         .unwrap();
-
+        std::fs::write(dir.path().join("b.log"), "2024-01-01 10:00:01 error b1\n").unwrap();
         let logical = LogicalQuery {
             must: vec![sq("error")],
             any: vec![],
-            none: vec![sq("fatal")],
+            none: vec![],
+            any_min_matches: 1,
         };
-        let req = base_request(dir.path().to_path_buf(), logical);
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.group_by_file = true;
+        req.sort_by = SortBy::TimestampAsc;
+        req.time_filter = Some(TimeFilter {
+            time_start: None,
+            time_end: None,
+            timestamp_regex: Some(r"\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}".into()),
+        });
         let engine = create_test_engine(32 * 1024);
+        let resp = engine.search(req).await.unwrap();
 
-        let hits = engine.search_file(path, &req).await.unwrap();
-        assert_eq!(hits.len(), 1);
-        assert!(hits[0].content.contains("error traffic"));
+        assert!(resp.hits.is_empty());
+        let groups = resp.grouped_hits.unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].file_path, dir.path().join("a.log"));
+        assert_eq!(groups[0].hits.len(), 2);
+        assert_eq!(groups[1].hits.len(), 1);
     }
 
     #[tokio::test]
-    async fn multiline_search_aggregates_entries() {
+    async fn min_level_drops_lower_levels() {
         let dir = tempdir().unwrap();
-        let path = dir.path().join("multi.log");
         std::fs::write(
-            &path,
-            "2025-01-01 INFO start\nline-a\n2025-01-01 ERROR boom\nline-b\n",
+            dir.path().join("app.log"),
+            "INFO db ok\nWARN db slow\nERROR db down\nDEBUG db trace\n",
         )
         .unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("db")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.min_level = Some("warn".into());
+        let engine = create_test_engine(32 * 1024);
+        let mut resp = engine.search(req.clone()).await.unwrap();
+        resp.hits.sort_by_key(|h| h.start_line);
+        let lines: Vec<usize> = resp.hits.iter().map(|h| h.start_line).collect();
+        assert_eq!(lines, vec![2, 3]);
+
+        req.min_level = Some("verbose".into());
+        assert!(engine.search(req).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn max_open_files_bounds_files_read_at_once() {
+        let dir = tempdir().unwrap();
+        for i in 0..4 {
+            std::fs::write(dir.path().join(format!("{i}.log")), "error here\n").unwrap();
+        }
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut cfg = test_config();
+        cfg.search.max_concurrent_files = 4;
+        cfg.search.max_open_files = Some(1);
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+        let resp = engine.search(base_request(dir.path().to_path_buf(), logical)).await.unwrap();
+        assert_eq!(resp.total_hits, 4);
+        assert_eq!(engine.open_files.available_permits(), 1);
+
+        #[cfg(unix)]
+        {
+            let emfile = LogSearchError::Io(std::io::Error::from_raw_os_error(24));
+            assert!(describe_file_error(&emfile).contains("search.max_open_files"));
+        }
+    }
 
+    #[tokio::test]
+    async fn template_groups_all_hits_before_paging() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("app.log"),
+            "ERROR disk full on /dev/sda1\nERROR timeout after 5123ms\nERROR timeout after 4999ms\n",
+        )
+        .unwrap();
         let logical = LogicalQuery {
             must: vec![sq("ERROR")],
             any: vec![],
             none: vec![],
+            any_min_matches: 1,
         };
         let mut req = base_request(dir.path().to_path_buf(), logical);
-        req.log_start_pattern = Some(r"^\d{4}-\d{2}-\d{2}".to_string());
+        req.page_size = 1;
+        let engine = create_test_engine(32 * 1024);
+        assert!(engine.search(req.clone()).await.unwrap().templates.is_none());
 
+        req.template = true;
+        let resp = engine.search(req).await.unwrap();
+        assert_eq!(resp.hits.len(), 1);
+        let templates = resp.templates.unwrap();
+        let groups: Vec<(&str, usize)> = templates.iter().map(|g| (g.normalized.as_str(), g.count)).collect();
+        assert_eq!(groups, vec![("ERROR timeout after #ms", 2), ("ERROR disk full on /dev/sda#", 1)]);
+        assert_eq!(templates[0].example.content.trim_end(), "ERROR timeout after 5123ms");
+    }
+
+    #[tokio::test]
+    async fn error_summary_counts_groups_and_buckets_by_hour() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("app.log"),
+            "2024-01-01 09:59:00 ERROR timeout after 30s\n\
+             2024-01-01 10:05:00 ERROR timeout after 31s\n\
+             2024-01-01 10:20:00 INFO error budget ok\n\
+             2024-01-01 10:40:00 ERROR timeout after 40s\n\
+             2024-01-01 11:15:00 FATAL disk full\n\
+             2024-01-01 12:30:00 ERROR timeout after 50s\n",
+        )
+        .unwrap();
+        let scan_config = FileScanConfig {
+            root_path: dir.path().to_path_buf(),
+            include_globs: Vec::new(),
+            exclude_globs: Vec::new(),
+            filename_query: None,
+            content_type_filter: Default::default(),
+            line_range: None,
+        };
+        let window = TimeFilter {
+            time_start: Some("2024-01-01 10:00:00".into()),
+            time_end: Some("2024-01-01 12:00:00".into()),
+            timestamp_regex: Some(r"\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}".into()),
+        };
         let engine = create_test_engine(32 * 1024);
-        let hits = engine.search_file(path, &req).await.unwrap();
+        let summary = engine.error_summary(&scan_config, Some(&window), None, 20).await.unwrap();
+        assert_eq!(summary.total_errors, 3);
+        assert_eq!(summary.distinct_messages, 2);
+        assert_eq!(summary.top_messages[0].count, 2);
+        assert!(summary.top_messages[0].normalized.contains("timeout after #s"));
+        assert_eq!(summary.first_seen.as_deref(), Some("2024-01-01T10:05:00.000Z"));
+        assert_eq!(summary.last_seen.as_deref(), Some("2024-01-01T11:15:00.000Z"));
+        let hourly: Vec<(&str, usize)> = summary.hourly.iter().map(|h| (h.hour.as_str(), h.count)).collect();
+        assert_eq!(hourly, vec![("2024-01-01T10:00:00Z", 2), ("2024-01-01T11:00:00Z", 1)]);
+        assert_eq!(summary.without_timestamp, 0);
 
-        assert_eq!(hits.len(), 1);
-        assert!(hits[0].content.starts_with("2025-01-01 ERROR"));
-        assert!(hits[0].content.contains("line-b"));
-        assert!(hits[0].start_line <= hits[0].end_line);
+        let summary = engine.error_summary(&scan_config, None, Some("FATAL"), 1).await.unwrap();
+        assert_eq!(summary.total_errors, 1);
+        assert!(summary.first_seen.is_none());
+        assert_eq!(summary.without_timestamp, 1);
     }
 
     #[tokio::test]
-    async fn search_invalid_root_returns_error() {
-        let root = std::path::PathBuf::from("D:/path/does/not/exist");
+    async fn estimate_matches_extrapolates_from_sampled_files() {
+        let dir = tempdir().unwrap();
+        for i in 0..10 {
+            std::fs::write(dir.path().join(format!("app{i}.log")), "ERROR x\nINFO y\n".repeat(4)).unwrap();
+        }
+        let scan_config = FileScanConfig {
+            root_path: dir.path().to_path_buf(),
+            include_globs: Vec::new(),
+            exclude_globs: Vec::new(),
+            filename_query: None,
+            content_type_filter: Default::default(),
+            line_range: None,
+        };
         let logical = LogicalQuery {
-            must: vec![sq("anything")],
+            must: vec![sq("ERROR")],
             any: vec![],
             none: vec![],
+            any_min_matches: 1,
         };
-        let req = SearchRequest {
-            scan_config: FileScanConfig {
-                root_path: root,
-                include_globs: vec!["**/*.log".to_string()],
-                exclude_globs: vec![],
-            },
-            logical_query: logical,
-            time_filter: None,
-            log_start_pattern: None,
-            page_size: 10,
-            page: 1,
-            max_hits: None,
-            hard_timeout_ms: None,
-            include_content: true,
+        let engine = create_test_engine(32 * 1024);
+
+        let estimate = engine.estimate_matches(&scan_config, &logical, 5, None, Some(7)).await.unwrap();
+        assert_eq!(estimate.files_total, 10);
+        assert_eq!(estimate.files_sampled, 5);
+        assert_eq!(estimate.sample_seed, 7);
+        assert_eq!(estimate.sampled_hits, 20);
+        assert_eq!((estimate.estimated_total, estimate.low, estimate.high), (40, 40, 40));
+        assert!(!estimate.exact);
+
+        // 每个文件只读前一半，按文件大小放大
+        let estimate = engine.estimate_matches(&scan_config, &logical, 10, Some(30), None).await.unwrap();
+        assert_eq!(estimate.sampled_hits, 20);
+        assert_eq!(estimate.estimated_total, 40);
+        assert!(!estimate.exact);
+
+        let estimate = engine.estimate_matches(&scan_config, &logical, 20, None, None).await.unwrap();
+        assert!(estimate.exact);
+        assert_eq!(estimate.estimated_total, 40);
+
+        assert!(engine.estimate_matches(&scan_config, &logical, 0, None, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn context_entries_surround_multiline_hits() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("app.log"),
+            "2024 INFO request /a\n  header x\n2024 ERROR failed\n  at frame\n2024 INFO retry\n2024 INFO done\n",
+        )
+        .unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("failed")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
         };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.log_start_pattern = Some("^2024".into());
+        req.context_entries_before = 2;
+        req.context_entries_after = 1;
         let engine = create_test_engine(32 * 1024);
-        let err = engine.search(req).await.unwrap_err().to_string();
-        assert!(err.contains("文件访问错误") || err.contains("not a directory"));
+        let resp = engine.search(req.clone()).await.unwrap();
+
+        assert_eq!(resp.total_hits, 1);
+        let hit = &resp.hits[0];
+        assert_eq!(hit.context_before.len(), 1);
+        assert_eq!(hit.context_before[0].start_line, 1);
+        assert_eq!(hit.context_before[0].end_line, 2);
+        assert_eq!(hit.context_after.len(), 1);
+        assert_eq!(hit.context_after[0].content, "2024 INFO retry\n");
+
+        req.merge_context = true;
+        let resp = engine.search(req).await.unwrap();
+        let hit = &resp.hits[0];
+        assert!(hit.context_before.is_empty() && hit.context_after.is_empty());
+        assert_eq!(hit.content, "2024 INFO request /a\n  header x\n2024 ERROR failed\n  at frame\n2024 INFO retry\n");
+        assert_eq!((hit.content_start_line, hit.start_line), (Some(1), 3));
+        let p = &hit.match_positions[0];
+        assert_eq!(&hit.content[p.offset..p.offset + p.length], "failed");
+    }
+
+    #[tokio::test]
+    async fn line_range_limits_matching_entries() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "error 1\nerror 2\nerror 3\nerror 4\nerror 5\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let mut req = base_request(dir.path().to_path_buf(), logical);
+        req.scan_config.line_range = Some((2, 3));
+        req.context_entries_before = 1;
+        req.context_entries_after = 1;
+        let engine = create_test_engine(1024);
+        let resp = engine.search(req.clone()).await.unwrap();
+        let lines: Vec<usize> = resp.hits.iter().map(|h| h.start_line).collect();
+        assert_eq!(lines, vec![2, 3]);
+        // 区间外的条目仍可作为上下文
+        assert_eq!(resp.hits[0].context_before[0].start_line, 1);
+        assert_eq!(resp.hits[1].context_after[0].start_line, 4);
+
+        req.scan_config.line_range = Some((0, 3));
+        assert!(engine.search(req.clone()).await.is_err());
+        req.scan_config.line_range = Some((4, 3));
+        assert!(engine.search(req).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn overlong_regex_is_rejected_before_searching() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "error\n").unwrap();
+        let mut config = test_config();
+        config.search.max_regex_len = Some(16);
+        let engine = SearchEngine::new(Arc::new(RwLock::new(config)));
+        let logical = LogicalQuery {
+            must: vec![SearchQuery {
+                query: Some(format!("error|{}", "x".repeat(32))),
+                regex: true,
+                case_sensitive: false,
+                whole_word: false,
+                whole_line: false,
+                per_physical_line: false,
+            }],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let err = engine
+            .search(base_request(dir.path().to_path_buf(), logical))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LogSearchError::RegexError { .. }));
+    }
+
+    #[tokio::test]
+    async fn head_file_returns_first_lines() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "first\r\nsecond\nthird\n").unwrap();
+        let secret = dir.path().join("id_rsa");
+        std::fs::write(&secret, "key\n").unwrap();
+        let mut cfg = test_config();
+        cfg.log_sources.default_root = Some(dir.path().to_path_buf());
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+
+        assert_eq!(engine.head_file(&path, 2).await.unwrap(), vec!["first", "second"]);
+        assert_eq!(engine.head_file(&path, 10).await.unwrap().len(), 3);
+        assert_eq!(engine.tail_file(&path, 1).await.unwrap(), vec!["third"]);
+        assert!(engine.head_file(&dir.path().join("missing.log"), 1).await.is_err());
+
+        // 不在允许范围内的文件（不匹配包含规则、跳出根目录或未配置根目录）拒绝读取
+        assert!(engine.head_file(&secret, 1).await.is_err());
+        assert!(engine.tail_file(&secret, 1).await.is_err());
+        let outside = tempdir().unwrap();
+        let other = outside.path().join("other.log");
+        std::fs::write(&other, "x\n").unwrap();
+        let traversal = dir.path().join("..").join(outside.path().file_name().unwrap()).join("other.log");
+        assert!(engine.head_file(&traversal, 1).await.is_err());
+        assert!(create_test_engine(32 * 1024).head_file(&path, 1).await.is_err());
+
+        // log_file_paths 中的文件不受根目录限制
+        let mut cfg = test_config();
+        cfg.log_sources.log_file_paths = Some(vec![secret.to_string_lossy().into_owned()]);
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+        assert_eq!(engine.head_file(&secret, 1).await.unwrap(), vec!["key"]);
+    }
+
+    #[tokio::test]
+    async fn resources_are_configured_files_read_by_line_range() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "l1\nl2\nl3\nl4\n").unwrap();
+        let mut cfg = test_config();
+        cfg.log_sources.log_file_paths = Some(vec![
+            path.to_string_lossy().to_string(),
+            dir.path().join("missing.log").to_string_lossy().to_string(),
+        ]);
+        let engine = SearchEngine::new(Arc::new(RwLock::new(cfg)));
+
+        assert_eq!(engine.resource_files(), vec![path.clone()]);
+        assert_eq!(engine.read_line_range(&path, 0, 2, 2).await.unwrap(), vec!["l2", "l3"]);
+        assert_eq!(engine.read_line_range(&path, 4, 1, 1).await.unwrap(), vec!["l3"]);
+        assert!(engine.read_line_range(&path, 0, 10, 2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn incremental_search_reads_only_appended_records() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("live.log");
+        std::fs::write(&path, "error old\ninfo old\n").unwrap();
+        let logical = LogicalQuery {
+            must: vec![sq("error")],
+            any: vec![],
+            none: vec![],
+            any_min_matches: 1,
+        };
+        let req = base_request(dir.path().to_path_buf(), logical);
+        let engine = create_test_engine(32 * 1024);
+
+        let baseline = engine.search_incremental(&req, None).await.unwrap();
+        assert!(baseline.hits.is_empty());
+        assert_eq!(baseline.positions[&path], TailPosition { offset: 19, line: 2 });
+
+        {
+            use std::io::Write;
+            let mut f = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+            write!(f, "error new\ninfo new\nerror partial").unwrap();
+        }
+        let next = engine
+            .search_incremental(&req, Some(baseline.positions))
+            .await
+            .unwrap();
+        assert_eq!(next.hits.len(), 1);
+        assert_eq!(next.hits[0].content, "error new\n");
+        assert_eq!(next.hits[0].start_line, 3);
+        assert_eq!(next.positions[&path].line, 4);
+
+        {
+            use std::io::Write;
+            let mut f = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+            writeln!(f).unwrap();
+        }
+        let last = engine
+            .search_incremental(&req, Some(next.positions))
+            .await
+            .unwrap();
+        assert_eq!(last.hits.len(), 1);
+        assert_eq!(last.hits[0].content, "error partial\n");
+        assert_eq!(last.hits[0].start_line, 5);
     }
 }
@@ -0,0 +1,291 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::{HitResult, SearchRequest, SearchResponse, TailPosition};
+
+/// 未指定 session_id 时使用的会话。
+pub const DEFAULT_SESSION: &str = "default";
+
+/// 同时保留的会话数，超出时丢弃最久未使用的会话及其全部状态。
+const MAX_SESSIONS: usize = 256;
+
+/// 每个会话保留的命名结果集数，超出时丢弃最早保存的。
+const MAX_RESULT_SETS_PER_SESSION: usize = 16;
+
+/// 每个会话记录的已搜索文件数上限，达到后不再记录新文件（之后的 `exclude_session_files` 会重新搜索它们）。
+const MAX_SEARCHED_FILES_PER_SESSION: usize = 100_000;
+
+/// 每个会话保留的游标数，超出时丢弃最早创建的。
+const MAX_CURSORS_PER_SESSION: usize = 16;
+
+/// 每个会话保留的预备搜索数，超出时丢弃最早创建的。
+const MAX_PREPARED_PER_SESSION: usize = 16;
+
+/// 每个会话保留的文件大小快照数，超出时丢弃最早创建的。
+const MAX_SNAPSHOTS_PER_SESSION: usize = 16;
+
+/// 命中条目的位置引用：只保存文件与行号，内容在需要时重新读取。
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct HitRef {
+    pub file_path: PathBuf,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl From<&HitResult> for HitRef {
+    fn from(hit: &HitResult) -> Self {
+        Self {
+            file_path: hit.file_path.clone(),
+            start_line: hit.start_line,
+            end_line: hit.end_line,
+        }
+    }
+}
+
+/// 命名结果集，用于在后续搜索中做交集/差集。
+#[derive(Debug, Clone, Default)]
+pub struct ResultSet {
+    refs: Vec<HitRef>,
+    keys: HashSet<(PathBuf, usize)>,
+}
+
+impl ResultSet {
+    pub fn new(refs: Vec<HitRef>) -> Self {
+        let keys = refs
+            .iter()
+            .map(|r| (r.file_path.clone(), r.start_line))
+            .collect();
+        Self { refs, keys }
+    }
+
+    pub fn refs(&self) -> &[HitRef] {
+        &self.refs
+    }
+
+    /// 以 (文件, 起始行) 判断条目是否在结果集中。
+    pub fn contains(&self, file_path: &Path, start_line: usize) -> bool {
+        self.keys.contains(&(file_path.to_path_buf(), start_line))
+    }
+}
+
+/// 游标分页的保存状态：首次搜索当前页之后的全部有序命中。
+#[derive(Debug, Clone)]
+pub struct CursorState {
+    pub hits: Arc<Vec<HitResult>>,
+    /// `hits[0]` 在整个结果中的序号（从 0 起）。
+    pub base: usize,
+    /// 首次搜索响应中与分页无关的字段（`hits` 为空）。
+    pub summary: SearchResponse,
+}
+
+/// `prepare_search` 保存的预备搜索：请求及扫描得到的文件列表；首次取页后缓存全部有序命中。
+#[derive(Debug, Clone)]
+pub struct PreparedSearch {
+    pub request: SearchRequest,
+    pub files: Arc<Vec<PathBuf>>,
+    pub scan_errors: Vec<(PathBuf, String)>,
+    pub refine_set: Option<ResultSet>,
+    pub sample_seed: Option<u64>,
+    /// 首次取页时计算的全部命中（`base` 为 0）。
+    pub results: Option<CursorState>,
+}
+
+#[derive(Debug, Default)]
+struct SessionState {
+    /// 最近一次访问时的 [`SessionManager`] 计数，用于淘汰最久未使用的会话。
+    last_used: AtomicU64,
+    /// 按保存顺序排列的命名结果集，同名保存时替换并移到末尾。
+    result_sets: VecDeque<(String, ResultSet)>,
+    /// 增量搜索在各文件上已读到的位置。
+    tail_positions: Option<HashMap<PathBuf, TailPosition>>,
+    /// 按创建顺序排列的游标。
+    cursors: VecDeque<(String, CursorState)>,
+    /// 本会话中已完整搜索过（未失败、未超时）的文件。
+    searched_files: HashSet<PathBuf>,
+    /// `snapshot_sizes` 分页时第 1 页记录的各文件大小，按创建顺序排列，以返回的 `snapshot_id` 区分。
+    size_snapshots: VecDeque<(String, Arc<HashMap<PathBuf, u64>>)>,
+    /// 按创建顺序排列的预备搜索。
+    prepared: VecDeque<(String, PreparedSearch)>,
+}
+
+/// 会话管理器：进程内保存各会话的中间状态。会话数超过 [`MAX_SESSIONS`] 时淘汰最久未使用的会话。
+#[derive(Default)]
+pub struct SessionManager {
+    sessions: RwLock<HashMap<String, SessionState>>,
+    /// 单调递增的访问计数。
+    clock: AtomicU64,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn touch(&self, state: &SessionState) {
+        state
+            .last_used
+            .store(self.clock.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    /// 读取已有会话并记录访问。
+    fn with_session<T>(&self, session_id: &str, f: impl FnOnce(&SessionState) -> Option<T>) -> Option<T> {
+        let sessions = self.sessions.read().unwrap();
+        let state = sessions.get(session_id)?;
+        self.touch(state);
+        f(state)
+    }
+
+    /// 取得（必要时创建）会话的可变状态；新建会话使总数超过上限时先淘汰最久未使用的会话。
+    fn update_session<T>(&self, session_id: &str, f: impl FnOnce(&mut SessionState) -> T) -> T {
+        let mut sessions = self.sessions.write().unwrap();
+        if !sessions.contains_key(session_id) && sessions.len() >= MAX_SESSIONS {
+            let oldest = sessions
+                .iter()
+                .min_by_key(|(_, state)| state.last_used.load(Ordering::Relaxed))
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+        let state = sessions.entry(session_id.to_string()).or_default();
+        self.touch(state);
+        f(state)
+    }
+
+    pub fn save_result_set(&self, session_id: &str, name: &str, refs: Vec<HitRef>) {
+        self.update_session(session_id, |s| {
+            s.result_sets.retain(|(n, _)| n != name);
+            push_capped(&mut s.result_sets, MAX_RESULT_SETS_PER_SESSION, name, ResultSet::new(refs));
+        });
+    }
+
+    pub fn result_set(&self, session_id: &str, name: &str) -> Option<ResultSet> {
+        self.with_session(session_id, |s| find(&s.result_sets, name))
+    }
+
+    pub fn save_tail_positions(&self, session_id: &str, positions: HashMap<PathBuf, TailPosition>) {
+        self.update_session(session_id, |s| s.tail_positions = Some(positions));
+    }
+
+    pub fn tail_positions(&self, session_id: &str) -> Option<HashMap<PathBuf, TailPosition>> {
+        self.with_session(session_id, |s| s.tail_positions.clone())
+    }
+
+    /// 记录已完整搜索过的文件，每个会话最多 [`MAX_SEARCHED_FILES_PER_SESSION`] 个。
+    pub fn record_searched_files(&self, session_id: &str, files: Vec<PathBuf>) {
+        self.update_session(session_id, |s| {
+            for file in files {
+                if s.searched_files.len() >= MAX_SEARCHED_FILES_PER_SESSION {
+                    break;
+                }
+                s.searched_files.insert(file);
+            }
+        });
+    }
+
+    /// 从 `files` 中去掉会话里已搜索过的文件。
+    pub fn retain_unsearched(&self, session_id: &str, files: &mut Vec<PathBuf>) {
+        self.with_session(session_id, |s| {
+            files.retain(|f| !s.searched_files.contains(f));
+            Some(())
+        });
+    }
+
+    pub fn save_size_snapshot(&self, session_id: &str, snapshot_id: &str, sizes: Arc<HashMap<PathBuf, u64>>) {
+        self.update_session(session_id, |s| {
+            push_capped(&mut s.size_snapshots, MAX_SNAPSHOTS_PER_SESSION, snapshot_id, sizes)
+        });
+    }
+
+    pub fn size_snapshot(&self, session_id: &str, snapshot_id: &str) -> Option<Arc<HashMap<PathBuf, u64>>> {
+        self.with_session(session_id, |s| find(&s.size_snapshots, snapshot_id))
+    }
+
+    pub fn save_cursor(&self, session_id: &str, cursor_id: &str, state: CursorState) {
+        self.update_session(session_id, |s| {
+            push_capped(&mut s.cursors, MAX_CURSORS_PER_SESSION, cursor_id, state)
+        });
+    }
+
+    pub fn save_prepared(&self, session_id: &str, handle: &str, prepared: PreparedSearch) {
+        self.update_session(session_id, |s| {
+            push_capped(&mut s.prepared, MAX_PREPARED_PER_SESSION, handle, prepared)
+        });
+    }
+
+    pub fn prepared(&self, session_id: &str, handle: &str) -> Option<PreparedSearch> {
+        self.with_session(session_id, |s| find(&s.prepared, handle))
+    }
+
+    /// 记录预备搜索的完整结果；预备搜索已被淘汰时忽略。
+    pub fn save_prepared_results(&self, session_id: &str, handle: &str, results: CursorState) {
+        let mut sessions = self.sessions.write().unwrap();
+        if let Some((_, prepared)) = sessions
+            .get_mut(session_id)
+            .and_then(|s| s.prepared.iter_mut().find(|(id, _)| id == handle))
+        {
+            prepared.results = Some(results);
+        }
+    }
+
+    pub fn cursor(&self, session_id: &str, cursor_id: &str) -> Option<CursorState> {
+        self.with_session(session_id, |s| find(&s.cursors, cursor_id))
+    }
+}
+
+/// 追加到按创建顺序排列的列表，超出 `cap` 时先丢弃最早的一项。
+fn push_capped<T>(entries: &mut VecDeque<(String, T)>, cap: usize, id: &str, value: T) {
+    if entries.len() >= cap {
+        entries.pop_front();
+    }
+    entries.push_back((id.to_string(), value));
+}
+
+fn find<T: Clone>(entries: &VecDeque<(String, T)>, id: &str) -> Option<T> {
+    entries.iter().find(|(n, _)| n == id).map(|(_, value)| value.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn result_sets_are_scoped_by_session() {
+        let manager = SessionManager::new();
+        let refs = vec![HitRef {
+            file_path: PathBuf::from("/var/log/a.log"),
+            start_line: 3,
+            end_line: 5,
+        }];
+        manager.save_result_set("s1", "errors", refs);
+
+        let set = manager.result_set("s1", "errors").unwrap();
+        assert_eq!(set.refs().len(), 1);
+        assert!(set.contains(Path::new("/var/log/a.log"), 3));
+        assert!(!set.contains(Path::new("/var/log/a.log"), 4));
+        assert!(manager.result_set("s2", "errors").is_none());
+    }
+
+    #[test]
+    fn sessions_and_result_sets_are_bounded() {
+        let manager = SessionManager::new();
+        for i in 0..MAX_RESULT_SETS_PER_SESSION + 1 {
+            manager.save_result_set("s", &format!("set{i}"), Vec::new());
+        }
+        assert!(manager.result_set("s", "set0").is_none());
+        assert!(manager.result_set("s", "set1").is_some());
+
+        // 最近访问过的会话在淘汰时保留
+        for i in 0..MAX_SESSIONS {
+            manager.save_result_set(&format!("client{i}"), "x", Vec::new());
+            assert!(manager.result_set("s", "set1").is_some());
+        }
+        assert_eq!(manager.sessions.read().unwrap().len(), MAX_SESSIONS);
+        assert!(manager.result_set("client0", "x").is_none());
+        assert!(manager.result_set(&format!("client{}", MAX_SESSIONS - 1), "x").is_some());
+    }
+}